@@ -0,0 +1,140 @@
+// A MIDI-triggered ADSR envelope, used as a key signal for the detection-based modes
+
+use crate::util;
+
+/// The release and decay stages are exponential and are considered finished once they get this
+/// close to their target.
+const SETTLED: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdsrSettings {
+    pub attack_ms: f32,
+    pub decay_ms: f32,
+    /// The level the envelope stays at while a note is held, in `[0, 1]`.
+    pub sustain: f32,
+    pub release_ms: f32,
+    /// How much the note's velocity scales the envelope, in `[0, 1]`. At zero every note triggers
+    /// the envelope at full scale.
+    pub velocity_amount: f32,
+}
+
+impl Default for AdsrSettings {
+    fn default() -> Self {
+        Self {
+            attack_ms: 1.0,
+            decay_ms: 100.0,
+            sustain: 0.0,
+            release_ms: 50.0,
+            velocity_amount: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Release,
+}
+
+/// A monophonic envelope. Every note on retriggers the attack from the current level, and the
+/// release only starts once all notes have been released.
+#[derive(Debug, Clone)]
+pub struct Adsr {
+    /// The level added per sample during the attack stage. The attack is linear so hits land
+    /// exactly where the notes were placed.
+    attack_step: f32,
+    decay_coefficient: f32,
+    sustain: f32,
+    release_coefficient: f32,
+    velocity_amount: f32,
+
+    stage: Stage,
+    level: f32,
+    /// The level the current note peaks at, based on its velocity.
+    peak: f32,
+    /// One bit per MIDI note number.
+    held_notes: u128,
+}
+
+impl Default for Adsr {
+    fn default() -> Self {
+        let mut adsr = Self {
+            attack_step: 1.0,
+            decay_coefficient: 1.0,
+            sustain: 0.0,
+            release_coefficient: 1.0,
+            velocity_amount: 1.0,
+
+            stage: Stage::Idle,
+            level: 0.0,
+            peak: 1.0,
+            held_notes: 0,
+        };
+        adsr.set_settings(&AdsrSettings::default(), 44100.0);
+
+        adsr
+    }
+}
+
+impl Adsr {
+    pub fn set_settings(&mut self, settings: &AdsrSettings, sample_rate: f32) {
+        let attack_samples = util::ms_to_samples(settings.attack_ms, sample_rate);
+        self.attack_step = 1.0 / attack_samples.max(1) as f32;
+        self.decay_coefficient = util::one_pole_coefficient(settings.decay_ms, sample_rate);
+        self.sustain = settings.sustain.clamp(0.0, 1.0);
+        self.release_coefficient = util::one_pole_coefficient(settings.release_ms, sample_rate);
+        self.velocity_amount = settings.velocity_amount.clamp(0.0, 1.0);
+    }
+
+    pub fn reset(&mut self) {
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+        self.held_notes = 0;
+    }
+
+    /// Start a note. `velocity` is in `[0, 1]`.
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        self.held_notes |= 1 << (note & 127);
+        self.peak = 1.0 - self.velocity_amount + self.velocity_amount * velocity.clamp(0.0, 1.0);
+        self.stage = Stage::Attack;
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        self.held_notes &= !(1 << (note & 127));
+        if self.held_notes == 0 && self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    #[inline]
+    pub fn next_sample(&mut self) -> f32 {
+        match self.stage {
+            Stage::Idle => (),
+            Stage::Attack => {
+                self.level += self.attack_step * self.peak;
+                if self.level >= self.peak {
+                    self.level = self.peak;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                let target = self.sustain * self.peak;
+                self.level += (target - self.level) * self.decay_coefficient;
+                if (self.level - target).abs() < SETTLED {
+                    self.level = target;
+                }
+            }
+            Stage::Release => {
+                self.level -= self.level * self.release_coefficient;
+                if self.level < SETTLED {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+
+        self.level
+    }
+}
@@ -0,0 +1,308 @@
+// Time alignment between the main signal and the sidechain for the summing modes. Two recordings
+// of the same source, like a DI and an amp mic, are often a couple of milliseconds apart, which
+// causes comb filtering when they're summed. The offset is learned with GCC-PHAT. Whichever signal
+// comes first is delayed to line them up. When that's the main signal the delay adds latency, so
+// the summing modes only add latency while the sidechain lags behind the main signal.
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::util;
+
+/// The largest offset that can be learned or set, in either direction.
+pub const MAX_OFFSET_MS: f32 = 20.0;
+
+/// The longest either signal gets delayed by to line them up, in samples.
+pub fn max_delay_samples(sample_rate: f32) -> usize {
+    util::ms_to_samples(MAX_OFFSET_MS, sample_rate)
+}
+
+/// How far the main signal is delayed to line it up with a sidechain that lags behind it by
+/// `offset` samples. This is the summing modes' latency. When the sidechain comes first it's
+/// delayed instead, so this is zero for offsets of zero or less.
+pub fn main_delay_samples(offset: f32) -> usize {
+    if offset <= 0.0 {
+        return 0;
+    }
+
+    // The sidechain then makes up the difference, which is kept above a sample so
+    // `FractionalDelay` can interpolate between its middle two points. Offsets converted from
+    // milliseconds are rarely exact, and being a thousandth of a sample off shouldn't cost a whole
+    // sample of latency.
+    (offset - 1e-3).ceil() as usize + 1
+}
+
+/// The length of the analyzed frames. Offsets are only found if they're shorter than half of this.
+const WINDOW_SIZE: usize = 4096;
+/// The frames are zero padded to twice their length so the correlation doesn't wrap around.
+const CORRELATION_SIZE: usize = WINDOW_SIZE * 2;
+const HOP_SIZE: usize = WINDOW_SIZE / 2;
+/// How long the cross spectrum is averaged over while learning.
+const AVERAGING_MS: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AlignmentSettings {
+    /// Keeps estimating the offset between the main signal and the sidechain. Turning this back on
+    /// starts over, and the last estimate is kept once it's turned off.
+    pub learn: bool,
+    /// Uses `offset_ms` instead of the learned offset.
+    pub manual: bool,
+    /// How far the sidechain lags behind the main signal. Positive offsets delay the main signal,
+    /// negative offsets delay the sidechain.
+    pub offset_ms: f32,
+}
+
+impl Default for AlignmentSettings {
+    fn default() -> Self {
+        Self {
+            learn: false,
+            manual: false,
+            offset_ms: 0.0,
+        }
+    }
+}
+
+/// Estimates the offset between the main signal and the sidechain with the generalized cross
+/// correlation with phase transform, which only looks at the phase differences between the two
+/// signals. This finds the same offset for a DI and a heavily distorted amp.
+///
+/// The buffers are allocated in `prepare()`, after that nothing allocates.
+#[derive(Clone, Default)]
+pub struct Aligner {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    ifft: Option<Arc<dyn Fft<f32>>>,
+    /// A Hann window.
+    window: Vec<f32>,
+    sample_rate: f32,
+    averaging_coefficient: f32,
+    /// The largest offset that's searched for, in samples.
+    max_lag: usize,
+    learning: bool,
+
+    main_input: Vec<f32>,
+    side_input: Vec<f32>,
+    /// The position in the circular input buffers.
+    pos: usize,
+    samples_until_frame: usize,
+
+    main_spectrum: Vec<Complex<f32>>,
+    side_spectrum: Vec<Complex<f32>>,
+    /// The averaged cross spectrum between the sidechain and the main signal.
+    cross_spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+
+    /// The current estimate, see `offset_ms()`.
+    offset_ms: f32,
+}
+
+impl fmt::Debug for Aligner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Aligner")
+            .field("learning", &self.learning)
+            .field("offset_ms", &self.offset_ms)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Aligner {
+    /// Plan the FFTs and allocate the buffers.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.averaging_coefficient =
+            util::one_pole_coefficient(AVERAGING_MS, sample_rate / HOP_SIZE as f32);
+        self.max_lag = util::ms_to_samples(MAX_OFFSET_MS, sample_rate).min(WINDOW_SIZE / 2 - 1);
+
+        if self.fft.is_none() {
+            let mut planner = FftPlanner::new();
+            let fft = planner.plan_fft_forward(CORRELATION_SIZE);
+            let ifft = planner.plan_fft_inverse(CORRELATION_SIZE);
+            let scratch_len = fft
+                .get_inplace_scratch_len()
+                .max(ifft.get_inplace_scratch_len());
+
+            self.window = (0..WINDOW_SIZE)
+                .map(|idx| 0.5 - 0.5 * (2.0 * PI * idx as f32 / WINDOW_SIZE as f32).cos())
+                .collect();
+            self.main_input = vec![0.0; WINDOW_SIZE];
+            self.side_input = vec![0.0; WINDOW_SIZE];
+            self.main_spectrum = vec![Complex::default(); CORRELATION_SIZE];
+            self.side_spectrum = vec![Complex::default(); CORRELATION_SIZE];
+            self.cross_spectrum = vec![Complex::default(); CORRELATION_SIZE];
+            self.scratch = vec![Complex::default(); scratch_len];
+            self.fft = Some(fft);
+            self.ifft = Some(ifft);
+        }
+
+        self.reset();
+    }
+
+    pub fn set_settings(&mut self, settings: &AlignmentSettings) {
+        if settings.learn && !self.learning {
+            self.reset();
+        }
+        self.learning = settings.learn;
+    }
+
+    /// Clear the analysis. The learned offset is kept.
+    pub fn reset(&mut self) {
+        self.main_input.fill(0.0);
+        self.side_input.fill(0.0);
+        self.cross_spectrum.fill(Complex::default());
+        self.pos = 0;
+        self.samples_until_frame = HOP_SIZE;
+    }
+
+    /// The learned offset in milliseconds, with the same sign as `AlignmentSettings::offset_ms`.
+    pub fn offset_ms(&self) -> f32 {
+        self.offset_ms
+    }
+
+    /// Replace the learned offset, for example with one that was saved with the plugin's state.
+    pub fn set_offset_ms(&mut self, offset_ms: f32) {
+        self.offset_ms = offset_ms.clamp(-MAX_OFFSET_MS, MAX_OFFSET_MS);
+    }
+
+    /// Analyze a sample. This only does anything while learning, and returns true when the
+    /// estimate has been updated.
+    #[inline]
+    pub fn process(&mut self, main: f32, side: f32) -> bool {
+        if !self.learning || self.main_input.is_empty() {
+            return false;
+        }
+
+        self.main_input[self.pos] = main;
+        self.side_input[self.pos] = side;
+        self.pos = (self.pos + 1) % WINDOW_SIZE;
+
+        self.samples_until_frame -= 1;
+        if self.samples_until_frame == 0 {
+            self.samples_until_frame = HOP_SIZE;
+            self.process_frame()
+        } else {
+            false
+        }
+    }
+
+    fn process_frame(&mut self) -> bool {
+        let (Some(fft), Some(ifft)) = (&self.fft, &self.ifft) else {
+            return false;
+        };
+
+        // `self.pos` now points at the oldest sample
+        for idx in 0..WINDOW_SIZE {
+            let buffer_idx = (self.pos + idx) % WINDOW_SIZE;
+            self.main_spectrum[idx] =
+                Complex::new(self.main_input[buffer_idx] * self.window[idx], 0.0);
+            self.side_spectrum[idx] =
+                Complex::new(self.side_input[buffer_idx] * self.window[idx], 0.0);
+        }
+        self.main_spectrum[WINDOW_SIZE..].fill(Complex::default());
+        self.side_spectrum[WINDOW_SIZE..].fill(Complex::default());
+        fft.process_with_scratch(&mut self.main_spectrum, &mut self.scratch);
+        fft.process_with_scratch(&mut self.side_spectrum, &mut self.scratch);
+
+        // The phase transform whitens the averaged cross spectrum, so the correlation becomes a
+        // single sharp peak at the offset regardless of the signals' spectra. The main spectrum's
+        // buffer is reused for the correlation.
+        for ((cross, main), side) in self
+            .cross_spectrum
+            .iter_mut()
+            .zip(&mut self.main_spectrum)
+            .zip(&self.side_spectrum)
+        {
+            *cross += (side * main.conj() - *cross) * self.averaging_coefficient;
+
+            let magnitude = cross.norm();
+            *main = if magnitude > f32::MIN_POSITIVE {
+                *cross / magnitude
+            } else {
+                Complex::default()
+            };
+        }
+        ifft.process_with_scratch(&mut self.main_spectrum, &mut self.scratch);
+
+        // Negative lags wrap around to the end of the correlation
+        let correlation =
+            |lag: isize| self.main_spectrum[lag.rem_euclid(CORRELATION_SIZE as isize) as usize].re;
+        let max_lag = self.max_lag as isize;
+        let Some(peak_lag) =
+            (-max_lag..=max_lag).max_by(|&a, &b| correlation(a).total_cmp(&correlation(b)))
+        else {
+            return false;
+        };
+        let peak = correlation(peak_lag);
+        if peak <= 0.0 {
+            return false;
+        }
+
+        // A parabola through the peak and its neighbors finds the offset in between samples
+        let (before, after) = (correlation(peak_lag - 1), correlation(peak_lag + 1));
+        let curvature = before - 2.0 * peak + after;
+        let fraction = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+
+        self.set_offset_ms((peak_lag as f32 + fraction) / self.sample_rate * 1000.0);
+        true
+    }
+}
+
+/// A delay line with a fractional delay time, using third order Lagrange interpolation. Whole
+/// sample delays pass the signal through unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct FractionalDelay {
+    buffer: Vec<f32>,
+    write_pos: usize,
+}
+
+impl FractionalDelay {
+    /// Allocate room for delays of up to `max_delay` samples. This clears the delay line.
+    pub fn resize(&mut self, max_delay: usize) {
+        self.buffer.clear();
+        // The interpolation reads up to two samples past the delay time
+        self.buffer.resize(max_delay + 3, 0.0);
+        self.write_pos = 0;
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+    }
+
+    /// Delay `input` by `delay` samples, which is clamped to the capacity set in `resize()`.
+    #[inline]
+    pub fn process(&mut self, input: f32, delay: f32) -> f32 {
+        let len = self.buffer.len();
+        if len == 0 {
+            return input;
+        }
+
+        self.buffer[self.write_pos] = input;
+        let delay = delay.clamp(0.0, (len - 3) as f32);
+        // The interpolation is the most accurate with the delay between the middle two points
+        let start = (delay as usize).saturating_sub(1);
+        let t = delay - start as f32;
+        let tap = |offset: usize| self.buffer[(self.write_pos + len * 2 - start - offset) % len];
+        // The weights are exactly one and zero for whole sample delays
+        let weights = [
+            -(t - 1.0) * (t - 2.0) * (t - 3.0) / 6.0,
+            t * (t - 2.0) * (t - 3.0) / 2.0,
+            -t * (t - 1.0) * (t - 3.0) / 2.0,
+            t * (t - 1.0) * (t - 2.0) / 6.0,
+        ];
+        let output = weights
+            .iter()
+            .enumerate()
+            .map(|(offset, weight)| tap(offset) * weight)
+            .sum();
+        self.write_pos = (self.write_pos + 1) % len;
+
+        output
+    }
+}
@@ -0,0 +1,253 @@
+// Runs a mode on a single channel of a single band
+
+use crate::alignment::{self, FractionalDelay};
+use crate::bitwise::Bitwise;
+use crate::bleed_cancel::BleedCanceller;
+use crate::delay::DelayLine;
+use crate::envelope_filter::EnvelopeFilter;
+use crate::gate::Gate;
+use crate::logic::ThresholdSwitch;
+use crate::multiband::BandSettings;
+use crate::pitch::Pitch;
+use crate::ring_mod::RingMod;
+use crate::smart_sum::SmartSum;
+use crate::smoother::Smoother;
+use crate::spectral_denoise::SpectralDenoise;
+use crate::spectral_duck::SpectralDuck;
+use crate::spectral_morph::SpectralMorph;
+use crate::stutter::{self, Stutter};
+use crate::{mode, onset, stft, util, Settings, Transport, MAX_LOOKAHEAD_MS};
+
+/// Used in place of a mode for mid/side components that aren't processed. The main signal passes
+/// through unchanged.
+pub const PASSTHROUGH: i32 = -1;
+
+/// Whether a mode is triggered by the shared onset detector with these settings.
+pub fn uses_onsets(mode: i32, settings: &Settings) -> bool {
+    mode == mode::STUTTER && settings.stutter.trigger == stutter::TRIGGER_ONSET
+}
+
+/// The number of samples a mode delays its output by, with the lookahead time and the summing
+/// modes' main signal delay in samples, see `alignment::main_delay_samples()`.
+pub fn mode_latency(mode: i32, lookahead_samples: usize, main_alignment_samples: usize) -> usize {
+    if mode::is_summing(mode) {
+        main_alignment_samples
+    } else if mode::uses_detection(mode) {
+        lookahead_samples
+    } else if mode::is_spectral(mode) {
+        stft::FFT_SIZE
+    } else {
+        0
+    }
+}
+
+/// The state for every mode on one channel of one band. Only the state for the current mode is
+/// used, but everything is allocated up front so switching modes doesn't allocate.
+#[derive(Debug, Clone, Default)]
+pub struct BandChannel {
+    /// Delays the main signal for detection-based modes, see `Settings::lookahead_ms`.
+    lookahead: DelayLine,
+    /// Line the signals up in the summing modes by delaying whichever comes first, see
+    /// `alignment`. The main signal is delayed by whole samples and the sidechain makes up the
+    /// rest.
+    main_alignment: DelayLine,
+    sidechain_alignment: FractionalDelay,
+    smart_sum: SmartSum,
+    gate: Gate,
+    spectral_duck: SpectralDuck,
+    spectral_morph: SpectralMorph,
+    spectral_denoise: SpectralDenoise,
+    ring_mod: RingMod,
+    envelope_filter: EnvelopeFilter,
+    stutter: Stutter,
+    bleed_canceller: BleedCanceller,
+    threshold_switch: ThresholdSwitch,
+    bitwise: Bitwise,
+    /// Delays the output so bands with less latency line up with the band with the most latency.
+    compensation: DelayLine,
+}
+
+impl BandChannel {
+    pub fn prepare(&mut self, sample_rate: f32) {
+        // Modes triggered by onsets need at least the onset detector's latency as lookahead
+        let max_lookahead = util::ms_to_samples(MAX_LOOKAHEAD_MS, sample_rate).max(onset::LATENCY);
+        self.lookahead.resize(max_lookahead);
+        // The main signal is delayed by up to a sample more than the offset, and the sidechain
+        // makes up for that, see `alignment::main_delay_samples()` and
+        // `Processor::update_alignment()`
+        let max_alignment = alignment::max_delay_samples(sample_rate);
+        self.main_alignment.resize(max_alignment + 1);
+        self.sidechain_alignment.resize(max_alignment + 3);
+        self.compensation
+            .resize(max_lookahead.max(max_alignment + 1).max(stft::FFT_SIZE));
+        self.spectral_duck.prepare();
+        self.spectral_morph.prepare();
+        self.spectral_denoise.prepare();
+        self.stutter.prepare(sample_rate);
+        self.bleed_canceller.prepare(sample_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.lookahead.reset();
+        self.compensation.reset();
+        self.main_alignment.reset();
+        self.sidechain_alignment.reset();
+        self.reset_mode();
+    }
+
+    /// Clear the modes' state so a newly selected mode doesn't continue from wherever it was the
+    /// last time it was active.
+    pub fn reset_mode(&mut self) {
+        self.smart_sum.reset();
+        self.gate.reset();
+        self.spectral_duck.reset();
+        self.spectral_morph.reset();
+        self.spectral_denoise.reset();
+        self.ring_mod.reset();
+        self.envelope_filter.reset();
+        self.stutter.reset();
+        self.bleed_canceller.reset();
+        self.threshold_switch.reset();
+        self.bitwise.reset();
+    }
+
+    /// Set the lookahead time, the main signal's delay in the summing modes, and the delay that
+    /// lines this band up with the band with the most latency.
+    pub fn set_delays(
+        &mut self,
+        lookahead_samples: usize,
+        main_alignment_samples: usize,
+        compensation_samples: usize,
+    ) {
+        self.lookahead.set_delay(lookahead_samples);
+        self.main_alignment.set_delay(main_alignment_samples);
+        self.compensation.set_delay(compensation_samples);
+    }
+
+    pub fn set_settings(&mut self, settings: &Settings, sample_rate: f32) {
+        self.smart_sum
+            .set_settings(&settings.smart_sum, sample_rate);
+        self.gate.set_settings(&settings.gate, sample_rate);
+        self.spectral_duck
+            .set_settings(&settings.spectral_duck, sample_rate);
+        self.spectral_morph
+            .set_settings(&settings.spectral_morph, sample_rate);
+        self.spectral_denoise
+            .set_settings(&settings.spectral_denoise, sample_rate);
+        self.ring_mod.set_settings(&settings.ring_mod, sample_rate);
+        self.envelope_filter.set_settings(
+            &settings.envelope_filter,
+            settings.envelope_follower_smoothing,
+            sample_rate,
+        );
+        self.stutter.set_settings(&settings.stutter, sample_rate);
+        self.bleed_canceller
+            .set_settings(&settings.bleed_cancel, sample_rate);
+        self.threshold_switch
+            .set_settings(&settings.threshold_switch, sample_rate);
+        self.bitwise.set_settings(&settings.bitwise, sample_rate);
+    }
+
+    pub fn set_transport(&mut self, transport: &Transport, sample_rate: f32) {
+        self.stutter.set_transport(transport, sample_rate);
+    }
+
+    /// Combine a main sample with a sidechain sample using `mode`. Both have already been scaled by
+    /// the input gains. `pitch` is the sidechain's current pitch, `onset` is set when an onset
+    /// trigger fires at this sample, and `alignment` is the offset between the signals in samples.
+    #[inline]
+    pub fn process(
+        &mut self,
+        mode: i32,
+        sample: f32,
+        sidechain_sample: f32,
+        pitch: Pitch,
+        onset: bool,
+        alignment: f32,
+    ) -> f32 {
+        // The lookahead buffer is always kept up to date so switching to a detection-based mode
+        // doesn't play back stale audio
+        let delayed = self.lookahead.process(sample);
+        let sample = if mode::uses_detection(mode) {
+            delayed
+        } else {
+            sample
+        };
+        // Like the lookahead, the alignment delays are always kept up to date
+        let aligned = self.main_alignment.process(sample);
+        let aligned_sidechain = self.sidechain_alignment.process(
+            sidechain_sample,
+            (self.main_alignment.current_delay() - alignment).max(0.0),
+        );
+
+        let output = match mode {
+            mode::ADDITION => aligned + aligned_sidechain,
+            mode::MULTIPLICATION => sample * sidechain_sample,
+            mode::ABS_MULTIPLICATION => sample * sidechain_sample.abs(),
+            // `x % 0.0` is NaN, but the remainder goes to zero as the divisor does
+            mode::MODULO if sidechain_sample == 0.0 => 0.0,
+            mode::MODULO => sample % sidechain_sample,
+            mode::RING_MODULATION => self.ring_mod.process(sample, pitch),
+            mode::GATE => self.gate.process(sample, sidechain_sample),
+            mode::SPECTRAL_DUCK => self.spectral_duck.process(sample, sidechain_sample),
+            mode::ENVELOPE_FILTER => self.envelope_filter.process(sample, sidechain_sample),
+            mode::STUTTER => self.stutter.process(sample, sidechain_sample, onset),
+            mode::SPECTRAL_MORPH => self.spectral_morph.process(sample, sidechain_sample),
+            mode::SPECTRAL_DENOISE => self.spectral_denoise.process(sample, sidechain_sample),
+            mode::BLEED_CANCEL => self.bleed_canceller.process(sample, sidechain_sample),
+            mode::SMART_SUM => self.smart_sum.process(aligned, aligned_sidechain),
+            mode::MINIMUM => sample.min(sidechain_sample),
+            mode::MAXIMUM => sample.max(sidechain_sample),
+            mode::ABS_MINIMUM if sidechain_sample.abs() < sample.abs() => sidechain_sample,
+            mode::ABS_MAXIMUM if sidechain_sample.abs() > sample.abs() => sidechain_sample,
+            mode::ABS_MINIMUM | mode::ABS_MAXIMUM => sample,
+            mode::SIGN_SELECT => sample.abs().copysign(sidechain_sample),
+            mode::THRESHOLD_SWITCH => self.threshold_switch.process(sample, sidechain_sample),
+            mode::BITWISE_AND | mode::BITWISE_OR | mode::BITWISE_XOR | mode::BITWISE_NAND => {
+                self.bitwise.process(mode, sample, sidechain_sample)
+            }
+            _ => sample, // testing ground, and `PASSTHROUGH`
+        };
+
+        self.compensation.process(output)
+    }
+}
+
+/// The smoothed gains for a single band.
+#[derive(Debug, Clone, Copy)]
+pub struct BandGains {
+    pub input: Smoother,
+    pub sidechain_input: Smoother,
+    pub output: Smoother,
+}
+
+impl Default for BandGains {
+    fn default() -> Self {
+        Self {
+            input: Smoother::new(1.0),
+            sidechain_input: Smoother::new(1.0),
+            output: Smoother::new(1.0),
+        }
+    }
+}
+
+impl BandGains {
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.input.set_sample_rate(sample_rate);
+        self.sidechain_input.set_sample_rate(sample_rate);
+        self.output.set_sample_rate(sample_rate);
+    }
+
+    pub fn set_targets(&mut self, settings: &BandSettings) {
+        self.input.set_target(settings.input_gain);
+        self.sidechain_input
+            .set_target(settings.sidechain_input_gain);
+        self.output.set_target(settings.output_gain);
+    }
+
+    pub fn reset(&mut self) {
+        self.input.reset();
+        self.sidechain_input.reset();
+        self.output.reset();
+    }
+}
@@ -0,0 +1,194 @@
+// The bitwise modes. Both signals are quantized to signed integers at a lower bit depth, combined
+// bit by bit, and turned back into samples. This is mostly useful for glitchy sound design, and
+// the smoothing filter tames the resulting harshness somewhat.
+
+use std::f32::consts::TAU;
+
+use crate::mode;
+use crate::svf::{Svf, BUTTERWORTH_Q};
+
+/// The lowest supported bit depth.
+pub const MIN_BITS: i32 = 4;
+/// The highest supported bit depth. Samples are `f32`s, so this is as precise as they get.
+pub const MAX_BITS: i32 = 24;
+
+/// At or above this cutoff the smoothing filter is bypassed.
+pub const MAX_SMOOTHING_HZ: f32 = 20000.0;
+
+/// The cutoff of the one-pole high-pass filter on NAND's output. Inverting a two's complement number
+/// also subtracts a step, so without this NAND turns silence into a constant offset.
+pub const NAND_DC_BLOCKER_HZ: f32 = 5.0;
+
+/// Samples are rounded to the nearest step without any dither.
+pub const DITHER_NONE: i32 = 0;
+/// Uniform noise of one step is added before rounding.
+pub const DITHER_RECTANGULAR: i32 = 1;
+/// Triangular noise of two steps is added before rounding, which also keeps the noise floor from
+/// depending on the signal.
+pub const DITHER_TRIANGULAR: i32 = 2;
+
+pub const DITHER_NAMES: [&str; 3] = ["None", "Rectangular", "Triangular"];
+
+pub fn dither_name(dither: i32) -> &'static str {
+    usize::try_from(dither)
+        .ok()
+        .and_then(|idx| DITHER_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a dither number or a (case insensitive) dither name.
+pub fn dither_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(dither) if (0..DITHER_NAMES.len() as i32).contains(&dither) => Some(dither),
+        Ok(_) => None,
+        Err(_) => DITHER_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+/// The seed for the dither noise. Every channel starts from the same seed after a reset so renders
+/// are reproducible.
+const DITHER_SEED: u32 = 0x2545_f491;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitwiseSettings {
+    /// The bit depth both signals are quantized to, between `MIN_BITS` and `MAX_BITS`.
+    pub bits: i32,
+    /// One of the `DITHER_*` constants.
+    pub dither: i32,
+    /// The cutoff of the low-pass filter after the operation. See `MAX_SMOOTHING_HZ`.
+    pub smoothing_hz: f32,
+}
+
+impl Default for BitwiseSettings {
+    fn default() -> Self {
+        Self {
+            bits: 8,
+            dither: DITHER_NONE,
+            smoothing_hz: MAX_SMOOTHING_HZ,
+        }
+    }
+}
+
+/// The bitwise modes for a single channel.
+#[derive(Debug, Clone)]
+pub struct Bitwise {
+    /// The number of steps between zero and full scale, `2^(bits - 1)`.
+    scale: f32,
+    dither: i32,
+    smoothing: bool,
+
+    smoothing_filter: Svf,
+    /// The DC blocker's feedback coefficient, see `NAND_DC_BLOCKER_HZ`.
+    dc_coefficient: f32,
+    /// The DC blocker's last input, or `None` when NAND wasn't used for the last sample.
+    dc_input: Option<f32>,
+    dc_output: f32,
+    /// The xorshift32 state for the dither noise.
+    rng_state: u32,
+}
+
+impl Default for Bitwise {
+    fn default() -> Self {
+        let mut bitwise = Self {
+            scale: 1.0,
+            dither: DITHER_NONE,
+            smoothing: false,
+
+            smoothing_filter: Svf::default(),
+            dc_coefficient: 1.0,
+            dc_input: None,
+            dc_output: 0.0,
+            rng_state: DITHER_SEED,
+        };
+        bitwise.set_settings(&BitwiseSettings::default(), 44100.0);
+
+        bitwise
+    }
+}
+
+impl Bitwise {
+    pub fn set_settings(&mut self, settings: &BitwiseSettings, sample_rate: f32) {
+        self.scale = (1 << (settings.bits.clamp(MIN_BITS, MAX_BITS) - 1)) as f32;
+        self.dither = settings.dither;
+        self.smoothing = settings.smoothing_hz < MAX_SMOOTHING_HZ;
+        self.smoothing_filter
+            .set(settings.smoothing_hz, BUTTERWORTH_Q, sample_rate);
+        self.dc_coefficient = (-TAU * NAND_DC_BLOCKER_HZ / sample_rate).exp();
+    }
+
+    pub fn reset(&mut self) {
+        self.smoothing_filter.reset();
+        self.dc_input = None;
+        self.dc_output = 0.0;
+        self.rng_state = DITHER_SEED;
+    }
+
+    /// Combine a main sample with a sidechain sample using one of the bitwise modes.
+    #[inline]
+    pub fn process(&mut self, mode: i32, sample: f32, key: f32) -> f32 {
+        let main = self.quantize(sample);
+        let side = self.quantize(key);
+        // The operations on two's complement numbers in range always stay in range
+        let combined = match mode {
+            mode::BITWISE_AND => main & side,
+            mode::BITWISE_OR => main | side,
+            mode::BITWISE_XOR => main ^ side,
+            mode::BITWISE_NAND => !(main & side),
+            _ => main,
+        };
+        let output = combined as f32 / self.scale;
+        let output = if mode == mode::BITWISE_NAND {
+            self.block_dc(output)
+        } else {
+            self.dc_input = None;
+            self.dc_output = 0.0;
+            output
+        };
+
+        if self.smoothing {
+            self.smoothing_filter.process(output).low
+        } else {
+            output
+        }
+    }
+
+    /// Remove the constant offset from NAND's output. The filter starts from the first sample after
+    /// switching to NAND, so silence stays exactly silent.
+    #[inline]
+    fn block_dc(&mut self, input: f32) -> f32 {
+        let previous_input = self.dc_input.replace(input).unwrap_or(input);
+        self.dc_output = input - previous_input + self.dc_coefficient * self.dc_output;
+
+        self.dc_output
+    }
+
+    /// Dither and round a sample to a signed integer with `scale` steps on either side of zero.
+    /// Anything past full scale is clipped.
+    #[inline]
+    fn quantize(&mut self, sample: f32) -> i32 {
+        let dither = match self.dither {
+            DITHER_RECTANGULAR => self.next_uniform(),
+            DITHER_TRIANGULAR => self.next_uniform() + self.next_uniform(),
+            _ => 0.0,
+        };
+
+        (sample * self.scale + dither)
+            .round()
+            .clamp(-self.scale, self.scale - 1.0) as i32
+    }
+
+    /// Uniform noise in `[-0.5, 0.5)`.
+    #[inline]
+    fn next_uniform(&mut self) -> f32 {
+        self.rng_state ^= self.rng_state << 13;
+        self.rng_state ^= self.rng_state >> 17;
+        self.rng_state ^= self.rng_state << 5;
+
+        (self.rng_state >> 8) as f32 / (1 << 24) as f32 - 0.5
+    }
+}
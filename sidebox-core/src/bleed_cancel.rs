@@ -0,0 +1,124 @@
+// Adaptive bleed cancellation. An NLMS filter learns the path from the sidechain, a clean recording
+// of the bleeding source, to the main signal, and the filtered sidechain is subtracted from the
+// main signal.
+
+use crate::util;
+
+/// The longest filter, which covers a source about seven meters from the mic. Every tap costs two
+/// multiplications per sample, so this is kept fairly short.
+pub const MAX_LENGTH_MS: f32 = 20.0;
+/// Keeps the step size normalization from blowing up when the sidechain is silent.
+const REGULARIZATION: f32 = 1e-6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BleedCancelSettings {
+    /// The NLMS step size, in `(0, 1]`. Larger steps adapt faster, smaller steps are more accurate
+    /// once the filter has converged and get thrown off less while the source in the main signal
+    /// is playing.
+    pub step_size: f32,
+    /// The filter's length. This needs to cover the delay between the sidechain and its bleed in
+    /// the main signal, plus the room's reflections.
+    pub length_ms: f32,
+    /// Stops the adaptation and keeps the filter as it is.
+    pub freeze: bool,
+}
+
+impl Default for BleedCancelSettings {
+    fn default() -> Self {
+        Self {
+            step_size: 0.1,
+            length_ms: 10.0,
+            freeze: false,
+        }
+    }
+}
+
+/// A normalized least mean squares adaptive filter for a single channel. The sidechain should reach
+/// the processor no later than its bleed in the main signal, since the filter can only model a
+/// causal path.
+///
+/// The buffers are allocated in `prepare()`, after that nothing allocates.
+#[derive(Debug, Clone, Default)]
+pub struct BleedCanceller {
+    step_size: f32,
+    freeze: bool,
+    /// The number of taps, at most `weights.len()`.
+    length: usize,
+
+    weights: Vec<f32>,
+    /// The sidechain's past samples, stored twice so the last `length` samples are always
+    /// available as one contiguous slice starting at `pos`.
+    history: Vec<f32>,
+    pos: usize,
+    /// The sum of the squares of the last `length` sidechain samples.
+    energy: f64,
+}
+
+impl BleedCanceller {
+    /// Allocate the filter for a sample rate.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        let max_length = util::ms_to_samples(MAX_LENGTH_MS, sample_rate).max(1);
+        self.weights.resize(max_length, 0.0);
+        self.history.resize(max_length * 2, 0.0);
+        self.length = self.length.clamp(1, max_length);
+        self.reset();
+    }
+
+    pub fn set_settings(&mut self, settings: &BleedCancelSettings, sample_rate: f32) {
+        self.step_size = settings.step_size.clamp(0.0, 1.0);
+        self.freeze = settings.freeze;
+
+        // The learned filter doesn't mean anything at another length
+        let length = util::ms_to_samples(settings.length_ms, sample_rate)
+            .clamp(1, self.weights.len().max(1));
+        if length != self.length {
+            self.length = length;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.weights.fill(0.0);
+        self.history.fill(0.0);
+        self.pos = 0;
+        self.energy = 0.0;
+    }
+
+    /// Subtract the filtered `key` from `sample`.
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        if self.weights.is_empty() {
+            return sample;
+        }
+
+        // The newest sample goes first, so `weights[0]` is the tap without any delay
+        let length = self.length;
+        self.pos = if self.pos == 0 {
+            length - 1
+        } else {
+            self.pos - 1
+        };
+        let oldest = self.history[self.pos + length];
+        self.history[self.pos] = key;
+        self.history[self.pos + length] = key;
+        self.energy = (self.energy + (key as f64).powi(2) - (oldest as f64).powi(2)).max(0.0);
+
+        let input = &self.history[self.pos..self.pos + length];
+        let weights = &mut self.weights[..length];
+        let estimate: f32 = weights
+            .iter()
+            .zip(input)
+            .map(|(weight, x)| weight * x)
+            .sum();
+        let error = sample - estimate;
+
+        if !self.freeze {
+            let step = self.step_size * error / (self.energy as f32 + REGULARIZATION);
+            for (weight, x) in weights.iter_mut().zip(input) {
+                *weight += step * x;
+            }
+        }
+
+        error
+    }
+}
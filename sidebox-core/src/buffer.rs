@@ -0,0 +1,141 @@
+// Adapted from Buffr Glitch: a MIDI-controlled buffer repeater
+// Copyright (C) 2022-2024 Robbert van der Helm
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+/// A super simple ring buffer abstraction that records audio into a buffer until it is full, and
+/// then starts looping the already recorded audio. The recording starts when the loop is triggered
+/// so transients are preserved correctly. This holds a single channel, every channel gets its own
+/// ring buffer.
+///
+/// The buffer is allocated in `resize()`, after that nothing allocates.
+#[derive(Debug, Clone, Default)]
+pub struct RingBuffer {
+    /// When a loop is triggered, `next_sample_pos` is set to 0 and the incoming audio is recorded
+    /// into this buffer until `next_sample_pos` reaches `len`. At that point the incoming audio is
+    /// replaced by the previously recorded audio.
+    audio_buffer: Vec<f32>,
+    /// The length of the current loop, at most `audio_buffer.len()`.
+    len: usize,
+    /// The current playback position in `audio_buffer`.
+    next_sample_pos: usize,
+    /// The length of the crossfade, in samples. After the first loop this many additional samples
+    /// are recorded and faded back into the buffer.
+    crossfade_length: usize,
+    /// See [`BufferStatus`].
+    buffer_status: BufferStatus,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum BufferStatus {
+    /// The buffer has not yet been filled and all sample should be recorded into the buffer.
+    #[default]
+    Recording,
+    /// The buffer has wrapped around, but `crossfade_length` is set to 1 or more samples. This
+    /// second pass continues recording, and replaces the buffer's start with a cross faded version
+    /// of that input and the existing contents.
+    Crossfading,
+    /// The buffer has wrapped around once and `crossfade_length` is set to 0, or it has wrapped
+    /// around twice and crossfading is enabled. Samples only need to be read from the buffer, all
+    /// work is done.
+    Ready,
+}
+
+impl RingBuffer {
+    /// Allocate room for loops of up to `max_len` samples. Make sure to call
+    /// [`reset()`][Self::reset()] after this.
+    pub fn resize(&mut self, max_len: usize) {
+        self.audio_buffer.resize(max_len.max(1), 0.0);
+        self.len = self.len.clamp(1, self.audio_buffer.len());
+    }
+
+    /// Zero out the buffer and start recording from scratch.
+    pub fn reset(&mut self) {
+        self.audio_buffer.fill(0.0);
+        self.next_sample_pos = 0;
+        self.buffer_status = BufferStatus::Recording;
+    }
+
+    /// The length of the current loop, in samples.
+    pub fn loop_len(&self) -> usize {
+        self.len
+    }
+
+    /// The position the next call to [`next_sample()`][Self::next_sample()] reads from.
+    pub fn position(&self) -> usize {
+        self.next_sample_pos
+    }
+
+    /// Read a sample from the buffer without advancing the playback position. `pos` wraps around
+    /// at the buffer's capacity, not at the current loop's length.
+    pub fn sample_at(&self, pos: usize) -> f32 {
+        self.audio_buffer[pos % self.audio_buffer.len()]
+    }
+
+    /// Prepare the buffer to loop `len` samples. This resets the buffer to record the next `len`
+    /// samples, which are then looped until the next call. The loop length is clamped to the
+    /// capacity set in `resize()`. The crossfade length is also set at this point since right now
+    /// we don't record more than necessary and can't change this afterwards.
+    pub fn prepare_playback(&mut self, len: usize, crossfade_length: usize) {
+        // This buffer doesn't need to be cleared since the data is not read until the entire buffer
+        // has been recorded to
+        self.len = len.clamp(1, self.audio_buffer.len().max(1));
+
+        // The buffer is filled on the first `len` calls to `next_sample`, plus a little more for
+        // the crossfade if set
+        self.next_sample_pos = 0;
+        self.crossfade_length = crossfade_length.min(self.len);
+        self.buffer_status = BufferStatus::Recording;
+    }
+
+    /// Read or write a sample from or to the ring buffer, and return the output. On the first loop
+    /// this will store the input samples into the bufffer and return the input value as is.
+    /// Afterwards it will read the previously recorded data from the buffer.
+    #[inline]
+    pub fn next_sample(&mut self, input_sample: f32) -> f32 {
+        if self.audio_buffer.is_empty() {
+            return input_sample;
+        }
+
+        match self.buffer_status {
+            BufferStatus::Recording => self.audio_buffer[self.next_sample_pos] = input_sample,
+            BufferStatus::Crossfading if self.next_sample_pos < self.crossfade_length => {
+                // This is an equal power fade between the part of the input after the first loop
+                // and the buffer's existing contents. The `.max(1)` is needed to avoid NaNs with
+                // crossfade lengths of 1 sample.
+                let crossfade_t =
+                    self.next_sample_pos as f32 / (self.crossfade_length - 1).max(1) as f32;
+                let new_t = (1.0 - crossfade_t).sqrt();
+                let existing_t = crossfade_t.sqrt();
+
+                self.audio_buffer[self.next_sample_pos] =
+                    (input_sample * new_t) + (self.audio_buffer[self.next_sample_pos] * existing_t);
+            }
+            _ => (),
+        }
+        let result = self.audio_buffer[self.next_sample_pos];
+
+        self.next_sample_pos += 1;
+        if self.next_sample_pos >= self.len {
+            self.next_sample_pos = 0;
+
+            self.buffer_status = match self.buffer_status {
+                BufferStatus::Recording if self.crossfade_length > 0 => BufferStatus::Crossfading,
+                _ => BufferStatus::Ready,
+            };
+        }
+
+        result
+    }
+}
@@ -0,0 +1,109 @@
+// Breakpoint curves drawn in the editor, used as a shape for the ghost key source
+
+/// The most points a curve can have. Curves are stored inline so they can be copied to the audio
+/// thread without allocating.
+pub const MAX_POINTS: usize = 32;
+
+/// A point on a curve. Both coordinates are in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Breakpoint {
+    pub x: f32,
+    pub y: f32,
+    /// The shape of the segment from this point to the next, in `[-1, 1]`. Zero is a straight
+    /// line, positive values make the segment start slowly and negative values make it start fast.
+    pub tension: f32,
+}
+
+impl Breakpoint {
+    pub const fn new(x: f32, y: f32, tension: f32) -> Self {
+        Self { x, y, tension }
+    }
+}
+
+/// A curve over `[0, 1]` through up to `MAX_POINTS` breakpoints. Before the first point and after
+/// the last point the curve holds that point's value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curve {
+    points: [Breakpoint; MAX_POINTS],
+    len: usize,
+}
+
+impl Default for Curve {
+    /// Full level at the start that quickly falls off, similar to a kick drum's envelope.
+    fn default() -> Self {
+        Self::from_points(&[
+            Breakpoint::new(0.0, 1.0, -0.5),
+            Breakpoint::new(0.6, 0.0, 0.0),
+            Breakpoint::new(1.0, 0.0, 0.0),
+        ])
+    }
+}
+
+impl Curve {
+    /// Build a curve from points sorted by their x-coordinates. Anything past `MAX_POINTS` is
+    /// ignored, and out of range values are clamped.
+    pub fn from_points(points: &[Breakpoint]) -> Self {
+        let mut curve = Self {
+            points: [Breakpoint::new(0.0, 0.0, 0.0); MAX_POINTS],
+            len: 0,
+        };
+
+        let mut min_x = 0.0;
+        for point in points.iter().take(MAX_POINTS) {
+            let x = point.x.clamp(min_x, 1.0);
+            min_x = x;
+
+            curve.points[curve.len] = Breakpoint {
+                x,
+                y: point.y.clamp(0.0, 1.0),
+                tension: point.tension.clamp(-1.0, 1.0),
+            };
+            curve.len += 1;
+        }
+
+        curve
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points[..self.len]
+    }
+
+    /// The curve's value at `x`, in `[0, 1]`. An empty curve is silent.
+    pub fn value_at(&self, x: f32) -> f32 {
+        let points = self.points();
+        let (Some(first), Some(last)) = (points.first(), points.last()) else {
+            return 0.0;
+        };
+        if x <= first.x {
+            return first.y;
+        }
+        if x >= last.x {
+            return last.y;
+        }
+
+        // There are at most a couple dozen points, so a linear search is fine
+        let end_idx = points
+            .iter()
+            .position(|point| point.x > x)
+            .unwrap_or(points.len() - 1);
+        let (start, end) = (points[end_idx - 1], points[end_idx]);
+        let width = end.x - start.x;
+        if width <= 0.0 {
+            return end.y;
+        }
+
+        start.y + (end.y - start.y) * shape((x - start.x) / width, start.tension)
+    }
+}
+
+/// Bend a position `t` in `[0, 1]` along a segment according to the segment's tension. The result
+/// is also in `[0, 1]`, with the end points left in place.
+#[inline]
+pub fn shape(t: f32, tension: f32) -> f32 {
+    if tension == 0.0 {
+        t
+    } else {
+        t.powf((tension * 3.0).exp2())
+    }
+}
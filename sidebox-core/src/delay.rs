@@ -0,0 +1,94 @@
+// A fixed-capacity delay line for latency compensation
+
+/// How many samples a change in delay time is crossfaded over, about 5 ms at 48 kHz. Jumping to the
+/// new delay right away would click.
+pub const CROSSFADE_SAMPLES: usize = 256;
+
+/// Delays a signal by a whole number of samples. The buffer is allocated in `resize()`, changing the
+/// delay time afterwards does not allocate.
+#[derive(Debug, Clone, Default)]
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_pos: usize,
+    delay: usize,
+    /// The delay time being faded out after a change, see `CROSSFADE_SAMPLES`.
+    old_delay: usize,
+    /// The number of samples left until `delay` has fully replaced `old_delay`.
+    crossfade_remaining: usize,
+    /// Whether any audio went through since the last reset. Until then there's nothing to crossfade
+    /// from.
+    running: bool,
+}
+
+impl DelayLine {
+    /// Allocate room for delays of up to `max_delay` samples. This clears the delay line.
+    pub fn resize(&mut self, max_delay: usize) {
+        self.buffer.clear();
+        self.buffer.resize(max_delay + 1, 0.0);
+        self.delay = self.delay.min(max_delay);
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.fill(0.0);
+        self.write_pos = 0;
+        self.crossfade_remaining = 0;
+        self.running = false;
+    }
+
+    /// The delay time, gliding from the old to the new delay time while a change is being
+    /// crossfaded. Anything that needs to stay lined up with the delayed signal can follow this.
+    pub fn current_delay(&self) -> f32 {
+        self.delay as f32 + (self.old_delay as f32 - self.delay as f32) * self.old_gain()
+    }
+
+    /// Change the delay time. This is clamped to the capacity set in `resize()`. Once audio has gone
+    /// through the delay line the change is crossfaded over `CROSSFADE_SAMPLES` samples.
+    pub fn set_delay(&mut self, delay: usize) {
+        let delay = delay.min(self.buffer.len().saturating_sub(1));
+        if delay == self.delay {
+            return;
+        }
+
+        // A change in the middle of a crossfade fades out from the delay that was being faded to
+        if self.running {
+            self.old_delay = self.delay;
+            self.crossfade_remaining = CROSSFADE_SAMPLES;
+        }
+        self.delay = delay;
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> f32 {
+        if self.buffer.is_empty() {
+            return input;
+        }
+
+        // The input is always recorded so increasing the delay time later plays back actual audio
+        self.buffer[self.write_pos] = input;
+        let output = self.read(self.delay);
+        let output = if self.crossfade_remaining > 0 {
+            let old_gain = self.old_gain();
+            self.crossfade_remaining -= 1;
+
+            output + (self.read(self.old_delay) - output) * old_gain
+        } else {
+            output
+        };
+        self.write_pos = (self.write_pos + 1) % self.buffer.len();
+        self.running = true;
+
+        output
+    }
+
+    /// The old delay time's share in the output while crossfading.
+    #[inline]
+    fn old_gain(&self) -> f32 {
+        self.crossfade_remaining as f32 / (CROSSFADE_SAMPLES + 1) as f32
+    }
+
+    #[inline]
+    fn read(&self, delay: usize) -> f32 {
+        self.buffer[(self.write_pos + self.buffer.len() - delay) % self.buffer.len()]
+    }
+}
@@ -0,0 +1,57 @@
+/// The largest supported moving average window, matching the range of the plugin's envelope
+/// follower smoothing parameter.
+pub const MAX_ENVELOPE_FOLLOWER_SIZE: usize = 1000;
+
+/// A moving average over the last `size` inputs. The window lives in a circular buffer that is
+/// allocated once up front, so both `process()` and `set_size()` are safe to call on the audio
+/// thread.
+#[derive(Debug, Clone)]
+pub struct SimpleEnvelopeFollower {
+    current_value: f32,
+    inputs: Vec<f32>,
+    /// The position of the oldest input in `inputs`.
+    pos: usize,
+    sum: f32,
+    size: usize,
+}
+
+impl SimpleEnvelopeFollower {
+    pub fn new(size: i32) -> Self {
+        let mut follower = Self {
+            current_value: 0.0,
+            inputs: vec![0.0; MAX_ENVELOPE_FOLLOWER_SIZE],
+            pos: 0,
+            sum: 0.0,
+            size: 1,
+        };
+        follower.set_size(size);
+
+        follower
+    }
+
+    /// Change the window size. This clears the window since the old inputs would otherwise be
+    /// averaged over the wrong length.
+    pub fn set_size(&mut self, size: i32) {
+        let size = (size.max(1) as usize).min(MAX_ENVELOPE_FOLLOWER_SIZE);
+        if size != self.size {
+            self.size = size;
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.inputs.fill(0.0);
+        self.pos = 0;
+        self.sum = 0.0;
+        self.current_value = 0.0;
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let old_value = std::mem::replace(&mut self.inputs[self.pos], input);
+        self.sum += input - old_value;
+        self.pos = (self.pos + 1) % self.size;
+
+        self.current_value = self.sum / self.size as f32;
+        self.current_value
+    }
+}
@@ -0,0 +1,156 @@
+// A resonant filter on the main signal with its cutoff swept by the sidechain's envelope
+
+use crate::envelope::SimpleEnvelopeFollower;
+use crate::svf::{Svf, BUTTERWORTH_Q};
+use crate::util;
+
+pub const FILTER_LOW_PASS: i32 = 0;
+pub const FILTER_BAND_PASS: i32 = 1;
+pub const FILTER_HIGH_PASS: i32 = 2;
+
+pub const FILTER_NAMES: [&str; 3] = ["Low-pass", "Band-pass", "High-pass"];
+
+pub fn filter_name(filter: i32) -> &'static str {
+    usize::try_from(filter)
+        .ok()
+        .and_then(|idx| FILTER_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a filter type number or a (case insensitive) name.
+pub fn filter_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(filter) if (0..FILTER_NAMES.len() as i32).contains(&filter) => Some(filter),
+        Ok(_) => None,
+        Err(_) => FILTER_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+/// The cutoff is kept within this range no matter how far the envelope pushes it.
+pub const MIN_CUTOFF_HZ: f32 = 20.0;
+pub const MAX_CUTOFF_HZ: f32 = 20000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeFilterSettings {
+    /// One of the `FILTER_*` constants.
+    pub filter: i32,
+    /// The cutoff while the sidechain is silent.
+    pub cutoff_hz: f32,
+    /// How far a full scale envelope moves the cutoff away from `cutoff_hz`. Negative depths sweep
+    /// the cutoff down instead.
+    pub depth_octaves: f32,
+    /// The filter's Q. Higher values add a resonant peak at the cutoff.
+    pub resonance: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for EnvelopeFilterSettings {
+    fn default() -> Self {
+        Self {
+            filter: FILTER_LOW_PASS,
+            cutoff_hz: 200.0,
+            depth_octaves: 4.0,
+            resonance: 2.0,
+            attack_ms: 5.0,
+            release_ms: 150.0,
+        }
+    }
+}
+
+/// An envelope filter for a single channel.
+#[derive(Debug, Clone)]
+pub struct EnvelopeFilter {
+    filter: i32,
+    cutoff_hz: f32,
+    depth_octaves: f32,
+    resonance: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    sample_rate: f32,
+
+    /// Smooths the rectified key over `Settings::envelope_follower_smoothing` samples before the
+    /// attack and release are applied.
+    follower: SimpleEnvelopeFollower,
+    envelope: f32,
+    svf: Svf,
+}
+
+impl Default for EnvelopeFilter {
+    fn default() -> Self {
+        let mut envelope_filter = Self {
+            filter: FILTER_LOW_PASS,
+            cutoff_hz: 0.0,
+            depth_octaves: 0.0,
+            resonance: BUTTERWORTH_Q,
+            attack_coefficient: 1.0,
+            release_coefficient: 1.0,
+            sample_rate: 44100.0,
+
+            follower: SimpleEnvelopeFollower::new(1),
+            envelope: 0.0,
+            svf: Svf::default(),
+        };
+        envelope_filter.set_settings(&EnvelopeFilterSettings::default(), 1, 44100.0);
+        envelope_filter.reset();
+
+        envelope_filter
+    }
+}
+
+impl EnvelopeFilter {
+    /// `smoothing` is the envelope follower's window size in samples, see
+    /// `Settings::envelope_follower_smoothing`.
+    pub fn set_settings(
+        &mut self,
+        settings: &EnvelopeFilterSettings,
+        smoothing: i32,
+        sample_rate: f32,
+    ) {
+        self.filter = settings.filter;
+        self.cutoff_hz = settings.cutoff_hz.clamp(MIN_CUTOFF_HZ, MAX_CUTOFF_HZ);
+        self.depth_octaves = settings.depth_octaves;
+        self.resonance = settings.resonance;
+        self.attack_coefficient = util::one_pole_coefficient(settings.attack_ms, sample_rate);
+        self.release_coefficient = util::one_pole_coefficient(settings.release_ms, sample_rate);
+        self.sample_rate = sample_rate;
+        self.follower.set_size(smoothing);
+    }
+
+    pub fn reset(&mut self) {
+        self.follower.reset();
+        self.envelope = 0.0;
+        self.svf.reset();
+    }
+
+    /// Filter `sample` with a cutoff set by the level of `key`. Like the gate, lookahead is handled
+    /// by the processor.
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        let level = self.follower.process(key.abs());
+        let coefficient = if level > self.envelope {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.envelope += (level - self.envelope) * coefficient;
+
+        // The envelope is capped at full scale so hot sidechains don't push the cutoff past the
+        // depth that was set
+        let cutoff = (self.cutoff_hz * (self.depth_octaves * self.envelope.min(1.0)).exp2())
+            .clamp(MIN_CUTOFF_HZ, MAX_CUTOFF_HZ);
+        self.svf.set(cutoff, self.resonance, self.sample_rate);
+
+        let outputs = self.svf.process(sample);
+        match self.filter {
+            FILTER_BAND_PASS => outputs.band,
+            FILTER_HIGH_PASS => outputs.high,
+            _ => outputs.low,
+        }
+    }
+}
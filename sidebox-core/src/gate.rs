@@ -0,0 +1,140 @@
+// Sidechain keyed noise gate / downward expander. The main signal is only let through while the
+// sidechain is active.
+
+use crate::util;
+
+/// The detector's peak envelope falls at this rate. This keeps the gate from chattering on the key
+/// signal's zero crossings.
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+/// The shortest attack time. Even an instant attack gets faded in over this period so opening the
+/// gate never clicks, and shorter attack times are raised to this.
+pub const MIN_ATTACK_MS: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GateSettings {
+    /// The gate opens when the sidechain's level rises above this threshold.
+    pub threshold_db: f32,
+    /// The gate closes again once the level falls this far below the threshold.
+    pub hysteresis_db: f32,
+    pub attack_ms: f32,
+    /// How long the gate stays open after the level has dropped below the closing threshold.
+    pub hold_ms: f32,
+    pub release_ms: f32,
+    /// The attenuation applied while the gate is closed. Anything above -80 dB turns this into a
+    /// downward expander.
+    pub range_db: f32,
+}
+
+impl Default for GateSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: -30.0,
+            hysteresis_db: 6.0,
+            attack_ms: 1.0,
+            hold_ms: 50.0,
+            release_ms: 100.0,
+            range_db: -80.0,
+        }
+    }
+}
+
+/// A gate for a single channel.
+#[derive(Debug, Clone)]
+pub struct Gate {
+    open_threshold: f32,
+    close_threshold: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    detector_coefficient: f32,
+    hold_samples: usize,
+    /// The linear gain while the gate is closed.
+    floor: f32,
+
+    /// The key signal's peak envelope.
+    level: f32,
+    open: bool,
+    hold_counter: usize,
+    gain: f32,
+}
+
+impl Default for Gate {
+    fn default() -> Self {
+        let mut gate = Self {
+            open_threshold: 0.0,
+            close_threshold: 0.0,
+            attack_coefficient: 1.0,
+            release_coefficient: 1.0,
+            detector_coefficient: 1.0,
+            hold_samples: 0,
+            floor: 0.0,
+
+            level: 0.0,
+            open: false,
+            hold_counter: 0,
+            gain: 0.0,
+        };
+        gate.set_settings(&GateSettings::default(), 44100.0);
+        gate.reset();
+
+        gate
+    }
+}
+
+impl Gate {
+    pub fn set_settings(&mut self, settings: &GateSettings, sample_rate: f32) {
+        self.open_threshold = util::db_to_gain(settings.threshold_db);
+        self.close_threshold =
+            util::db_to_gain(settings.threshold_db - settings.hysteresis_db.max(0.0));
+        self.attack_coefficient =
+            util::one_pole_coefficient(settings.attack_ms.max(MIN_ATTACK_MS), sample_rate);
+        self.release_coefficient = util::one_pole_coefficient(settings.release_ms, sample_rate);
+        self.detector_coefficient = util::one_pole_coefficient(DETECTOR_RELEASE_MS, sample_rate);
+        self.hold_samples = util::ms_to_samples(settings.hold_ms, sample_rate);
+        self.floor = if settings.range_db <= -80.0 {
+            0.0
+        } else {
+            util::db_to_gain(settings.range_db.min(0.0))
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.level = 0.0;
+        self.open = false;
+        self.hold_counter = 0;
+        self.gain = self.floor;
+    }
+
+    /// Gate `sample` based on the level of `key`. Lookahead is handled by the processor, which
+    /// delays `sample` but not `key`.
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        let rectified = key.abs();
+        if rectified > self.level {
+            self.level = rectified;
+        } else {
+            self.level += (rectified - self.level) * self.detector_coefficient;
+        }
+
+        if self.level >= self.open_threshold {
+            self.open = true;
+            self.hold_counter = self.hold_samples;
+        } else if self.open && self.level >= self.close_threshold {
+            // Still within the hysteresis band
+            self.hold_counter = self.hold_samples;
+        } else if self.hold_counter > 0 {
+            self.hold_counter -= 1;
+        } else {
+            self.open = false;
+        }
+
+        let target = if self.open { 1.0 } else { self.floor };
+        let coefficient = if target > self.gain {
+            self.attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        self.gain += (target - self.gain) * coefficient;
+
+        sample * self.gain
+    }
+}
@@ -0,0 +1,219 @@
+// A tempo-synced key signal generated from the host's transport, for when the track that should
+// drive the sidechain isn't available
+
+use crate::curve::Curve;
+use crate::Transport;
+
+/// Used when the host doesn't report a tempo.
+const DEFAULT_TEMPO: f64 = 120.0;
+/// Used when the host doesn't report a time signature, in quarter notes.
+pub(crate) const DEFAULT_BAR_BEATS: f64 = 4.0;
+
+/// The note divisions the curve can repeat at, with their length in quarter notes. The length of a
+/// bar depends on the time signature, so it's only listed here for 4/4. These indices are part of
+/// the plugin's saved state, new divisions should be added to the end.
+pub const DIVISIONS: [(&str, f64); 10] = [
+    ("1/4", 1.0),
+    ("1/4 dotted", 1.5),
+    ("1/4 triplet", 2.0 / 3.0),
+    ("1/8", 0.5),
+    ("1/8 dotted", 0.75),
+    ("1/8 triplet", 1.0 / 3.0),
+    ("1/16", 0.25),
+    ("1/16 dotted", 0.375),
+    ("1/16 triplet", 1.0 / 6.0),
+    ("1 bar", 4.0),
+];
+
+/// The index of the bar division in `DIVISIONS`.
+pub const BAR: i32 = 9;
+
+/// A linear ramp from full scale down to silence, see `GhostSettings::length`.
+pub const SHAPE_RAMP: i32 = 0;
+/// The curve drawn in the editor, see `Processor::set_curve()`.
+pub const SHAPE_DRAWN: i32 = 1;
+
+pub const SHAPE_NAMES: [&str; 2] = ["Ramp", "Drawn"];
+
+pub fn shape_name(shape: i32) -> &'static str {
+    usize::try_from(shape)
+        .ok()
+        .and_then(|idx| SHAPE_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a shape number or a (case insensitive) shape name.
+pub fn shape_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(shape) if (0..SHAPE_NAMES.len() as i32).contains(&shape) => Some(shape),
+        Ok(_) => None,
+        Err(_) => SHAPE_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+/// The highest index into `DIVISIONS`.
+pub const MAX_DIVISION: i32 = DIVISIONS.len() as i32 - 1;
+
+pub fn division_name(division: i32) -> &'static str {
+    usize::try_from(division)
+        .ok()
+        .and_then(|idx| DIVISIONS.get(idx))
+        .map_or("Unknown", |(name, _)| name)
+}
+
+/// Parse either a division index or a (case insensitive) division name like `1/8 dotted`.
+pub fn division_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(division) if (0..=MAX_DIVISION).contains(&division) => Some(division),
+        Ok(_) => None,
+        Err(_) => DIVISIONS
+            .iter()
+            .position(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+/// The length of `division` in quarter notes, with the length of a bar from `bar_beats()`.
+pub(crate) fn division_beats(division: i32, bar_beats: f64) -> f64 {
+    if division == BAR {
+        bar_beats
+    } else {
+        DIVISIONS[division.clamp(0, MAX_DIVISION) as usize].1
+    }
+}
+
+/// The length of a bar in quarter notes, if the host reports a valid time signature.
+pub(crate) fn bar_beats(transport: &Transport) -> Option<f64> {
+    match (transport.time_sig_numerator, transport.time_sig_denominator) {
+        (Some(numerator), Some(denominator)) if numerator > 0 && denominator > 0 => {
+            Some(numerator as f64 * 4.0 / denominator as f64)
+        }
+        _ => None,
+    }
+}
+
+/// How many quarter notes pass every sample at the host's tempo.
+pub(crate) fn beats_per_sample(transport: &Transport, sample_rate: f32) -> f64 {
+    let tempo = transport.tempo.filter(|&tempo| tempo > 0.0);
+
+    tempo.unwrap_or(DEFAULT_TEMPO) / 60.0 / sample_rate as f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GhostSettings {
+    /// An index into `DIVISIONS`.
+    pub division: i32,
+    /// `SHAPE_RAMP` or `SHAPE_DRAWN`.
+    pub shape: i32,
+    /// How long the ramp takes to fall back to silence, as a fraction of the division.
+    pub length: f32,
+}
+
+impl Default for GhostSettings {
+    fn default() -> Self {
+        Self {
+            division: 0,
+            shape: SHAPE_RAMP,
+            length: 0.5,
+        }
+    }
+}
+
+/// Generates a curve that repeats every division. The ramp jumps to full scale at the start of
+/// every division and then falls off linearly, which the detection-based modes react to the same
+/// way they would to a kick drum. The drawn shape plays back the curve from the editor instead.
+///
+/// While the host's transport is playing the curve follows the song position. When it's stopped or
+/// the host doesn't report a position, the curve keeps running from where it was at the last known
+/// tempo so the effect can still be previewed.
+#[derive(Debug, Clone)]
+pub struct Ghost {
+    division: i32,
+    division_beats: f64,
+    /// The length of a bar in quarter notes, from the host's time signature.
+    bar_beats: f64,
+    shape: i32,
+    length: f64,
+    beats_per_sample: f64,
+    curve: Curve,
+
+    /// The position in quarter notes.
+    position: f64,
+}
+
+impl Default for Ghost {
+    fn default() -> Self {
+        let mut ghost = Self {
+            division: 0,
+            division_beats: 1.0,
+            bar_beats: DEFAULT_BAR_BEATS,
+            shape: SHAPE_RAMP,
+            length: 0.5,
+            beats_per_sample: 0.0,
+            curve: Curve::default(),
+
+            position: 0.0,
+        };
+        ghost.set_settings(&GhostSettings::default());
+        ghost.set_transport(&Transport::default(), 44100.0);
+
+        ghost
+    }
+}
+
+impl Ghost {
+    pub fn set_settings(&mut self, settings: &GhostSettings) {
+        self.division = settings.division.clamp(0, MAX_DIVISION);
+        self.shape = settings.shape;
+        self.length = settings.length.clamp(0.0, 1.0) as f64;
+        self.update_division();
+    }
+
+    pub fn set_curve(&mut self, curve: &Curve) {
+        self.curve = *curve;
+    }
+
+    fn update_division(&mut self) {
+        self.division_beats = division_beats(self.division, self.bar_beats);
+    }
+
+    /// Called at the start of every block with the host's transport information.
+    pub fn set_transport(&mut self, transport: &Transport, sample_rate: f32) {
+        self.beats_per_sample = beats_per_sample(transport, sample_rate);
+        if let Some(bar_beats) = bar_beats(transport) {
+            self.bar_beats = bar_beats;
+            self.update_division();
+        }
+
+        if transport.playing {
+            if let Some(pos_beats) = transport.pos_beats {
+                self.position = pos_beats;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.position = 0.0;
+    }
+
+    /// The next sample of the curve, in `[0, 1]`.
+    #[inline]
+    pub fn next_sample(&mut self) -> f32 {
+        let phase = (self.position / self.division_beats).rem_euclid(1.0);
+        self.position += self.beats_per_sample;
+
+        if self.shape == SHAPE_DRAWN {
+            self.curve.value_at(phase as f32)
+        } else if phase < self.length {
+            (1.0 - phase / self.length) as f32
+        } else {
+            0.0
+        }
+    }
+}
@@ -0,0 +1,930 @@
+// Sidebox's DSP. This works on plain slices so it can be driven by the plugin, the offline renderer,
+// tests, and benchmarks alike.
+
+pub mod adsr;
+pub mod alignment;
+mod band;
+pub mod bitwise;
+pub mod bleed_cancel;
+mod buffer;
+pub mod curve;
+mod delay;
+pub mod envelope;
+pub mod envelope_filter;
+pub mod gate;
+pub mod ghost;
+pub mod logic;
+pub mod mid_side;
+pub mod multiband;
+pub mod onset;
+pub mod pitch;
+pub mod ring_mod;
+pub mod smart_sum;
+mod smoother;
+pub mod spectral_denoise;
+pub mod spectral_duck;
+pub mod spectral_morph;
+pub mod stft;
+pub mod stutter;
+pub mod svf;
+pub mod util;
+
+use adsr::{Adsr, AdsrSettings};
+use alignment::{Aligner, AlignmentSettings};
+use band::{BandChannel, BandGains};
+use bitwise::BitwiseSettings;
+use bleed_cancel::BleedCancelSettings;
+use curve::Curve;
+use envelope_filter::EnvelopeFilterSettings;
+use gate::GateSettings;
+use ghost::{Ghost, GhostSettings};
+use logic::ThresholdSwitchSettings;
+use mid_side::MidSideSettings;
+use multiband::{BandSettings, Crossover, MultibandSettings, MAX_BANDS};
+use onset::{OnsetDetector, OnsetSettings};
+use pitch::{Pitch, PitchTracker};
+use ring_mod::RingModSettings;
+use smart_sum::SmartSumSettings;
+use smoother::Smoother;
+use spectral_denoise::SpectralDenoiseSettings;
+use spectral_duck::SpectralDuckSettings;
+use spectral_morph::SpectralMorphSettings;
+use stutter::StutterSettings;
+
+/// The plugin's stereo layout is the only supported layout. Other channel counts are accepted by
+/// `Processor::process()`, but any channels past this are left untouched.
+pub const MAX_CHANNELS: usize = 2;
+
+/// The longest supported lookahead time, see `Settings::lookahead_ms`.
+pub const MAX_LOOKAHEAD_MS: f32 = 20.0;
+
+/// The most note events that can be queued for a single block. Any events past this are dropped.
+pub const MAX_NOTE_EVENTS: usize = 512;
+
+/// Where the detection-based modes get their key signal from, as stored in `Settings::key_source`.
+pub mod key_source {
+    /// The sidechain input.
+    pub const SIDECHAIN: i32 = 0;
+    /// A tempo-synced curve, see `ghost::Ghost`.
+    pub const GHOST: i32 = 1;
+    /// An envelope triggered by MIDI notes, see `adsr::Adsr`.
+    pub const MIDI: i32 = 2;
+
+    pub const MAX: i32 = 2;
+
+    pub const NAMES: [&str; MAX as usize + 1] = ["Sidechain", "Ghost", "MIDI"];
+
+    pub fn name(source: i32) -> &'static str {
+        usize::try_from(source)
+            .ok()
+            .and_then(|idx| NAMES.get(idx))
+            .copied()
+            .unwrap_or("Unknown")
+    }
+
+    /// Parse either a source number or a (case insensitive) source name.
+    pub fn from_name(name: &str) -> Option<i32> {
+        let name = name.trim();
+        match name.parse::<i32>() {
+            Ok(source) if (0..=MAX).contains(&source) => Some(source),
+            Ok(_) => None,
+            Err(_) => NAMES
+                .iter()
+                .position(|candidate| candidate.eq_ignore_ascii_case(name))
+                .map(|idx| idx as i32),
+        }
+    }
+}
+
+/// How the detection-based modes' key signals are linked between channels, as stored in
+/// `Settings::detection_link`. Linking keeps the stereo image from shifting when only one side of
+/// the sidechain is loud.
+pub mod detection_link {
+    /// Every channel is keyed by its own sidechain channel.
+    pub const INDEPENDENT: i32 = 0;
+    /// Every channel is keyed by the loudest channel.
+    pub const MAX: i32 = 1;
+    /// Every channel is keyed by the channels' average level.
+    pub const AVERAGE: i32 = 2;
+    /// Every channel is keyed by the channels' summed level.
+    pub const SUM: i32 = 3;
+
+    pub const NAMES: [&str; SUM as usize + 1] = ["Independent", "Max", "Average", "Sum"];
+
+    pub fn name(link: i32) -> &'static str {
+        usize::try_from(link)
+            .ok()
+            .and_then(|idx| NAMES.get(idx))
+            .copied()
+            .unwrap_or("Unknown")
+    }
+
+    /// Parse either a link number or a (case insensitive) link name.
+    pub fn from_name(name: &str) -> Option<i32> {
+        let name = name.trim();
+        match name.parse::<i32>() {
+            Ok(link) if (0..=SUM).contains(&link) => Some(link),
+            Ok(_) => None,
+            Err(_) => NAMES
+                .iter()
+                .position(|candidate| candidate.eq_ignore_ascii_case(name))
+                .map(|idx| idx as i32),
+        }
+    }
+
+    /// Link one key sample per channel in place. Linked keys are rectified, which doesn't matter
+    /// to the detectors since they only look at the key's level. `amount` fades between the
+    /// channels' own levels at zero and the linked level at one.
+    #[inline]
+    pub fn apply(link: i32, amount: f32, keys: &mut [f32]) {
+        let levels = keys.iter().map(|key| key.abs());
+        let linked = match link {
+            MAX => levels.fold(0.0, f32::max),
+            AVERAGE => levels.sum::<f32>() / keys.len() as f32,
+            SUM => levels.sum(),
+            _ => return,
+        };
+
+        let amount = amount.clamp(0.0, 1.0);
+        for key in keys.iter_mut() {
+            let level = key.abs();
+            *key = level + (linked - level) * amount;
+        }
+    }
+}
+
+/// A MIDI note for the MIDI key source. `timing` is the sample offset into the next block passed to
+/// `Processor::process()`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoteEvent {
+    On {
+        timing: u32,
+        note: u8,
+        velocity: f32,
+    },
+    Off {
+        timing: u32,
+        note: u8,
+    },
+}
+
+impl NoteEvent {
+    pub fn timing(&self) -> u32 {
+        match *self {
+            NoteEvent::On { timing, .. } | NoteEvent::Off { timing, .. } => timing,
+        }
+    }
+}
+
+/// The host's transport information for the current block. All fields are optional in the plugin
+/// APIs, so hosts that don't provide them simply leave them at their defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Transport {
+    pub playing: bool,
+    /// In beats per minute.
+    pub tempo: Option<f64>,
+    /// The song position in quarter notes.
+    pub pos_beats: Option<f64>,
+    pub time_sig_numerator: Option<i32>,
+    pub time_sig_denominator: Option<i32>,
+}
+
+/// The combination modes, as stored in `Settings::mode` and the plugin's mode parameter. These
+/// numbers are part of the plugin's saved state, so they should never be changed.
+pub mod mode {
+    /// The signals are lined up first, see [`alignment`](crate::alignment).
+    pub const ADDITION: i32 = 0;
+    pub const MULTIPLICATION: i32 = 1;
+    pub const ABS_MULTIPLICATION: i32 = 2;
+    pub const MODULO: i32 = 3;
+    // 4: simple envelope follower, 5: sidechain as modulator, 6: convolution. These don't do
+    // anything yet and pass the main signal through.
+    /// Ring modulation with a carrier tuned to the sidechain's pitch.
+    pub const RING_MODULATION: i32 = 7;
+    pub const GATE: i32 = 8;
+    pub const SPECTRAL_DUCK: i32 = 9;
+    /// A filter on the main signal with its cutoff swept by the sidechain's envelope.
+    pub const ENVELOPE_FILTER: i32 = 10;
+    /// Sidechain transients capture a slice of the main signal and loop it.
+    pub const STUTTER: i32 = 11;
+    /// The main signal's spectrum is morphed towards the sidechain's.
+    pub const SPECTRAL_MORPH: i32 = 12;
+    /// Noise is removed from the main signal, with the sidechain as the noise reference.
+    pub const SPECTRAL_DENOISE: i32 = 13;
+    /// An adaptive filter learns how the sidechain bleeds into the main signal and subtracts it.
+    pub const BLEED_CANCEL: i32 = 14;
+    /// Addition with the sidechain's phase rotated to line up with the main signal's low end. Like
+    /// the addition mode, the signals are lined up in time first.
+    pub const SMART_SUM: i32 = 15;
+    /// The lower of the two samples.
+    pub const MINIMUM: i32 = 16;
+    /// The higher of the two samples.
+    pub const MAXIMUM: i32 = 17;
+    /// Whichever sample is closer to zero.
+    pub const ABS_MINIMUM: i32 = 18;
+    /// Whichever sample is further from zero.
+    pub const ABS_MAXIMUM: i32 = 19;
+    /// The main signal's magnitude with the sidechain's sign.
+    pub const SIGN_SELECT: i32 = 20;
+    /// Switches to the sidechain while it's louder than the main signal.
+    pub const THRESHOLD_SWITCH: i32 = 21;
+    /// The bitwise AND of both signals, quantized to `bitwise::BitwiseSettings::bits`.
+    pub const BITWISE_AND: i32 = 22;
+    /// The bitwise OR of both quantized signals.
+    pub const BITWISE_OR: i32 = 23;
+    /// The bitwise XOR of both quantized signals.
+    pub const BITWISE_XOR: i32 = 24;
+    /// The inverted bitwise AND of both quantized signals. Inverting adds a constant step below
+    /// zero, which is filtered out, see `bitwise::NAND_DC_BLOCKER_HZ`.
+    pub const BITWISE_NAND: i32 = 25;
+
+    /// The highest mode number.
+    pub const MAX: i32 = 25;
+
+    /// Display names for every mode, indexed by mode number.
+    pub const NAMES: [&str; MAX as usize + 1] = [
+        "Addition",
+        "Multiplication",
+        "Absolute value multiplication",
+        "Modulo",
+        "Envelope follower (not implemented)",
+        "Sidechain as modulator (not implemented)",
+        "Convolution (not implemented)",
+        "Ring modulation",
+        "Gate",
+        "Spectral ducking",
+        "Envelope filter",
+        "Stutter",
+        "Spectral morph",
+        "Spectral denoise",
+        "Bleed cancellation",
+        "Smart sum",
+        "Minimum",
+        "Maximum",
+        "Absolute minimum",
+        "Absolute maximum",
+        "Sign select",
+        "Threshold switch",
+        "Bitwise AND",
+        "Bitwise OR",
+        "Bitwise XOR",
+        "Bitwise NAND",
+    ];
+
+    /// Whether the mode reacts to the sidechain's level instead of its waveform. Only these modes
+    /// are affected by the lookahead setting.
+    pub fn uses_detection(mode: i32) -> bool {
+        matches!(mode, GATE | ENVELOPE_FILTER | STUTTER)
+    }
+
+    /// Whether the mode sums the signals after lining them up in time. These modes add latency when
+    /// the main signal needs to be delayed, see `alignment::main_delay_samples()`.
+    pub fn is_summing(mode: i32) -> bool {
+        matches!(mode, ADDITION | SMART_SUM)
+    }
+
+    /// Whether the mode works on the signals' spectra. These modes add `stft::FFT_SIZE` samples of
+    /// latency.
+    pub fn is_spectral(mode: i32) -> bool {
+        matches!(mode, SPECTRAL_DUCK | SPECTRAL_MORPH | SPECTRAL_DENOISE)
+    }
+
+    pub fn name(mode: i32) -> &'static str {
+        usize::try_from(mode)
+            .ok()
+            .and_then(|idx| NAMES.get(idx))
+            .copied()
+            .unwrap_or("Unknown")
+    }
+
+    /// Parse either a mode number or a (case insensitive) mode name.
+    pub fn from_name(name: &str) -> Option<i32> {
+        let name = name.trim();
+        match name.parse::<i32>() {
+            Ok(mode) if (0..=MAX).contains(&mode) => Some(mode),
+            Ok(_) => None,
+            Err(_) => NAMES
+                .iter()
+                .position(|candidate| candidate.eq_ignore_ascii_case(name))
+                .map(|idx| idx as i32),
+        }
+    }
+}
+
+/// Plain parameter values. Gains are linear.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// One of the constants from [`mode`].
+    pub mode: i32,
+    pub input_gain: f32,
+    pub sidechain_input_gain: f32,
+    pub output_gain: f32,
+    pub sidechain_phase_flip: bool,
+    /// The length in samples of the moving average that smooths the sidechain's level for the
+    /// envelope filter.
+    pub envelope_follower_smoothing: i32,
+    /// Delays the main signal while detection runs on the undelayed sidechain, so detection-based
+    /// modes can react before a transient instead of after it. This adds latency.
+    pub lookahead_ms: f32,
+    /// See `key_source`. Modes that use the sidechain's waveform always use the sidechain input.
+    pub key_source: i32,
+    /// See `detection_link`. Only used by detection-based modes.
+    pub detection_link: i32,
+    /// How much of the linked level is used, in `[0, 1]`.
+    pub detection_link_amount: f32,
+    /// Lines the main signal and the sidechain up in the summing modes.
+    pub alignment: AlignmentSettings,
+    pub smart_sum: SmartSumSettings,
+    pub gate: GateSettings,
+    pub ghost: GhostSettings,
+    pub midi: AdsrSettings,
+    pub spectral_duck: SpectralDuckSettings,
+    pub spectral_morph: SpectralMorphSettings,
+    pub spectral_denoise: SpectralDenoiseSettings,
+    pub ring_mod: RingModSettings,
+    pub envelope_filter: EnvelopeFilterSettings,
+    pub stutter: StutterSettings,
+    pub bleed_cancel: BleedCancelSettings,
+    pub threshold_switch: ThresholdSwitchSettings,
+    pub bitwise: BitwiseSettings,
+    /// The onset detector that triggers the onset-based modes.
+    pub onset: OnsetSettings,
+    /// Splits the signals into bands with their own modes.
+    pub multiband: MultibandSettings,
+    /// Processes the mid and side components instead of the left and right channels.
+    pub mid_side: MidSideSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            mode: mode::ADDITION,
+            input_gain: 1.0,
+            sidechain_input_gain: 1.0,
+            output_gain: 1.0,
+            sidechain_phase_flip: false,
+            envelope_follower_smoothing: 10,
+            lookahead_ms: 0.0,
+            key_source: key_source::SIDECHAIN,
+            detection_link: detection_link::INDEPENDENT,
+            detection_link_amount: 1.0,
+            alignment: AlignmentSettings::default(),
+            smart_sum: SmartSumSettings::default(),
+            gate: GateSettings::default(),
+            ghost: GhostSettings::default(),
+            midi: AdsrSettings::default(),
+            spectral_duck: SpectralDuckSettings::default(),
+            spectral_morph: SpectralMorphSettings::default(),
+            spectral_denoise: SpectralDenoiseSettings::default(),
+            ring_mod: RingModSettings::default(),
+            envelope_filter: EnvelopeFilterSettings::default(),
+            stutter: StutterSettings::default(),
+            bleed_cancel: BleedCancelSettings::default(),
+            threshold_switch: ThresholdSwitchSettings::default(),
+            bitwise: BitwiseSettings::default(),
+            onset: OnsetSettings::default(),
+            multiband: MultibandSettings::default(),
+            mid_side: MidSideSettings::default(),
+        }
+    }
+}
+
+/// Combines a main signal with a sidechain signal according to [`Settings`].
+#[derive(Debug, Clone)]
+pub struct Processor {
+    settings: Settings,
+    sample_rate: f32,
+    max_block_size: usize,
+
+    input_gain: Smoother,
+    sidechain_input_gain: Smoother,
+    output_gain: Smoother,
+
+    /// `bands[band_idx][channel_idx]`. Without multiband processing only the first band is used.
+    bands: [[BandChannel; MAX_CHANNELS]; MAX_BANDS],
+    band_gains: [[BandGains; MAX_BANDS]; MAX_CHANNELS],
+    main_crossovers: [Crossover; MAX_CHANNELS],
+    sidechain_crossovers: [Crossover; MAX_CHANNELS],
+    /// The latency of the band with the most latency, see `latency_samples()`.
+    latency: usize,
+    /// The delay for the detection-based modes, including the onset detector's latency when it's
+    /// needed.
+    lookahead_samples: usize,
+    /// How far the summing modes delay the main signal, see `alignment::main_delay_samples()`.
+    main_alignment_samples: usize,
+    /// Shared by all channels.
+    ghost: Ghost,
+    transport: Transport,
+    midi_envelope: Adsr,
+    /// Follows the sidechain's pitch for the pitch-tracking modes, see `sidechain_pitch()`.
+    pitch_tracker: PitchTracker,
+    /// Finds onsets in the sidechain for the onset-triggered modes, see `onsets()`.
+    onset_detector: OnsetDetector,
+    /// Learns the offset between the main signal and the sidechain, see `learned_alignment_ms()`.
+    aligner: Aligner,
+    /// The offset the summing modes currently align the signals by, in samples. Positive offsets
+    /// move the sidechain earlier.
+    alignment: Smoother,
+    /// The positions of the onset triggers in the last processed block. Has a capacity of
+    /// `max_block_size`.
+    onsets: Vec<usize>,
+    /// The events for the next block, see `queue_note_event()`. This never grows past its initial
+    /// capacity.
+    note_events: Vec<NoteEvent>,
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Processor {
+    pub fn new() -> Self {
+        let settings = Settings::default();
+
+        Self {
+            settings,
+            sample_rate: 44100.0,
+            max_block_size: 0,
+
+            input_gain: Smoother::new(settings.input_gain),
+            sidechain_input_gain: Smoother::new(settings.sidechain_input_gain),
+            output_gain: Smoother::new(settings.output_gain),
+
+            bands: Default::default(),
+            band_gains: Default::default(),
+            main_crossovers: Default::default(),
+            sidechain_crossovers: Default::default(),
+            latency: 0,
+            lookahead_samples: 0,
+            main_alignment_samples: 0,
+            ghost: Ghost::default(),
+            transport: Transport::default(),
+            midi_envelope: Adsr::default(),
+            pitch_tracker: PitchTracker::default(),
+            onset_detector: OnsetDetector::default(),
+            aligner: Aligner::default(),
+            alignment: Smoother::new(0.0),
+            onsets: Vec::new(),
+            note_events: Vec::with_capacity(MAX_NOTE_EVENTS),
+        }
+    }
+
+    /// Set up the processor for a sample rate and a maximum block size. This may allocate, and it
+    /// must be called before the first call to [`process()`][Self::process()]. Make sure to call
+    /// [`reset()`][Self::reset()] after this.
+    pub fn prepare(&mut self, sample_rate: f32, max_block_size: usize) {
+        debug_assert!(sample_rate > 0.0);
+
+        self.sample_rate = sample_rate;
+        self.max_block_size = max_block_size;
+
+        self.input_gain.set_sample_rate(sample_rate);
+        self.sidechain_input_gain.set_sample_rate(sample_rate);
+        self.output_gain.set_sample_rate(sample_rate);
+        self.alignment.set_sample_rate(sample_rate);
+
+        for band in self.bands.iter_mut().flatten() {
+            band.prepare(sample_rate);
+            band.set_transport(&self.transport, sample_rate);
+        }
+        for gains in self.band_gains.iter_mut().flatten() {
+            gains.set_sample_rate(sample_rate);
+        }
+        self.ghost.set_transport(&self.transport, sample_rate);
+        self.pitch_tracker.prepare(sample_rate);
+        self.onset_detector.prepare(sample_rate);
+        self.aligner.prepare(sample_rate);
+        self.onsets.clear();
+        self.onsets.reserve(max_block_size);
+        // The coefficients depend on the sample rate
+        self.set_settings(self.settings);
+    }
+
+    /// Clear all internal state and jump to the current settings without smoothing. This does not
+    /// allocate.
+    pub fn reset(&mut self) {
+        self.input_gain.reset();
+        self.sidechain_input_gain.reset();
+        self.output_gain.reset();
+        self.alignment.reset();
+
+        for band in self.bands.iter_mut().flatten() {
+            band.reset();
+        }
+        for gains in self.band_gains.iter_mut().flatten() {
+            gains.reset();
+        }
+        for crossover in self
+            .main_crossovers
+            .iter_mut()
+            .chain(self.sidechain_crossovers.iter_mut())
+        {
+            crossover.reset();
+        }
+        self.ghost.reset();
+        self.midi_envelope.reset();
+        self.pitch_tracker.reset();
+        self.onset_detector.reset();
+        self.aligner.reset();
+        self.onsets.clear();
+        self.note_events.clear();
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Update the settings. Gain changes are smoothed over the next couple of blocks.
+    pub fn set_settings(&mut self, settings: Settings) {
+        // Stateful modes should start from scratch instead of continuing from wherever they were
+        // the last time they were active, and bands that were just added shouldn't play back stale
+        // audio. Switching between left/right and mid/side processing starts over completely.
+        let (old_num_bands, old_bands) = channel_bands(&self.settings);
+        let (num_bands, bands) = channel_bands(&settings);
+        let rerouted = settings.mid_side.channels != self.settings.mid_side.channels;
+        if rerouted {
+            for crossover in self
+                .main_crossovers
+                .iter_mut()
+                .chain(self.sidechain_crossovers.iter_mut())
+            {
+                crossover.reset();
+            }
+        }
+        for (band_idx, band) in self.bands.iter_mut().enumerate().take(num_bands) {
+            for (channel_idx, channel) in band.iter_mut().enumerate() {
+                if rerouted || band_idx >= old_num_bands {
+                    channel.reset();
+                } else if bands[channel_idx][band_idx].mode != old_bands[channel_idx][band_idx].mode
+                {
+                    channel.reset_mode();
+                }
+            }
+        }
+        self.settings = settings;
+
+        self.input_gain.set_target(settings.input_gain);
+        self.sidechain_input_gain
+            .set_target(settings.sidechain_input_gain);
+        self.output_gain.set_target(settings.output_gain);
+        self.aligner.set_settings(&settings.alignment);
+        self.update_alignment();
+
+        let mut lookahead_samples = util::ms_to_samples(
+            settings.lookahead_ms.clamp(0.0, MAX_LOOKAHEAD_MS),
+            self.sample_rate,
+        );
+        // Onsets can only be triggered once they've been detected, so the main signal needs to be
+        // delayed by at least that long for the triggers to line up with it
+        if bands
+            .iter()
+            .flat_map(|bands| &bands[..num_bands])
+            .any(|band| band::uses_onsets(band.mode, &settings))
+        {
+            lookahead_samples = lookahead_samples.max(onset::LATENCY);
+        }
+        self.onset_detector
+            .set_settings(&settings.onset, lookahead_samples, self.sample_rate);
+        self.lookahead_samples = lookahead_samples;
+        self.update_latency();
+        for channel in self.bands.iter_mut().flatten() {
+            channel.set_settings(&settings, self.sample_rate);
+        }
+        for (channel_idx, channel_gains) in self.band_gains.iter_mut().enumerate() {
+            for (band_idx, gains) in channel_gains.iter_mut().enumerate() {
+                gains.set_targets(&bands[channel_idx][band_idx]);
+                if band_idx >= old_num_bands {
+                    gains.reset();
+                }
+            }
+        }
+        for crossover in self
+            .main_crossovers
+            .iter_mut()
+            .chain(self.sidechain_crossovers.iter_mut())
+        {
+            crossover.set_settings(&settings.multiband, self.sample_rate);
+        }
+        self.ghost.set_settings(&settings.ghost);
+        self.midi_envelope
+            .set_settings(&settings.midi, self.sample_rate);
+    }
+
+    /// Replace the curve used by the ghost key source's drawn shape. This copies the curve and does
+    /// not allocate.
+    pub fn set_curve(&mut self, curve: &Curve) {
+        self.ghost.set_curve(curve);
+    }
+
+    /// Update the transport information. This should be called before every `process()` call, and
+    /// is only needed for the ghost key source and the tempo-synced stutter.
+    pub fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+        self.ghost.set_transport(&transport, self.sample_rate);
+        for band in self.bands.iter_mut().flatten() {
+            band.set_transport(&transport, self.sample_rate);
+        }
+    }
+
+    /// Queue a note event for the next `process()` call. Events must be queued in order, and are
+    /// applied at the sample given by their timing. This does not allocate.
+    pub fn queue_note_event(&mut self, event: NoteEvent) {
+        if self.note_events.len() < self.note_events.capacity() {
+            self.note_events.push(event);
+        }
+    }
+
+    /// The number of samples the main signal is delayed by with the current settings. The plugin
+    /// reports this to the host, so it changes with the lookahead time and when the summing modes'
+    /// alignment offset changes. This is the most latency any mode could have with those settings,
+    /// so automating the modes doesn't make the host's delay compensation jump around. Bands whose
+    /// mode has less latency are delayed to match.
+    pub fn latency_samples(&self) -> u32 {
+        self.latency as u32
+    }
+
+    /// The sidechain's pitch as of the end of the last processed block. The pitch is tracked
+    /// regardless of the mode.
+    pub fn sidechain_pitch(&self) -> Pitch {
+        self.pitch_tracker.pitch()
+    }
+
+    /// The sample positions in the last processed block where onset triggers fired, in order.
+    /// Onsets are detected on the sidechain regardless of the mode. Triggers fire the lookahead
+    /// time after the onset, or at least [`onset::LATENCY`] samples after it, so they line up with
+    /// the main signal in the onset-triggered modes.
+    pub fn onsets(&self) -> &[usize] {
+        &self.onsets
+    }
+
+    /// The offset between the main signal and the sidechain found by learning the alignment, in
+    /// milliseconds. This is positive when the sidechain lags behind the main signal. The offset
+    /// is kept after learning stops, and it isn't part of [`Settings`], so the plugin saves it
+    /// separately and restores it with `set_learned_alignment_ms()`.
+    pub fn learned_alignment_ms(&self) -> f32 {
+        self.aligner.offset_ms()
+    }
+
+    pub fn set_learned_alignment_ms(&mut self, offset_ms: f32) {
+        self.aligner.set_offset_ms(offset_ms);
+        self.update_alignment();
+        self.update_latency();
+    }
+
+    /// Process a block of audio in place. `main` and `side` contain one slice per channel, and all
+    /// slices must have the same length. If the sidechain has fewer channels than the main input,
+    /// the last sidechain channel is reused. This does not allocate.
+    pub fn process(&mut self, main: &mut [&mut [f32]], side: &[&[f32]]) {
+        let num_samples = main.first().map_or(0, |channel| channel.len());
+        debug_assert!(num_samples <= self.max_block_size);
+        debug_assert!(side.iter().all(|channel| channel.len() == num_samples));
+        self.onsets.clear();
+        if side.is_empty() {
+            self.note_events.clear();
+            return;
+        }
+
+        let (num_bands, bands) = channel_bands(&self.settings);
+        let uses_detection = bands
+            .iter()
+            .flat_map(|bands| &bands[..num_bands])
+            .any(|band| mode::uses_detection(band.mode));
+        let key_source = if uses_detection {
+            self.settings.key_source
+        } else {
+            key_source::SIDECHAIN
+        };
+        let num_channels = main.len().min(MAX_CHANNELS);
+        let mid_side = num_channels == MAX_CHANNELS
+            && self.settings.mid_side.channels != mid_side::CHANNELS_LEFT_RIGHT;
+        let mut next_event_idx = 0;
+        for sample_idx in 0..num_samples {
+            // Notes are always tracked so the envelope is in the right state when switching to the
+            // MIDI key source halfway through a note
+            while let Some(&event) = self.note_events.get(next_event_idx) {
+                if event.timing() as usize > sample_idx {
+                    break;
+                }
+
+                self.apply_note_event(event);
+                next_event_idx += 1;
+            }
+
+            let generated_key = match key_source {
+                key_source::GHOST => Some(self.ghost.next_sample()),
+                key_source::MIDI => Some(self.midi_envelope.next_sample()),
+                _ => None,
+            };
+            let input_gain = self.input_gain.next();
+            let mut sidechain_input_gain = self.sidechain_input_gain.next();
+            let output_gain = self.output_gain.next();
+            if self.settings.sidechain_phase_flip {
+                sidechain_input_gain = -sidechain_input_gain;
+            }
+
+            let mut samples = [0.0; MAX_CHANNELS];
+            let mut sidechain_samples = [0.0; MAX_CHANNELS];
+            for (channel_idx, sidechain_sample) in sidechain_samples.iter_mut().enumerate() {
+                let sidechain_channel = side.get(channel_idx).unwrap_or(&side[side.len() - 1]);
+                *sidechain_sample = sidechain_channel[sample_idx] * sidechain_input_gain;
+            }
+            for (sample, channel) in samples.iter_mut().zip(main.iter()) {
+                *sample = channel[sample_idx] * input_gain;
+            }
+            // The pitch and onsets are tracked on the sidechain's mid component, and the alignment
+            // is learned from both signals' mid components
+            let sidechain_mid = mid_side::encode(sidechain_samples)[0];
+            if self
+                .aligner
+                .process(mid_side::encode(samples)[0], sidechain_mid)
+            {
+                self.update_alignment();
+                self.update_latency();
+            }
+            let alignment = self.alignment.next();
+            self.pitch_tracker.process(sidechain_mid);
+            let pitch = self.pitch_tracker.pitch();
+            let onset = self.onset_detector.process(sidechain_mid);
+            if onset && self.onsets.len() < self.onsets.capacity() {
+                self.onsets.push(sample_idx);
+            }
+
+            if mid_side {
+                samples = mid_side::encode(samples);
+            }
+            sidechain_samples = match self.settings.mid_side.key {
+                mid_side::KEY_MID => [mid_side::encode(sidechain_samples)[0]; MAX_CHANNELS],
+                mid_side::KEY_SIDE => [mid_side::encode(sidechain_samples)[1]; MAX_CHANNELS],
+                _ if mid_side => mid_side::encode(sidechain_samples),
+                _ => sidechain_samples,
+            };
+
+            // With a single band these pass the signals through unchanged
+            let mut main_bands = [[0.0; MAX_BANDS]; MAX_CHANNELS];
+            let mut keys = [[0.0; MAX_BANDS]; MAX_CHANNELS];
+            for channel_idx in 0..num_channels {
+                self.main_crossovers[channel_idx]
+                    .process(samples[channel_idx], &mut main_bands[channel_idx]);
+                self.sidechain_crossovers[channel_idx]
+                    .process(sidechain_samples[channel_idx], &mut keys[channel_idx]);
+            }
+
+            for band_idx in 0..num_bands {
+                let detecting =
+                    |channel_idx: usize| mode::uses_detection(bands[channel_idx][band_idx].mode);
+
+                // Generated keys aren't split into bands
+                if let Some(key) = generated_key {
+                    for channel_idx in (0..num_channels).filter(|&idx| detecting(idx)) {
+                        keys[channel_idx][band_idx] = key * sidechain_input_gain;
+                    }
+                }
+
+                if self.settings.detection_link != detection_link::INDEPENDENT
+                    && (0..num_channels).all(detecting)
+                {
+                    let mut band_keys = [0.0; MAX_CHANNELS];
+                    for (band_key, keys) in band_keys.iter_mut().zip(&keys) {
+                        *band_key = keys[band_idx];
+                    }
+                    detection_link::apply(
+                        self.settings.detection_link,
+                        self.settings.detection_link_amount,
+                        &mut band_keys[..num_channels],
+                    );
+                    for (keys, band_key) in keys.iter_mut().zip(band_keys) {
+                        keys[band_idx] = band_key;
+                    }
+                }
+            }
+
+            for channel_idx in 0..num_channels {
+                let mut output = 0.0;
+                for band_idx in 0..num_bands {
+                    let gains = &mut self.band_gains[channel_idx][band_idx];
+                    output += self.bands[band_idx][channel_idx].process(
+                        bands[channel_idx][band_idx].mode,
+                        main_bands[channel_idx][band_idx] * gains.input.next(),
+                        keys[channel_idx][band_idx] * gains.sidechain_input.next(),
+                        pitch,
+                        onset,
+                        alignment,
+                    ) * gains.output.next();
+                }
+
+                samples[channel_idx] = output;
+            }
+
+            if mid_side {
+                samples = mid_side::decode(samples);
+            }
+            for (channel, sample) in main.iter_mut().zip(samples) {
+                channel[sample_idx] = sample * output_gain;
+            }
+        }
+
+        // Events with timings past the end of the block still need to end up in the right state
+        for idx in next_event_idx..self.note_events.len() {
+            self.apply_note_event(self.note_events[idx]);
+        }
+        self.note_events.clear();
+    }
+
+    /// Point the alignment at either the manual or the learned offset.
+    fn update_alignment(&mut self) {
+        let offset_ms = if self.settings.alignment.manual {
+            self.settings.alignment.offset_ms
+        } else {
+            self.aligner.offset_ms()
+        };
+        let offset_ms = offset_ms.clamp(-alignment::MAX_OFFSET_MS, alignment::MAX_OFFSET_MS);
+        let offset = offset_ms / 1000.0 * self.sample_rate;
+        self.alignment.set_target(offset);
+        // The learned offset wobbles a little, and the latency shouldn't change every time it
+        // crosses a whole sample. The sidechain makes up for the main signal being delayed by up to
+        // three samples more than the offset.
+        let main_delay = self.main_alignment_samples as f32;
+        let learned = !self.settings.alignment.manual;
+        if !learned || offset <= 0.0 || offset > main_delay - 1.0 || offset < main_delay - 3.0 {
+            self.main_alignment_samples = alignment::main_delay_samples(offset);
+        }
+    }
+
+    /// Work out every band's latency from its mode, and delay the bands so they line up with the
+    /// most latency any mode could have. This needs to be called whenever the modes, the lookahead,
+    /// or the main signal's alignment delay change.
+    fn update_latency(&mut self) {
+        let (num_bands, bands) = channel_bands(&self.settings);
+        let (lookahead_samples, main_alignment_samples) =
+            (self.lookahead_samples, self.main_alignment_samples);
+        let latencies = bands.map(|bands| {
+            bands.map(|band| {
+                band::mode_latency(band.mode, lookahead_samples, main_alignment_samples)
+            })
+        });
+        // Any band can be switched to any mode, and switching to the onset-triggered stutter
+        // raises the lookahead to the onset detector's latency
+        let max_lookahead_samples = if self.settings.stutter.trigger == stutter::TRIGGER_ONSET {
+            lookahead_samples.max(onset::LATENCY)
+        } else {
+            lookahead_samples
+        };
+        self.latency = (0..=mode::MAX)
+            .map(|mode| band::mode_latency(mode, max_lookahead_samples, main_alignment_samples))
+            .chain(
+                latencies
+                    .iter()
+                    .flat_map(|latencies| &latencies[..num_bands])
+                    .copied(),
+            )
+            .max()
+            .unwrap_or(0);
+        for (band_idx, band) in self.bands.iter_mut().enumerate() {
+            for (channel_idx, channel) in band.iter_mut().enumerate() {
+                channel.set_delays(
+                    lookahead_samples,
+                    main_alignment_samples,
+                    self.latency - latencies[channel_idx][band_idx].min(self.latency),
+                );
+            }
+        }
+    }
+
+    fn apply_note_event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On { note, velocity, .. } => self.midi_envelope.note_on(note, velocity),
+            NoteEvent::Off { note, .. } => self.midi_envelope.note_off(note),
+        }
+    }
+}
+
+/// The number of bands, and the mode and gains for every channel's bands. Without multiband
+/// processing there's a single band that uses `Settings::mode`. With mid/side processing the
+/// channels are the mid and side components, and components that aren't processed get passed
+/// through unchanged.
+fn channel_bands(settings: &Settings) -> (usize, [[BandSettings; MAX_BANDS]; MAX_CHANNELS]) {
+    let num_bands = settings.multiband.num_bands.clamp(1, MAX_BANDS);
+    let bands = if num_bands == 1 {
+        [BandSettings {
+            mode: settings.mode,
+            ..BandSettings::default()
+        }; MAX_BANDS]
+    } else {
+        settings.multiband.bands
+    };
+    let passthrough = [BandSettings {
+        mode: band::PASSTHROUGH,
+        ..BandSettings::default()
+    }; MAX_BANDS];
+
+    let channels = match settings.mid_side.channels {
+        mid_side::CHANNELS_MID => [bands, passthrough],
+        mid_side::CHANNELS_SIDE => [passthrough, bands],
+        mid_side::CHANNELS_MID_SIDE => [bands, [settings.mid_side.side; MAX_BANDS]],
+        _ => [bands; MAX_CHANNELS],
+    };
+
+    (num_bands, channels)
+}
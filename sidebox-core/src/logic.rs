@@ -0,0 +1,96 @@
+// The threshold switch logic mode, which outputs whichever signal is louder. The other logic modes
+// compare the signals sample by sample and don't need any state.
+
+use crate::util;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdSwitchSettings {
+    /// How much louder than the main signal the sidechain needs to be before it's selected.
+    pub threshold_db: f32,
+    /// How quickly the level detectors fall after a peak. This keeps the switch from flipping back
+    /// and forth on every zero crossing.
+    pub release_ms: f32,
+    /// How long it takes to fade from one signal to the other.
+    pub crossfade_ms: f32,
+}
+
+impl Default for ThresholdSwitchSettings {
+    fn default() -> Self {
+        Self {
+            threshold_db: 0.0,
+            release_ms: 50.0,
+            crossfade_ms: 5.0,
+        }
+    }
+}
+
+/// Switches between the main signal and the sidechain for a single channel, based on their peak
+/// levels.
+#[derive(Debug, Clone)]
+pub struct ThresholdSwitch {
+    threshold: f32,
+    release_coefficient: f32,
+    crossfade_coefficient: f32,
+
+    main_level: f32,
+    side_level: f32,
+    /// How much of the sidechain is in the output, from 0 to 1.
+    mix: f32,
+}
+
+impl Default for ThresholdSwitch {
+    fn default() -> Self {
+        let mut switch = Self {
+            threshold: 1.0,
+            release_coefficient: 1.0,
+            crossfade_coefficient: 1.0,
+
+            main_level: 0.0,
+            side_level: 0.0,
+            mix: 0.0,
+        };
+        switch.set_settings(&ThresholdSwitchSettings::default(), 44100.0);
+
+        switch
+    }
+}
+
+impl ThresholdSwitch {
+    pub fn set_settings(&mut self, settings: &ThresholdSwitchSettings, sample_rate: f32) {
+        self.threshold = util::db_to_gain(settings.threshold_db);
+        self.release_coefficient = util::one_pole_coefficient(settings.release_ms, sample_rate);
+        self.crossfade_coefficient = util::one_pole_coefficient(settings.crossfade_ms, sample_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.main_level = 0.0;
+        self.side_level = 0.0;
+        self.mix = 0.0;
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        for (level, input) in [(&mut self.main_level, sample), (&mut self.side_level, key)] {
+            let rectified = input.abs();
+            if rectified > *level {
+                *level = rectified;
+            } else {
+                *level += (rectified - *level) * self.release_coefficient;
+            }
+        }
+
+        let target = if self.side_level > self.main_level * self.threshold {
+            1.0
+        } else {
+            0.0
+        };
+        self.mix += (target - self.mix) * self.crossfade_coefficient;
+        // Snap once the other signal is 80 dB down so a settled switch passes its input through
+        // exactly. Close to one the fade stalls before it gets much closer than this.
+        if (target - self.mix).abs() < 1e-4 {
+            self.mix = target;
+        }
+
+        sample * (1.0 - self.mix) + key * self.mix
+    }
+}
@@ -0,0 +1,102 @@
+// Mid/side encoding, so the modes can work on the mid and side components of a stereo signal
+// instead of on the left and right channels
+
+use crate::multiband::BandSettings;
+
+/// Process the left and right channels, without any encoding.
+pub const CHANNELS_LEFT_RIGHT: i32 = 0;
+/// Only process the mid component, the side component passes through.
+pub const CHANNELS_MID: i32 = 1;
+/// Only process the side component, the mid component passes through.
+pub const CHANNELS_SIDE: i32 = 2;
+/// Process both components, the side component with `MidSideSettings::side`.
+pub const CHANNELS_MID_SIDE: i32 = 3;
+
+pub const CHANNELS_NAMES: [&str; 4] = ["Left/right", "Mid", "Side", "Mid/side"];
+
+pub fn channels_name(channels: i32) -> &'static str {
+    usize::try_from(channels)
+        .ok()
+        .and_then(|idx| CHANNELS_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a channels number or a (case insensitive) name.
+pub fn channels_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(channels) if (0..CHANNELS_NAMES.len() as i32).contains(&channels) => Some(channels),
+        Ok(_) => None,
+        Err(_) => CHANNELS_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+/// Every channel is keyed by the matching sidechain channel. When the main signal is encoded to
+/// mid/side, so is the sidechain.
+pub const KEY_MATCHING: i32 = 0;
+/// Every channel is keyed by the sidechain's mid component.
+pub const KEY_MID: i32 = 1;
+/// Every channel is keyed by the sidechain's side component.
+pub const KEY_SIDE: i32 = 2;
+
+pub const KEY_NAMES: [&str; 3] = ["Matching", "Mid", "Side"];
+
+pub fn key_name(key: i32) -> &'static str {
+    usize::try_from(key)
+        .ok()
+        .and_then(|idx| KEY_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a key number or a (case insensitive) key name.
+pub fn key_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(key) if (0..KEY_NAMES.len() as i32).contains(&key) => Some(key),
+        Ok(_) => None,
+        Err(_) => KEY_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MidSideSettings {
+    /// One of the `CHANNELS_*` constants. This only has an effect on stereo signals.
+    pub channels: i32,
+    /// One of the `KEY_*` constants.
+    pub key: i32,
+    /// The mode and gains for the side component with `CHANNELS_MID_SIDE`, while the mid component
+    /// uses the regular settings. With multiple bands, every band of the side component uses
+    /// these.
+    pub side: BandSettings,
+}
+
+impl Default for MidSideSettings {
+    fn default() -> Self {
+        Self {
+            channels: CHANNELS_LEFT_RIGHT,
+            key: KEY_MATCHING,
+            side: BandSettings::default(),
+        }
+    }
+}
+
+/// Left and right to mid and side. Decoding the result with [`decode()`] results in the original
+/// signal.
+#[inline]
+pub fn encode([left, right]: [f32; 2]) -> [f32; 2] {
+    [(left + right) * 0.5, (left - right) * 0.5]
+}
+
+/// Mid and side back to left and right.
+#[inline]
+pub fn decode([mid, side]: [f32; 2]) -> [f32; 2] {
+    [mid + side, mid - side]
+}
@@ -0,0 +1,163 @@
+// Splits the main and sidechain signals into bands so every band can use its own mode
+
+use crate::mode;
+use crate::svf::{Svf, BUTTERWORTH_Q};
+
+/// The most bands the signals can be split into.
+pub const MAX_BANDS: usize = 4;
+
+/// The lowest and highest crossover frequencies.
+pub const MIN_CROSSOVER_HZ: f32 = 20.0;
+pub const MAX_CROSSOVER_HZ: f32 = 20000.0;
+
+/// The mode and gains for a single band. Gains are linear and are applied on top of the global
+/// gains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandSettings {
+    /// One of the constants from [`mode`].
+    pub mode: i32,
+    pub input_gain: f32,
+    pub sidechain_input_gain: f32,
+    pub output_gain: f32,
+}
+
+impl Default for BandSettings {
+    fn default() -> Self {
+        Self {
+            mode: mode::ADDITION,
+            input_gain: 1.0,
+            sidechain_input_gain: 1.0,
+            output_gain: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MultibandSettings {
+    /// Between 1 and `MAX_BANDS`. With a single band nothing gets split and `Settings::mode` is
+    /// used instead of the band settings.
+    pub num_bands: usize,
+    /// The frequencies between neighbouring bands, from low to high. Only the first
+    /// `num_bands - 1` are used.
+    pub crossovers_hz: [f32; MAX_BANDS - 1],
+    pub bands: [BandSettings; MAX_BANDS],
+}
+
+impl Default for MultibandSettings {
+    fn default() -> Self {
+        Self {
+            num_bands: 1,
+            crossovers_hz: [150.0, 1000.0, 4000.0],
+            bands: [BandSettings::default(); MAX_BANDS],
+        }
+    }
+}
+
+/// A fourth order Linkwitz-Riley split into a low and a high band. The bands sum back to an
+/// all-pass with a flat magnitude response.
+#[derive(Debug, Clone, Copy, Default)]
+struct Split {
+    first_stage: Svf,
+    low_stage: Svf,
+    high_stage: Svf,
+}
+
+impl Split {
+    fn set(&mut self, frequency: f32, sample_rate: f32) {
+        for svf in [
+            &mut self.first_stage,
+            &mut self.low_stage,
+            &mut self.high_stage,
+        ] {
+            svf.set(frequency, BUTTERWORTH_Q, sample_rate);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.first_stage.reset();
+        self.low_stage.reset();
+        self.high_stage.reset();
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> (f32, f32) {
+        let first = self.first_stage.process(input);
+
+        (
+            self.low_stage.process(first.low).low,
+            self.high_stage.process(first.high).high,
+        )
+    }
+}
+
+/// Splits a single channel into up to `MAX_BANDS` bands that sum back to an all-pass filtered
+/// version of the input. The lower bands are split off one at a time, so every band also goes
+/// through all-pass filters matching the phase shift of the crossovers above it.
+#[derive(Debug, Clone)]
+pub struct Crossover {
+    num_bands: usize,
+    splits: [Split; MAX_BANDS - 1],
+    /// `all_passes[band_idx][crossover_idx]`, only used for crossovers above the band.
+    all_passes: [[Svf; MAX_BANDS - 1]; MAX_BANDS],
+}
+
+impl Default for Crossover {
+    fn default() -> Self {
+        Self {
+            num_bands: 1,
+            splits: Default::default(),
+            all_passes: Default::default(),
+        }
+    }
+}
+
+impl Crossover {
+    pub fn set_settings(&mut self, settings: &MultibandSettings, sample_rate: f32) {
+        let num_bands = settings.num_bands.clamp(1, MAX_BANDS);
+        if num_bands != self.num_bands {
+            self.num_bands = num_bands;
+            self.reset();
+        }
+
+        // The crossovers can't cross each other, so every frequency is at least the one before it
+        let mut min_frequency = MIN_CROSSOVER_HZ;
+        for (crossover_idx, &frequency) in settings.crossovers_hz.iter().enumerate() {
+            let frequency = frequency.clamp(min_frequency, MAX_CROSSOVER_HZ);
+            min_frequency = frequency;
+
+            self.splits[crossover_idx].set(frequency, sample_rate);
+            for all_passes in self.all_passes.iter_mut() {
+                all_passes[crossover_idx].set(frequency, BUTTERWORTH_Q, sample_rate);
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for split in self.splits.iter_mut() {
+            split.reset();
+        }
+        for svf in self.all_passes.iter_mut().flatten() {
+            svf.reset();
+        }
+    }
+
+    /// Split a sample into the first `num_bands` entries of `bands`, from low to high.
+    #[inline]
+    pub fn process(&mut self, input: f32, bands: &mut [f32; MAX_BANDS]) {
+        let last_band_idx = self.num_bands - 1;
+
+        let mut rest = input;
+        for (band, split) in bands.iter_mut().zip(&mut self.splits).take(last_band_idx) {
+            let (low, high) = split.process(rest);
+            *band = low;
+            rest = high;
+        }
+        bands[last_band_idx] = rest;
+
+        for (band_idx, band) in bands.iter_mut().enumerate().take(last_band_idx) {
+            for all_pass in &mut self.all_passes[band_idx][band_idx + 1..last_band_idx] {
+                *band = all_pass.process_all_pass(*band);
+            }
+        }
+    }
+}
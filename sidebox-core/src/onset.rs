@@ -0,0 +1,315 @@
+// Onset detection on the sidechain, shared by the modes that are triggered by transients
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::util;
+
+/// The analysis window. This is much shorter than the spectral modes' window since onsets need to
+/// be found quickly.
+const FRAME_SIZE: usize = 512;
+const NUM_BINS: usize = FRAME_SIZE / 2 + 1;
+const HOP_SIZE: usize = FRAME_SIZE / 4;
+/// An onset is located somewhere in the last this many samples when it's detected. Triggers are
+/// delayed by at least this much so they can fire at the onset's exact position.
+pub const LATENCY: usize = HOP_SIZE * 2;
+/// The located onset falls in the block of this many samples whose peak rose the most.
+const SUB_BLOCK_SIZE: usize = 16;
+/// The adaptive threshold follows the median of the detection function over this period. The median
+/// isn't pulled up by a single loud onset, so a hit right after it can still be detected.
+const THRESHOLD_WINDOW_MS: f32 = 150.0;
+/// Frames that are quieter than this never contain an onset, so noise in otherwise silent parts
+/// doesn't trigger anything.
+const SILENCE_DB: f32 = -60.0;
+
+/// The positive change in every bin's magnitude between frames. Works well for most material.
+pub const METHOD_SPECTRAL_FLUX: i32 = 0;
+/// The energy weighted by frequency. Reacts strongly to percussive attacks, and ignores changes in
+/// the low end.
+pub const METHOD_HFC: i32 = 1;
+
+pub const METHOD_NAMES: [&str; 2] = ["Spectral flux", "High frequency content"];
+
+pub fn method_name(method: i32) -> &'static str {
+    usize::try_from(method)
+        .ok()
+        .and_then(|idx| METHOD_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a method number or a (case insensitive) name.
+pub fn method_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(method) if (0..METHOD_NAMES.len() as i32).contains(&method) => Some(method),
+        Ok(_) => None,
+        Err(_) => METHOD_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnsetSettings {
+    /// One of the `METHOD_*` constants.
+    pub method: i32,
+    /// An onset is detected when the detection function rises above its recent median times this
+    /// factor. Lower values detect more onsets.
+    pub threshold: f32,
+    /// Onsets closer than this to the previous onset are ignored.
+    pub min_interval_ms: f32,
+}
+
+impl Default for OnsetSettings {
+    fn default() -> Self {
+        Self {
+            method: METHOD_SPECTRAL_FLUX,
+            threshold: 2.0,
+            min_interval_ms: 50.0,
+        }
+    }
+}
+
+/// Finds onsets in a signal one sample at a time. Every `HOP_SIZE` samples the last `FRAME_SIZE`
+/// samples are analyzed, and a detected onset is then located to the exact sample in the time
+/// domain. The trigger fires a fixed delay after that sample, so triggers land on the same sample
+/// no matter how the signal is split into blocks.
+///
+/// The buffers are allocated in `prepare()`, after that nothing allocates.
+#[derive(Clone, Default)]
+pub struct OnsetDetector {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    /// A Hann window.
+    window: Vec<f32>,
+    full_scale_magnitude: f32,
+
+    method: i32,
+    threshold: f32,
+    min_interval: u64,
+    delay: u64,
+    /// The number of detection function values the adaptive threshold takes the median of.
+    history_len: usize,
+
+    input: Vec<f32>,
+    /// The position of the oldest sample in `input`.
+    pos: usize,
+    samples_until_frame: usize,
+    /// The number of samples processed so far, used to keep track of the onsets' positions.
+    time: u64,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+    previous_magnitudes: Vec<f32>,
+    /// The last `history_len` detection function values, in a circular buffer.
+    history: Vec<f32>,
+    history_pos: usize,
+    /// `history` sorted, for the median.
+    sorted_history: Vec<f32>,
+    previous_above: bool,
+    previous_value: f32,
+    last_onset: Option<u64>,
+    /// The times at which detected onsets fire, oldest first.
+    pending: [u64; 4],
+    num_pending: usize,
+}
+
+impl fmt::Debug for OnsetDetector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnsetDetector")
+            .field("method", &self.method)
+            .field("time", &self.time)
+            .field("num_pending", &self.num_pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OnsetDetector {
+    /// Plan the FFT and allocate the buffers for a sample rate.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        if self.fft.is_none() {
+            let mut planner = FftPlanner::new();
+            let fft = planner.plan_fft_forward(FRAME_SIZE);
+
+            self.window = (0..FRAME_SIZE)
+                .map(|idx| 0.5 - 0.5 * (2.0 * PI * idx as f32 / FRAME_SIZE as f32).cos())
+                .collect();
+            self.full_scale_magnitude = self.window.iter().sum::<f32>() / 2.0;
+            self.input = vec![0.0; FRAME_SIZE];
+            self.spectrum = vec![Complex::default(); FRAME_SIZE];
+            self.scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+            self.magnitudes = vec![0.0; NUM_BINS];
+            self.previous_magnitudes = vec![0.0; NUM_BINS];
+            self.fft = Some(fft);
+        }
+
+        let frame_rate = sample_rate / HOP_SIZE as f32;
+        self.history_len = ((THRESHOLD_WINDOW_MS / 1000.0 * frame_rate).ceil() as usize).max(1);
+        self.history.clear();
+        self.history.resize(self.history_len, 0.0);
+        self.sorted_history.clear();
+        self.sorted_history.resize(self.history_len, 0.0);
+        self.reset();
+    }
+
+    /// `delay` is the number of samples between an onset and its trigger, which is at least
+    /// [`LATENCY`].
+    pub fn set_settings(&mut self, settings: &OnsetSettings, delay: usize, sample_rate: f32) {
+        self.method = settings.method;
+        self.threshold = settings.threshold.max(1.0);
+        // Onsets are always at least a sample apart so they stay in order
+        self.min_interval =
+            util::ms_to_samples(settings.min_interval_ms, sample_rate).max(1) as u64;
+        self.delay = delay.max(LATENCY) as u64;
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.pos = 0;
+        self.samples_until_frame = HOP_SIZE;
+        self.time = 0;
+        self.previous_magnitudes.fill(0.0);
+        self.history.fill(0.0);
+        self.history_pos = 0;
+        self.previous_above = false;
+        self.previous_value = 0.0;
+        self.last_onset = None;
+        self.num_pending = 0;
+    }
+
+    /// Process a single sample. Returns whether an onset's trigger fires at this sample.
+    #[inline]
+    pub fn process(&mut self, sample: f32) -> bool {
+        let triggered = self.num_pending > 0 && self.pending[0] <= self.time;
+        if triggered {
+            self.pending.copy_within(1..self.num_pending, 0);
+            self.num_pending -= 1;
+        }
+
+        if !self.input.is_empty() {
+            self.input[self.pos] = sample;
+            self.pos = (self.pos + 1) % FRAME_SIZE;
+            self.samples_until_frame -= 1;
+            if self.samples_until_frame == 0 {
+                self.samples_until_frame = HOP_SIZE;
+                self.analyze();
+            }
+        }
+        self.time += 1;
+
+        triggered
+    }
+
+    /// The sample at `idx` in chronological order, with zero being the oldest sample.
+    fn input_at(&self, idx: usize) -> f32 {
+        self.input[(self.pos + idx) % FRAME_SIZE]
+    }
+
+    fn analyze(&mut self) {
+        let Some(fft) = &self.fft else {
+            return;
+        };
+
+        // `self.pos` now points at the oldest sample
+        for idx in 0..FRAME_SIZE {
+            let sample = self.input[(self.pos + idx) % FRAME_SIZE];
+            self.spectrum[idx] = Complex::new(sample * self.window[idx], 0.0);
+        }
+        fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        for (magnitude, bin) in self.magnitudes.iter_mut().zip(&self.spectrum) {
+            *magnitude = bin.norm() / self.full_scale_magnitude;
+        }
+
+        let value = match self.method {
+            METHOD_HFC => {
+                self.magnitudes
+                    .iter()
+                    .enumerate()
+                    .map(|(bin_idx, magnitude)| bin_idx as f32 * magnitude * magnitude)
+                    .sum::<f32>()
+                    / NUM_BINS as f32
+            }
+            _ => self
+                .magnitudes
+                .iter()
+                .zip(&self.previous_magnitudes)
+                .map(|(magnitude, previous)| (magnitude - previous).max(0.0))
+                .sum(),
+        };
+        std::mem::swap(&mut self.magnitudes, &mut self.previous_magnitudes);
+
+        // The threshold is based on the values before this frame so the onset itself doesn't
+        // raise it
+        self.sorted_history.copy_from_slice(&self.history);
+        self.sorted_history.sort_unstable_by(f32::total_cmp);
+        let median = self.sorted_history[self.history_len / 2];
+        self.history[self.history_pos] = value;
+        self.history_pos = (self.history_pos + 1) % self.history_len;
+
+        // A hit right after another one doesn't fall below the threshold in between, so it's
+        // detected by the jump from the previous frame instead
+        let above = value > median * self.threshold && value > 0.0;
+        let rising =
+            above && (!self.previous_above || value > self.previous_value * self.threshold);
+        self.previous_above = above;
+        self.previous_value = value;
+        if !rising {
+            return;
+        }
+
+        let Some(onset) = self.locate_onset() else {
+            return;
+        };
+        if self
+            .last_onset
+            .is_some_and(|last_onset| onset < last_onset + self.min_interval)
+        {
+            return;
+        }
+
+        self.last_onset = Some(onset);
+        if self.num_pending < self.pending.len() {
+            self.pending[self.num_pending] = onset + self.delay;
+            self.num_pending += 1;
+        }
+    }
+
+    /// Find the sample in the last `LATENCY` samples where the onset starts, as a time. This is the
+    /// first sample at half the peak of the sub-block whose peak rose the most compared to the
+    /// sub-block before it. Returns `None` when the signal is too quiet.
+    fn locate_onset(&self) -> Option<u64> {
+        let silence = util::db_to_gain(SILENCE_DB);
+        let peak = |start: usize| {
+            (start..start + SUB_BLOCK_SIZE)
+                .map(|idx| self.input_at(idx).abs())
+                .fold(0.0, f32::max)
+        };
+
+        let search_start = FRAME_SIZE - LATENCY;
+        let mut previous_peak = peak(search_start - SUB_BLOCK_SIZE);
+        let mut best = None;
+        let mut best_rise = 0.0;
+        for start in (search_start..FRAME_SIZE).step_by(SUB_BLOCK_SIZE) {
+            let current_peak = peak(start);
+            let rise = current_peak / (previous_peak + silence);
+            if current_peak >= silence && rise > best_rise {
+                best = Some((start, current_peak));
+                best_rise = rise;
+            }
+            previous_peak = current_peak;
+        }
+
+        let (start, block_peak) = best?;
+        let offset = (start..start + SUB_BLOCK_SIZE)
+            .find(|&idx| self.input_at(idx).abs() >= block_peak * 0.5)
+            .unwrap_or(start);
+
+        // `self.time` is the time of the newest sample in the window
+        Some((self.time + 1 + offset as u64).saturating_sub(FRAME_SIZE as u64))
+    }
+}
@@ -0,0 +1,224 @@
+// Monophonic pitch detection for the sidechain, using the McLeod pitch method
+
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// The analysis window covers at least this much time. The window needs to fit a couple of periods
+/// of the lowest frequency that can be detected.
+const WINDOW_MS: f32 = 40.0;
+/// The range of fundamentals that can be detected.
+pub const MIN_FREQUENCY_HZ: f32 = 50.0;
+pub const MAX_FREQUENCY_HZ: f32 = 2000.0;
+/// The first peak in the normalized square difference function that's at least this close to the
+/// highest peak is picked as the period. Lower values prefer higher octaves.
+const PEAK_THRESHOLD: f32 = 0.9;
+/// Windows with less energy per sample than this are considered silent.
+const SILENCE_THRESHOLD: f32 = 1e-8;
+
+/// A detected pitch.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pitch {
+    /// The fundamental frequency in Hz. This holds on to the last detected frequency while nothing
+    /// can be detected, and is zero until the first detection.
+    pub frequency_hz: f32,
+    /// How periodic the signal is, in `[0, 1]`. Silence and noise have a low confidence.
+    pub confidence: f32,
+}
+
+/// Tracks the pitch of a signal one sample at a time. The last window's worth of samples is kept
+/// in a circular buffer, and it gets analyzed every quarter window. The autocorrelation is computed
+/// with FFTs so the analysis stays cheap for long windows.
+///
+/// The buffers are allocated in `prepare()`, after that nothing allocates.
+#[derive(Clone, Default)]
+pub struct PitchTracker {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    ifft: Option<Arc<dyn Fft<f32>>>,
+    sample_rate: f32,
+    window_size: usize,
+    min_period: usize,
+    max_period: usize,
+
+    input: Vec<f32>,
+    /// The position of the oldest sample in `input`.
+    pos: usize,
+    samples_until_analysis: usize,
+    /// `input` in chronological order.
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+    /// The normalized square difference function for every period up to `max_period`.
+    nsdf: Vec<f32>,
+    /// The periods with the highest NSDF value between every pair of zero crossings.
+    key_maxima: Vec<usize>,
+
+    pitch: Pitch,
+}
+
+impl fmt::Debug for PitchTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PitchTracker")
+            .field("window_size", &self.window_size)
+            .field("pitch", &self.pitch)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PitchTracker {
+    /// Size the window for a sample rate, plan the FFTs, and allocate the buffers.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        let window_size = ((WINDOW_MS / 1000.0 * sample_rate) as usize).next_power_of_two();
+        self.sample_rate = sample_rate;
+        self.min_period = (sample_rate / MAX_FREQUENCY_HZ).floor().max(2.0) as usize;
+        self.max_period = ((sample_rate / MIN_FREQUENCY_HZ).ceil() as usize).min(window_size / 2);
+
+        if window_size != self.window_size {
+            // The window is zero padded to twice its size so the autocorrelation doesn't wrap around
+            let mut planner = FftPlanner::new();
+            let fft = planner.plan_fft_forward(window_size * 2);
+            let ifft = planner.plan_fft_inverse(window_size * 2);
+            let scratch_len = fft
+                .get_inplace_scratch_len()
+                .max(ifft.get_inplace_scratch_len());
+
+            self.window_size = window_size;
+            self.input = vec![0.0; window_size];
+            self.window = vec![0.0; window_size];
+            self.spectrum = vec![Complex::default(); window_size * 2];
+            self.scratch = vec![Complex::default(); scratch_len];
+            self.nsdf = vec![0.0; window_size / 2 + 2];
+            self.key_maxima = Vec::with_capacity(window_size / 2 + 2);
+            self.fft = Some(fft);
+            self.ifft = Some(ifft);
+        }
+
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.input.fill(0.0);
+        self.pos = 0;
+        self.samples_until_analysis = (self.window_size / 4).max(1);
+        self.pitch = Pitch::default();
+    }
+
+    /// The pitch as of the last analysis.
+    pub fn pitch(&self) -> Pitch {
+        self.pitch
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32) {
+        if self.input.is_empty() {
+            return;
+        }
+
+        self.input[self.pos] = sample;
+        self.pos = (self.pos + 1) % self.window_size;
+
+        self.samples_until_analysis -= 1;
+        if self.samples_until_analysis == 0 {
+            self.samples_until_analysis = self.window_size / 4;
+            self.analyze();
+        }
+    }
+
+    fn analyze(&mut self) {
+        let (Some(fft), Some(ifft)) = (&self.fft, &self.ifft) else {
+            return;
+        };
+        let window_size = self.window_size;
+
+        let (newer, older) = self.input.split_at(self.pos);
+        self.window[..older.len()].copy_from_slice(older);
+        self.window[older.len()..].copy_from_slice(newer);
+
+        let energy: f32 = self.window.iter().map(|sample| sample * sample).sum();
+        if !energy.is_finite() || energy / (window_size as f32) < SILENCE_THRESHOLD {
+            self.pitch.confidence = 0.0;
+            return;
+        }
+
+        // The autocorrelation is the inverse transform of the power spectrum
+        for (bin, &sample) in self.spectrum.iter_mut().zip(&self.window) {
+            *bin = Complex::new(sample, 0.0);
+        }
+        self.spectrum[window_size..].fill(Complex::default());
+        fft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        for bin in self.spectrum.iter_mut() {
+            *bin = Complex::new(bin.norm_sqr(), 0.0);
+        }
+        ifft.process_with_scratch(&mut self.spectrum, &mut self.scratch);
+        let normalization = 1.0 / (window_size * 2) as f32;
+
+        // `m` is the sum of the squares of the two overlapping parts of the window for every period
+        let mut m = 2.0 * energy;
+        self.nsdf[0] = 1.0;
+        for period in 1..=self.max_period + 1 {
+            m -= self.window[period - 1].powi(2) + self.window[window_size - period].powi(2);
+            let autocorrelation = self.spectrum[period].re * normalization;
+            self.nsdf[period] = if m > f32::EPSILON {
+                2.0 * autocorrelation / m
+            } else {
+                0.0
+            };
+        }
+
+        // Every region where the NSDF is positive contains one key maximum, except for the first
+        // one which starts at a period of zero
+        self.key_maxima.clear();
+        let mut period = 1;
+        while period <= self.max_period && self.nsdf[period] > 0.0 {
+            period += 1;
+        }
+        let mut current_max: Option<usize> = None;
+        for period in period..=self.max_period {
+            if self.nsdf[period] > 0.0 {
+                if current_max.is_none_or(|max| self.nsdf[period] > self.nsdf[max]) {
+                    current_max = Some(period);
+                }
+            } else if let Some(max) = current_max.take() {
+                self.key_maxima.push(max);
+            }
+        }
+        self.key_maxima.extend(current_max);
+
+        let Some(highest) = self
+            .key_maxima
+            .iter()
+            .map(|&period| self.nsdf[period])
+            .reduce(f32::max)
+        else {
+            self.pitch.confidence = 0.0;
+            return;
+        };
+        let Some(&period) = self.key_maxima.iter().find(|&&period| {
+            period >= self.min_period && self.nsdf[period] >= highest * PEAK_THRESHOLD
+        }) else {
+            self.pitch.confidence = 0.0;
+            return;
+        };
+
+        // Parabolic interpolation between the neighbouring periods
+        let (previous, current, next) = (
+            self.nsdf[period - 1],
+            self.nsdf[period],
+            self.nsdf[period + 1],
+        );
+        let curvature = previous - 2.0 * current + next;
+        let (offset, value) = if curvature < 0.0 {
+            let offset = 0.5 * (previous - next) / curvature;
+            (offset, current - 0.25 * (previous - next) * offset)
+        } else {
+            (0.0, current)
+        };
+
+        self.pitch = Pitch {
+            frequency_hz: self.sample_rate / (period as f32 + offset),
+            confidence: value.clamp(0.0, 1.0),
+        };
+    }
+}
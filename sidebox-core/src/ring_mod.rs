@@ -0,0 +1,91 @@
+// Ring modulation with a sine carrier that follows the sidechain's pitch
+
+use std::f32::consts::TAU;
+
+use crate::pitch::Pitch;
+use crate::util;
+
+/// The carrier's frequency until the first pitch has been detected.
+const DEFAULT_CARRIER_HZ: f32 = 440.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RingModSettings {
+    /// The carrier's pitch relative to the sidechain's pitch, in semitones.
+    pub transpose_semitones: f32,
+    /// How quickly the carrier follows pitch changes.
+    pub glide_ms: f32,
+    /// The carrier only follows pitches detected with at least this confidence, and holds its
+    /// frequency otherwise. In `[0, 1]`.
+    pub min_confidence: f32,
+}
+
+impl Default for RingModSettings {
+    fn default() -> Self {
+        Self {
+            transpose_semitones: 0.0,
+            glide_ms: 20.0,
+            min_confidence: 0.8,
+        }
+    }
+}
+
+/// A ring modulator for a single channel.
+#[derive(Debug, Clone)]
+pub struct RingMod {
+    ratio: f32,
+    glide_coefficient: f32,
+    min_confidence: f32,
+    sample_rate: f32,
+
+    target_frequency: f32,
+    frequency: f32,
+    /// In `[0, 1)`.
+    phase: f32,
+}
+
+impl Default for RingMod {
+    fn default() -> Self {
+        let mut ring_mod = Self {
+            ratio: 1.0,
+            glide_coefficient: 1.0,
+            min_confidence: 0.0,
+            sample_rate: 44100.0,
+
+            target_frequency: DEFAULT_CARRIER_HZ,
+            frequency: DEFAULT_CARRIER_HZ,
+            phase: 0.0,
+        };
+        ring_mod.set_settings(&RingModSettings::default(), 44100.0);
+
+        ring_mod
+    }
+}
+
+impl RingMod {
+    pub fn set_settings(&mut self, settings: &RingModSettings, sample_rate: f32) {
+        self.ratio = (settings.transpose_semitones / 12.0).exp2();
+        self.glide_coefficient = util::one_pole_coefficient(settings.glide_ms, sample_rate);
+        self.min_confidence = settings.min_confidence.clamp(0.0, 1.0);
+        self.sample_rate = sample_rate;
+    }
+
+    pub fn reset(&mut self) {
+        self.target_frequency = DEFAULT_CARRIER_HZ;
+        self.frequency = DEFAULT_CARRIER_HZ;
+        self.phase = 0.0;
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32, pitch: Pitch) -> f32 {
+        if pitch.confidence >= self.min_confidence && pitch.frequency_hz > 0.0 {
+            self.target_frequency = pitch.frequency_hz;
+        }
+        self.frequency += (self.target_frequency - self.frequency) * self.glide_coefficient;
+
+        let carrier = (self.phase * TAU).sin();
+        self.phase += (self.frequency * self.ratio / self.sample_rate).min(0.5);
+        self.phase -= self.phase.floor();
+
+        sample * carrier
+    }
+}
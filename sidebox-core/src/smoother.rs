@@ -0,0 +1,49 @@
+// Parameter smoothing for values that are only updated once per block
+
+/// The time it takes to get most of the way to a new target value. This matches the 50 ms
+/// smoothing used for the plugin's gain parameters.
+const SMOOTHING_TIME_MS: f32 = 50.0;
+
+/// A one-pole smoother. `Processor` receives new settings at most once per block, so without this
+/// gain changes would cause zipper noise.
+#[derive(Debug, Clone, Copy)]
+pub struct Smoother {
+    current: f32,
+    target: f32,
+    coefficient: f32,
+}
+
+impl Smoother {
+    pub fn new(value: f32) -> Self {
+        Self {
+            current: value,
+            target: value,
+            coefficient: 1.0,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.coefficient = 1.0 - (-1.0 / (SMOOTHING_TIME_MS / 1000.0 * sample_rate)).exp();
+    }
+
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    /// Jump straight to the target value.
+    pub fn reset(&mut self) {
+        self.current = self.target;
+    }
+
+    #[inline]
+    pub fn next(&mut self) -> f32 {
+        self.current += (self.target - self.current) * self.coefficient;
+        // Snap to the target once the difference is inaudible so this doesn't keep producing
+        // denormals, and so constant settings result in bit-exact gain staging
+        if (self.target - self.current).abs() < 1e-6 {
+            self.current = self.target;
+        }
+
+        self.current
+    }
+}
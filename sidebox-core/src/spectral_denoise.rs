@@ -0,0 +1,163 @@
+// Spectral denoising with the sidechain as the noise reference, for example an ambient mic. The
+// sidechain's spectrum is averaged into a noise profile that's continuously updated, and every bin
+// of the main signal is attenuated based on how far it rises above that profile.
+
+use crate::stft::{Stft, HOP_SIZE, NUM_BINS};
+use crate::util;
+
+/// The widest the gains are averaged over with full musical noise suppression, in bins on either
+/// side of every bin.
+const MAX_SPREAD_BINS: f32 = 4.0;
+/// How slowly the gains change with full musical noise suppression.
+const MAX_GAIN_SMOOTHING_MS: f32 = 50.0;
+
+/// Power spectral subtraction. The noise profile's power is subtracted from every bin.
+pub const METHOD_SUBTRACTION: i32 = 0;
+/// Wiener filtering. Every bin is scaled by its estimated signal-to-noise ratio, which attenuates
+/// bins close to the noise floor more gently than subtraction.
+pub const METHOD_WIENER: i32 = 1;
+
+pub const METHOD_NAMES: [&str; 2] = ["Subtraction", "Wiener"];
+
+pub fn method_name(method: i32) -> &'static str {
+    usize::try_from(method)
+        .ok()
+        .and_then(|idx| METHOD_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a method number or a (case insensitive) name.
+pub fn method_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(method) if (0..METHOD_NAMES.len() as i32).contains(&method) => Some(method),
+        Ok(_) => None,
+        Err(_) => METHOD_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectralDenoiseSettings {
+    /// One of the `METHOD_*` constants.
+    pub method: i32,
+    /// The most any bin is attenuated by, in decibels. Leaving some of the noise in sounds more
+    /// natural than removing all of it.
+    pub reduction_db: f32,
+    /// How quickly the noise profile follows the sidechain. The sidechain's input gain can be used
+    /// to match the reference's level to the noise in the main signal.
+    pub smoothing_ms: f32,
+    /// Smooths the gains over time and frequency, in `[0, 1]`. Without this, noise that briefly
+    /// rises above the profile in random bins turns into chirping tones.
+    pub musical_noise: f32,
+}
+
+impl Default for SpectralDenoiseSettings {
+    fn default() -> Self {
+        Self {
+            method: METHOD_WIENER,
+            reduction_db: 18.0,
+            smoothing_ms: 200.0,
+            musical_noise: 0.5,
+        }
+    }
+}
+
+/// Spectral denoising for a single channel.
+#[derive(Debug, Clone, Default)]
+pub struct SpectralDenoise {
+    stft: Stft,
+
+    method: i32,
+    floor: f32,
+    noise_coefficient: f32,
+    spread_bins: usize,
+    gain_coefficient: f32,
+
+    /// The sidechain's smoothed power spectrum.
+    noise_powers: Vec<f32>,
+    /// Every bin's gain for the current frame, before smoothing.
+    gains: Vec<f32>,
+    /// Running sums over `gains` for the smoothing over frequency.
+    gain_sums: Vec<f32>,
+    /// The gains that are applied, smoothed over time.
+    smoothed_gains: Vec<f32>,
+}
+
+impl SpectralDenoise {
+    /// Allocate the buffers. This needs to be called before the first call to `set_settings()`.
+    pub fn prepare(&mut self) {
+        self.stft.prepare();
+        self.noise_powers.resize(NUM_BINS, 0.0);
+        self.gains.resize(NUM_BINS, 1.0);
+        self.gain_sums.resize(NUM_BINS + 1, 0.0);
+        self.smoothed_gains.resize(NUM_BINS, 1.0);
+    }
+
+    pub fn set_settings(&mut self, settings: &SpectralDenoiseSettings, sample_rate: f32) {
+        let musical_noise = settings.musical_noise.clamp(0.0, 1.0);
+
+        self.method = settings.method;
+        self.floor = util::db_to_gain(-settings.reduction_db.max(0.0));
+
+        // Everything is only updated once per frame
+        let frame_rate = sample_rate / HOP_SIZE as f32;
+        self.noise_coefficient = util::one_pole_coefficient(settings.smoothing_ms, frame_rate);
+        self.spread_bins = (musical_noise * MAX_SPREAD_BINS).round() as usize;
+        self.gain_coefficient =
+            util::one_pole_coefficient(musical_noise * MAX_GAIN_SMOOTHING_MS, frame_rate);
+    }
+
+    pub fn reset(&mut self) {
+        self.stft.reset();
+        self.noise_powers.fill(0.0);
+        self.smoothed_gains.fill(1.0);
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        self.stft.process(sample, key, |main, side| {
+            for (((gain, noise_power), bin), side_bin) in self
+                .gains
+                .iter_mut()
+                .zip(&mut self.noise_powers)
+                .zip(main.iter())
+                .zip(side)
+            {
+                *noise_power += (side_bin.norm_sqr() - *noise_power) * self.noise_coefficient;
+
+                let power = bin.norm_sqr();
+                let unfloored_gain = if *noise_power <= 0.0 {
+                    1.0
+                } else if self.method == METHOD_WIENER {
+                    // The maximum likelihood estimate of the signal-to-noise ratio
+                    let snr = (power / *noise_power - 1.0).max(0.0);
+                    snr / (1.0 + snr)
+                } else {
+                    (1.0 - *noise_power / power).max(0.0).sqrt()
+                };
+                *gain = unfloored_gain.max(self.floor);
+            }
+
+            self.gain_sums[0] = 0.0;
+            for (bin_idx, gain) in self.gains.iter().enumerate() {
+                self.gain_sums[bin_idx + 1] = self.gain_sums[bin_idx] + gain;
+            }
+
+            for (bin_idx, (bin, smoothed_gain)) in
+                main.iter_mut().zip(&mut self.smoothed_gains).enumerate()
+            {
+                let low = bin_idx.saturating_sub(self.spread_bins);
+                let high = (bin_idx + self.spread_bins).min(NUM_BINS - 1);
+                let gain =
+                    (self.gain_sums[high + 1] - self.gain_sums[low]) / (high - low + 1) as f32;
+
+                *smoothed_gain += (gain - *smoothed_gain) * self.gain_coefficient;
+                *bin *= *smoothed_gain;
+            }
+        })
+    }
+}
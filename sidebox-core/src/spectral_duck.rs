@@ -0,0 +1,138 @@
+// Spectral ducking. The main signal is only attenuated in the frequency bins where the sidechain
+// has energy, which leaves room for the sidechain without ducking the entire main signal.
+
+use crate::stft::{self, Stft, HOP_SIZE, NUM_BINS};
+use crate::util;
+
+/// Sidechain levels at or below this don't cause any attenuation. Levels between this and 0 dBFS
+/// scale the attenuation from nothing up to the full depth.
+const FLOOR_DB: f32 = -60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectralDuckSettings {
+    /// The attenuation in bins where the sidechain is at full scale, in decibels.
+    pub depth_db: f32,
+    /// Bins outside of this range are left alone.
+    pub low_hz: f32,
+    pub high_hz: f32,
+    /// How quickly every bin's attenuation follows the sidechain.
+    pub attack_ms: f32,
+    pub release_ms: f32,
+    /// The width of the band the sidechain's spectrum is averaged over for every bin, in octaves.
+    /// Without any smoothing the attenuation follows individual harmonics.
+    pub smoothing_octaves: f32,
+}
+
+impl Default for SpectralDuckSettings {
+    fn default() -> Self {
+        Self {
+            depth_db: 12.0,
+            low_hz: 20.0,
+            high_hz: 20000.0,
+            attack_ms: 10.0,
+            release_ms: 150.0,
+            smoothing_octaves: 0.33,
+        }
+    }
+}
+
+/// Spectral ducking for a single channel.
+#[derive(Debug, Clone, Default)]
+pub struct SpectralDuck {
+    stft: Stft,
+
+    depth_db: f32,
+    low_bin: usize,
+    high_bin: usize,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    /// For every bin, the range of bins its smoothed sidechain level is averaged over.
+    smoothing_ranges: Vec<(usize, usize)>,
+
+    /// The sidechain's magnitude spectrum, as amplitudes relative to full scale.
+    side_levels: Vec<f32>,
+    /// Running sums over `side_levels` for the smoothing.
+    side_level_sums: Vec<f32>,
+    /// The smoothed level every bin's attenuation is based on.
+    envelopes: Vec<f32>,
+}
+
+impl SpectralDuck {
+    /// Allocate the buffers. This needs to be called before the first call to `set_settings()`.
+    pub fn prepare(&mut self) {
+        self.stft.prepare();
+        self.smoothing_ranges.resize(NUM_BINS, (0, 0));
+        self.side_levels.resize(NUM_BINS, 0.0);
+        self.side_level_sums.resize(NUM_BINS + 1, 0.0);
+        self.envelopes.resize(NUM_BINS, 0.0);
+    }
+
+    pub fn set_settings(&mut self, settings: &SpectralDuckSettings, sample_rate: f32) {
+        let bin_for = |frequency: f32| {
+            ((frequency / stft::bin_frequency(1, sample_rate)).round() as usize).min(NUM_BINS - 1)
+        };
+
+        self.depth_db = settings.depth_db.max(0.0);
+        self.low_bin = bin_for(settings.low_hz.max(0.0));
+        self.high_bin = bin_for(settings.high_hz.max(settings.low_hz));
+
+        // The envelopes are only updated once per frame
+        let frame_rate = sample_rate / HOP_SIZE as f32;
+        self.attack_coefficient = util::one_pole_coefficient(settings.attack_ms, frame_rate);
+        self.release_coefficient = util::one_pole_coefficient(settings.release_ms, frame_rate);
+
+        let half_width = (settings.smoothing_octaves.max(0.0) / 2.0).exp2();
+        for (bin_idx, range) in self.smoothing_ranges.iter_mut().enumerate() {
+            let low = (bin_idx as f32 / half_width).floor() as usize;
+            let high = ((bin_idx as f32 * half_width).ceil() as usize).min(NUM_BINS - 1);
+            *range = (low.min(bin_idx), high.max(bin_idx));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.stft.reset();
+        self.envelopes.fill(0.0);
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        let full_scale = self.stft.full_scale_magnitude();
+        self.stft.process(sample, key, |main, side| {
+            for (level, bin) in self.side_levels.iter_mut().zip(side) {
+                *level = bin.norm() / full_scale;
+            }
+
+            self.side_level_sums[0] = 0.0;
+            for (bin_idx, level) in self.side_levels.iter().enumerate() {
+                self.side_level_sums[bin_idx + 1] = self.side_level_sums[bin_idx] + level;
+            }
+
+            let bins = self.low_bin..=self.high_bin;
+            for ((bin, envelope), &(low, high)) in main[bins.clone()]
+                .iter_mut()
+                .zip(&mut self.envelopes[bins.clone()])
+                .zip(&self.smoothing_ranges[bins])
+            {
+                let level = (self.side_level_sums[high + 1] - self.side_level_sums[low])
+                    / (high - low + 1) as f32;
+
+                let coefficient = if level > *envelope {
+                    self.attack_coefficient
+                } else {
+                    self.release_coefficient
+                };
+                *envelope += (level - *envelope) * coefficient;
+
+                *bin *= gain(*envelope, self.depth_db);
+            }
+        })
+    }
+}
+
+/// The gain for a bin with a sidechain level of `level`.
+#[inline]
+fn gain(level: f32, depth_db: f32) -> f32 {
+    let amount = ((util::gain_to_db(level) - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+
+    util::db_to_gain(-depth_db * amount)
+}
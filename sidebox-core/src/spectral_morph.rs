@@ -0,0 +1,166 @@
+// Spectral morphing. The main signal's spectrum is interpolated towards the sidechain's, with the
+// magnitudes interpolated in the log domain and the phases interpolated after unwrapping them.
+
+use std::f32::consts::{PI, TAU};
+
+use rustfft::num_complex::Complex;
+
+use crate::smoother::Smoother;
+use crate::stft::{Stft, HOP_SIZE, NUM_BINS};
+
+/// Both the magnitudes and the phases are morphed.
+pub const MORPH_BOTH: i32 = 0;
+/// Only the magnitudes are morphed, the phases are the main signal's. This imposes the sidechain's
+/// spectral envelope on the main signal.
+pub const MORPH_MAGNITUDE: i32 = 1;
+/// Only the phases are morphed, the magnitudes are the main signal's.
+pub const MORPH_PHASE: i32 = 2;
+
+pub const MORPH_NAMES: [&str; 3] = ["Magnitude and phase", "Magnitude", "Phase"];
+
+pub fn morph_name(morph: i32) -> &'static str {
+    usize::try_from(morph)
+        .ok()
+        .and_then(|idx| MORPH_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a morph number or a (case insensitive) name.
+pub fn morph_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(morph) if (0..MORPH_NAMES.len() as i32).contains(&morph) => Some(morph),
+        Ok(_) => None,
+        Err(_) => MORPH_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpectralMorphSettings {
+    /// How far the main signal is morphed towards the sidechain, in `[0, 1]`. At 0 the main signal
+    /// passes through, at 1 the morphed parts come entirely from the sidechain.
+    pub amount: f32,
+    /// One of the `MORPH_*` constants.
+    pub morph: i32,
+}
+
+impl Default for SpectralMorphSettings {
+    fn default() -> Self {
+        Self {
+            amount: 0.5,
+            morph: MORPH_BOTH,
+        }
+    }
+}
+
+/// Spectral morphing for a single channel.
+#[derive(Debug, Clone)]
+pub struct SpectralMorph {
+    stft: Stft,
+
+    morph: i32,
+    /// Updated once per frame, so automating the amount doesn't cause jumps.
+    amount: Smoother,
+
+    /// The phase difference between the sidechain and the main signal in every bin, from the
+    /// previous frame.
+    previous_differences: Vec<f32>,
+    /// The phase added to the main signal's phase in every bin, wrapped to `[-pi, pi]`.
+    phase_offsets: Vec<f32>,
+}
+
+impl Default for SpectralMorph {
+    fn default() -> Self {
+        Self {
+            stft: Stft::default(),
+            morph: MORPH_BOTH,
+            amount: Smoother::new(0.0),
+            previous_differences: Vec::new(),
+            phase_offsets: Vec::new(),
+        }
+    }
+}
+
+impl SpectralMorph {
+    /// Allocate the buffers. This needs to be called before the first call to `set_settings()`.
+    pub fn prepare(&mut self) {
+        self.stft.prepare();
+        self.previous_differences.resize(NUM_BINS, 0.0);
+        self.phase_offsets.resize(NUM_BINS, 0.0);
+    }
+
+    pub fn set_settings(&mut self, settings: &SpectralMorphSettings, sample_rate: f32) {
+        self.morph = settings.morph;
+        self.amount.set_sample_rate(sample_rate / HOP_SIZE as f32);
+        self.amount.set_target(settings.amount.clamp(0.0, 1.0));
+    }
+
+    pub fn reset(&mut self) {
+        self.stft.reset();
+        self.amount.reset();
+        self.previous_differences.fill(0.0);
+        self.phase_offsets.fill(0.0);
+    }
+
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32) -> f32 {
+        self.stft.process(sample, key, |main, side| {
+            let amount = self.amount.next();
+            let morph_magnitude = self.morph != MORPH_PHASE;
+            let morph_phase = self.morph != MORPH_MAGNITUDE;
+
+            for (((bin, side_bin), previous_difference), phase_offset) in main
+                .iter_mut()
+                .zip(side)
+                .zip(&mut self.previous_differences)
+                .zip(&mut self.phase_offsets)
+            {
+                let (main_magnitude, main_phase) = bin.to_polar();
+                let (side_magnitude, side_phase) = side_bin.to_polar();
+
+                // The phase difference is unwrapped over time by accumulating how much it changed
+                // since the last frame. Accumulating a share of that change instead of scaling the
+                // difference itself means changing the amount never makes the phase jump.
+                let difference = wrap_phase(side_phase - main_phase);
+                let change = wrap_phase(difference - *previous_difference);
+                *previous_difference = difference;
+                *phase_offset = wrap_phase(*phase_offset + change * amount);
+                // At the ends of the range the phases are exactly the main or the sidechain's
+                if amount == 0.0 {
+                    *phase_offset = 0.0;
+                } else if amount == 1.0 {
+                    *phase_offset = difference;
+                }
+
+                if amount == 0.0 {
+                    continue;
+                }
+
+                // This is a linear interpolation between the log magnitudes. `powf()` returns 1
+                // for a zero base with a zero exponent, so the ends of the range are exact.
+                let magnitude = if morph_magnitude {
+                    main_magnitude.powf(1.0 - amount) * side_magnitude.powf(amount)
+                } else {
+                    main_magnitude
+                };
+                let phase = if morph_phase {
+                    main_phase + *phase_offset
+                } else {
+                    main_phase
+                };
+
+                *bin = Complex::from_polar(magnitude, phase);
+            }
+        })
+    }
+}
+
+/// Wrap a phase to `[-pi, pi]`.
+#[inline]
+fn wrap_phase(phase: f32) -> f32 {
+    phase - TAU * ((phase + PI) / TAU).floor()
+}
@@ -0,0 +1,165 @@
+// Short-time Fourier transform with overlap-add resynthesis, shared by the spectral modes
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+/// The window size. This is also the latency of the spectral modes.
+pub const FFT_SIZE: usize = 2048;
+/// The number of bins passed to the spectral processing functions, from DC up to Nyquist.
+pub const NUM_BINS: usize = FFT_SIZE / 2 + 1;
+/// Frames overlap by 75%.
+pub const HOP_SIZE: usize = FFT_SIZE / 4;
+
+/// Runs a main and a sidechain signal through an STFT one sample at a time. Every `HOP_SIZE`
+/// samples both spectra are passed to a function that modifies the main spectrum in place, which is
+/// then resynthesized with overlap-add. The output is delayed by `FFT_SIZE` samples.
+///
+/// The buffers are allocated in `prepare()`, after that nothing allocates.
+#[derive(Clone, Default)]
+pub struct Stft {
+    fft: Option<Arc<dyn Fft<f32>>>,
+    ifft: Option<Arc<dyn Fft<f32>>>,
+    /// A square root Hann window, used for both analysis and synthesis.
+    window: Vec<f32>,
+    full_scale_magnitude: f32,
+
+    main_input: Vec<f32>,
+    side_input: Vec<f32>,
+    output: Vec<f32>,
+    /// The position in the circular input and output buffers.
+    pos: usize,
+    samples_until_frame: usize,
+
+    main_spectrum: Vec<Complex<f32>>,
+    side_spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl fmt::Debug for Stft {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stft")
+            .field("pos", &self.pos)
+            .field("samples_until_frame", &self.samples_until_frame)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Stft {
+    /// Plan the FFTs and allocate the buffers.
+    pub fn prepare(&mut self) {
+        if self.fft.is_some() {
+            return;
+        }
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(FFT_SIZE);
+        let ifft = planner.plan_fft_inverse(FFT_SIZE);
+        let scratch_len = fft
+            .get_inplace_scratch_len()
+            .max(ifft.get_inplace_scratch_len());
+
+        self.window = (0..FFT_SIZE)
+            .map(|idx| (0.5 - 0.5 * (2.0 * PI * idx as f32 / FFT_SIZE as f32).cos()).sqrt())
+            .collect();
+        // A real sine's energy is split between the positive and negative frequencies
+        self.full_scale_magnitude = self.window.iter().sum::<f32>() / 2.0;
+        self.main_input = vec![0.0; FFT_SIZE];
+        self.side_input = vec![0.0; FFT_SIZE];
+        self.output = vec![0.0; FFT_SIZE];
+        self.main_spectrum = vec![Complex::default(); FFT_SIZE];
+        self.side_spectrum = vec![Complex::default(); FFT_SIZE];
+        self.scratch = vec![Complex::default(); scratch_len];
+        self.fft = Some(fft);
+        self.ifft = Some(ifft);
+        self.reset();
+    }
+
+    /// The magnitude a full scale sine wave has in its bin, used to express bin magnitudes as
+    /// amplitudes.
+    pub fn full_scale_magnitude(&self) -> f32 {
+        self.full_scale_magnitude
+    }
+
+    pub fn reset(&mut self) {
+        self.main_input.fill(0.0);
+        self.side_input.fill(0.0);
+        self.output.fill(0.0);
+        self.pos = 0;
+        self.samples_until_frame = HOP_SIZE;
+    }
+
+    /// Process a single sample. `process_frame` receives the main and sidechain spectra's first
+    /// `NUM_BINS` bins, and only needs to modify the main spectrum's bins in that range. The upper
+    /// half of the spectrum is mirrored from those afterwards.
+    #[inline]
+    pub fn process(
+        &mut self,
+        main: f32,
+        side: f32,
+        mut process_frame: impl FnMut(&mut [Complex<f32>], &[Complex<f32>]),
+    ) -> f32 {
+        self.main_input[self.pos] = main;
+        self.side_input[self.pos] = side;
+        let output = std::mem::take(&mut self.output[self.pos]);
+        self.pos = (self.pos + 1) % FFT_SIZE;
+
+        self.samples_until_frame -= 1;
+        if self.samples_until_frame == 0 {
+            self.samples_until_frame = HOP_SIZE;
+            self.process_frame(&mut process_frame);
+        }
+
+        output
+    }
+
+    fn process_frame(
+        &mut self,
+        process_frame: &mut impl FnMut(&mut [Complex<f32>], &[Complex<f32>]),
+    ) {
+        let (Some(fft), Some(ifft)) = (&self.fft, &self.ifft) else {
+            return;
+        };
+
+        // `self.pos` now points at the oldest sample
+        for idx in 0..FFT_SIZE {
+            let buffer_idx = (self.pos + idx) % FFT_SIZE;
+            self.main_spectrum[idx] =
+                Complex::new(self.main_input[buffer_idx] * self.window[idx], 0.0);
+            self.side_spectrum[idx] =
+                Complex::new(self.side_input[buffer_idx] * self.window[idx], 0.0);
+        }
+        fft.process_with_scratch(&mut self.main_spectrum, &mut self.scratch);
+        fft.process_with_scratch(&mut self.side_spectrum, &mut self.scratch);
+
+        process_frame(
+            &mut self.main_spectrum[..NUM_BINS],
+            &self.side_spectrum[..NUM_BINS],
+        );
+
+        // The input was real, so the output needs to be as well
+        self.main_spectrum[0].im = 0.0;
+        self.main_spectrum[NUM_BINS - 1].im = 0.0;
+        for bin_idx in 1..NUM_BINS - 1 {
+            self.main_spectrum[FFT_SIZE - bin_idx] = self.main_spectrum[bin_idx].conj();
+        }
+        ifft.process_with_scratch(&mut self.main_spectrum, &mut self.scratch);
+
+        // The squared windows of four overlapping frames sum to 2, and the inverse FFT is scaled by
+        // the FFT size
+        let normalization = 1.0 / (FFT_SIZE as f32 * 2.0);
+        for idx in 0..FFT_SIZE {
+            let buffer_idx = (self.pos + idx) % FFT_SIZE;
+            self.output[buffer_idx] +=
+                self.main_spectrum[idx].re * self.window[idx] * normalization;
+        }
+    }
+}
+
+/// The center frequency of a bin.
+pub fn bin_frequency(bin_idx: usize, sample_rate: f32) -> f32 {
+    bin_idx as f32 * sample_rate / FFT_SIZE as f32
+}
@@ -0,0 +1,278 @@
+// Sidechain-triggered stutter. A transient on the sidechain captures a slice of the main signal and
+// loops it until the next transient, or until the sidechain falls silent.
+
+use crate::buffer::RingBuffer;
+use crate::{ghost, util, Transport};
+
+/// The longest loop, also when the loop length is synced to a slow tempo.
+pub const MAX_LENGTH_MS: f32 = 4000.0;
+/// The detector's peak envelope falls at this rate, like the gate's.
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+/// After triggering, the sidechain's level needs to fall this far below the threshold before the
+/// next transient can trigger a new loop.
+const REARM_HYSTERESIS_DB: f32 = 6.0;
+/// The sidechain needs to stay below the release threshold for this long before the loop is
+/// released, so short gaps don't end the loop.
+const RELEASE_HOLD_MS: f32 = 50.0;
+
+/// A new loop is captured when the sidechain's level rises above the threshold.
+pub const TRIGGER_LEVEL: i32 = 0;
+/// A new loop is captured at every onset found by the shared onset detector, see
+/// `Settings::onset`.
+pub const TRIGGER_ONSET: i32 = 1;
+
+pub const TRIGGER_NAMES: [&str; 2] = ["Level", "Onset"];
+
+pub fn trigger_name(trigger: i32) -> &'static str {
+    usize::try_from(trigger)
+        .ok()
+        .and_then(|idx| TRIGGER_NAMES.get(idx))
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+/// Parse either a trigger number or a (case insensitive) name.
+pub fn trigger_from_name(name: &str) -> Option<i32> {
+    let name = name.trim();
+    match name.parse::<i32>() {
+        Ok(trigger) if (0..TRIGGER_NAMES.len() as i32).contains(&trigger) => Some(trigger),
+        Ok(_) => None,
+        Err(_) => TRIGGER_NAMES
+            .iter()
+            .position(|candidate| candidate.eq_ignore_ascii_case(name))
+            .map(|idx| idx as i32),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StutterSettings {
+    /// One of the `TRIGGER_*` constants.
+    pub trigger: i32,
+    /// With `TRIGGER_LEVEL`, a new loop is captured when the sidechain's level rises above this
+    /// threshold.
+    pub threshold_db: f32,
+    /// Whether the loop length follows `division` instead of `length_ms`.
+    pub sync: bool,
+    pub length_ms: f32,
+    /// An index into `ghost::DIVISIONS`.
+    pub division: i32,
+    /// How long the loop's seam, and the transitions into and out of a loop, are faded over.
+    pub crossfade_ms: f32,
+    /// The loop is released and the main signal passes through again once the sidechain's level
+    /// stays below this threshold.
+    pub release_db: f32,
+}
+
+impl Default for StutterSettings {
+    fn default() -> Self {
+        Self {
+            trigger: TRIGGER_LEVEL,
+            threshold_db: -20.0,
+            sync: false,
+            length_ms: 125.0,
+            // 1/16
+            division: 6,
+            crossfade_ms: 5.0,
+            release_db: -50.0,
+        }
+    }
+}
+
+/// A stutter for a single channel.
+#[derive(Debug, Clone)]
+pub struct Stutter {
+    trigger: i32,
+    threshold: f32,
+    rearm_threshold: f32,
+    release_threshold: f32,
+    detector_coefficient: f32,
+    release_hold_samples: usize,
+    sync: bool,
+    length_ms: f32,
+    division: i32,
+    crossfade_samples: usize,
+    sample_rate: f32,
+    beats_per_sample: f64,
+    bar_beats: f64,
+
+    buffer: RingBuffer,
+    /// The key signal's peak envelope.
+    level: f32,
+    /// Whether the next transient may trigger a new loop.
+    armed: bool,
+    /// How long the key has been below the release threshold.
+    silent_samples: usize,
+    looping: bool,
+    /// The loop's share in the output, faded in and out over the crossfade time.
+    wet: f32,
+    /// When a new loop interrupts the previous one, the previous loop keeps playing from here while
+    /// it gets faded out.
+    tail_pos: usize,
+    tail_len: usize,
+    tail_remaining: usize,
+}
+
+impl Default for Stutter {
+    fn default() -> Self {
+        let mut stutter = Self {
+            trigger: TRIGGER_LEVEL,
+            threshold: 0.0,
+            rearm_threshold: 0.0,
+            release_threshold: 0.0,
+            detector_coefficient: 1.0,
+            release_hold_samples: 0,
+            sync: false,
+            length_ms: 0.0,
+            division: 0,
+            crossfade_samples: 0,
+            sample_rate: 44100.0,
+            beats_per_sample: 0.0,
+            bar_beats: ghost::DEFAULT_BAR_BEATS,
+
+            buffer: RingBuffer::default(),
+            level: 0.0,
+            armed: true,
+            silent_samples: 0,
+            looping: false,
+            wet: 0.0,
+            tail_pos: 0,
+            tail_len: 1,
+            tail_remaining: 0,
+        };
+        stutter.set_settings(&StutterSettings::default(), 44100.0);
+        stutter.set_transport(&Transport::default(), 44100.0);
+
+        stutter
+    }
+}
+
+impl Stutter {
+    /// Allocate the loop buffer for a sample rate.
+    pub fn prepare(&mut self, sample_rate: f32) {
+        self.buffer
+            .resize(util::ms_to_samples(MAX_LENGTH_MS, sample_rate));
+    }
+
+    pub fn set_settings(&mut self, settings: &StutterSettings, sample_rate: f32) {
+        self.trigger = settings.trigger;
+        self.threshold = util::db_to_gain(settings.threshold_db);
+        self.rearm_threshold = util::db_to_gain(settings.threshold_db - REARM_HYSTERESIS_DB);
+        // A release threshold above the trigger threshold would release loops right after they
+        // start
+        self.release_threshold = if settings.trigger == TRIGGER_ONSET {
+            util::db_to_gain(settings.release_db)
+        } else {
+            util::db_to_gain(settings.release_db.min(settings.threshold_db))
+        };
+        self.detector_coefficient = util::one_pole_coefficient(DETECTOR_RELEASE_MS, sample_rate);
+        self.release_hold_samples = util::ms_to_samples(RELEASE_HOLD_MS, sample_rate);
+        self.sync = settings.sync;
+        self.length_ms = settings.length_ms;
+        self.division = settings.division;
+        self.crossfade_samples = util::ms_to_samples(settings.crossfade_ms, sample_rate);
+        self.sample_rate = sample_rate;
+    }
+
+    /// Called at the start of every block with the host's transport information, for the synced
+    /// loop lengths.
+    pub fn set_transport(&mut self, transport: &Transport, sample_rate: f32) {
+        self.beats_per_sample = ghost::beats_per_sample(transport, sample_rate);
+        if let Some(bar_beats) = ghost::bar_beats(transport) {
+            self.bar_beats = bar_beats;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer.reset();
+        self.level = 0.0;
+        self.armed = true;
+        self.silent_samples = 0;
+        self.looping = false;
+        self.wet = 0.0;
+        self.tail_remaining = 0;
+    }
+
+    /// The loop length for the current settings and tempo, in samples.
+    fn loop_length(&self) -> usize {
+        let length = if self.sync {
+            (ghost::division_beats(self.division, self.bar_beats) / self.beats_per_sample).round()
+                as usize
+        } else {
+            util::ms_to_samples(self.length_ms, self.sample_rate)
+        };
+
+        length.max(1)
+    }
+
+    fn start_loop(&mut self) {
+        // Whatever was playing before gets faded out instead of cutting off
+        if self.wet > 0.0 && self.crossfade_samples > 0 {
+            self.tail_pos = self.buffer.position();
+            self.tail_len = self.buffer.loop_len();
+            self.tail_remaining = self.crossfade_samples;
+        }
+
+        self.buffer
+            .prepare_playback(self.loop_length(), self.crossfade_samples);
+        self.looping = true;
+        self.silent_samples = 0;
+    }
+
+    /// Loop `sample` based on the transients in `key`, or on `onset` when triggering on onsets.
+    /// Lookahead is handled by the processor, which delays `sample` but not `key`. The processor
+    /// delays the onsets to line up with `sample`.
+    #[inline]
+    pub fn process(&mut self, sample: f32, key: f32, onset: bool) -> f32 {
+        let rectified = key.abs();
+        if rectified > self.level {
+            self.level = rectified;
+        } else {
+            self.level += (rectified - self.level) * self.detector_coefficient;
+        }
+
+        if self.trigger == TRIGGER_ONSET {
+            if onset {
+                self.start_loop();
+            }
+        } else if self.armed && self.level >= self.threshold {
+            self.armed = false;
+            self.start_loop();
+        } else if !self.armed && self.level < self.rearm_threshold {
+            self.armed = true;
+        }
+
+        if self.level < self.release_threshold {
+            self.silent_samples = self.silent_samples.saturating_add(1);
+            if self.silent_samples >= self.release_hold_samples {
+                self.looping = false;
+            }
+        } else {
+            self.silent_samples = 0;
+        }
+
+        let target = if self.looping { 1.0 } else { 0.0 };
+        if self.crossfade_samples == 0 {
+            self.wet = target;
+        } else {
+            let step = 1.0 / self.crossfade_samples as f32;
+            self.wet = (self.wet + (target - self.wet).clamp(-step, step)).clamp(0.0, 1.0);
+        }
+        if self.wet == 0.0 {
+            return sample;
+        }
+
+        // The tail is read before the new loop overwrites it
+        let looped = if self.tail_remaining > 0 {
+            let tail = self.buffer.sample_at(self.tail_pos);
+            let t = self.tail_remaining as f32 / (self.crossfade_samples + 1) as f32;
+            self.tail_pos = (self.tail_pos + 1) % self.tail_len;
+            self.tail_remaining -= 1;
+
+            tail * t + self.buffer.next_sample(sample) * (1.0 - t)
+        } else {
+            self.buffer.next_sample(sample)
+        };
+
+        looped * self.wet + sample * (1.0 - self.wet)
+    }
+}
@@ -0,0 +1,88 @@
+// A state variable filter, used for the crossover and any other filtering the modes need
+
+use std::f32::consts::PI;
+
+/// The Q of a second order Butterworth filter.
+pub const BUTTERWORTH_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// The highest cutoff frequency relative to the sample rate. The filter's prewarping breaks down
+/// when getting too close to Nyquist.
+const MAX_RELATIVE_FREQUENCY: f32 = 0.49;
+
+/// The low-pass, band-pass, and high-pass outputs for a single input sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SvfOutputs {
+    pub low: f32,
+    pub band: f32,
+    pub high: f32,
+}
+
+/// A second order trapezoidal state variable filter that computes all three responses at once. The
+/// coefficients can be changed while processing without blowing up.
+#[derive(Debug, Clone, Copy)]
+pub struct Svf {
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl Default for Svf {
+    fn default() -> Self {
+        let mut svf = Self {
+            k: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        };
+        svf.set(1000.0, BUTTERWORTH_Q, 44100.0);
+
+        svf
+    }
+}
+
+impl Svf {
+    pub fn set(&mut self, frequency: f32, q: f32, sample_rate: f32) {
+        let frequency = frequency.clamp(1.0, sample_rate * MAX_RELATIVE_FREQUENCY);
+        let g = (PI * frequency / sample_rate).tan();
+
+        self.k = 1.0 / q.max(0.01);
+        self.a1 = 1.0 / (1.0 + g * (g + self.k));
+        self.a2 = g * self.a1;
+        self.a3 = g * self.a2;
+    }
+
+    pub fn reset(&mut self) {
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+
+    #[inline]
+    pub fn process(&mut self, input: f32) -> SvfOutputs {
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        SvfOutputs {
+            low: v2,
+            band: v1,
+            high: input - self.k * v1 - v2,
+        }
+    }
+
+    /// The all-pass response, `low - k * band + high`.
+    #[inline]
+    pub fn process_all_pass(&mut self, input: f32) -> f32 {
+        let outputs = self.process(input);
+
+        input - 2.0 * self.k * outputs.band
+    }
+}
@@ -0,0 +1,24 @@
+// Small conversions shared by the modes
+
+pub fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+/// Converts a linear gain to decibels. Silence is clamped to -100 dB instead of negative infinity.
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.max(1e-5).log10()
+}
+
+pub fn ms_to_samples(ms: f32, sample_rate: f32) -> usize {
+    (ms.max(0.0) / 1000.0 * sample_rate).round() as usize
+}
+
+/// The coefficient for a one-pole filter `y += (x - y) * coefficient` that gets 63% of the way to
+/// a new value in `ms` milliseconds. A time of zero results in no smoothing at all.
+pub fn one_pole_coefficient(ms: f32, sample_rate: f32) -> f32 {
+    if ms <= 0.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / (ms / 1000.0 * sample_rate)).exp()
+    }
+}
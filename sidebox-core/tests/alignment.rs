@@ -0,0 +1,283 @@
+// The addition mode should line up the main signal and the sidechain, either by the offset it
+// learned or by a manual offset
+
+mod common;
+
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use common::{noise, render, with_mode, Stereo, BLOCK_SIZE, SAMPLE_RATE};
+use sidebox_core::alignment::{self, AlignmentSettings};
+use sidebox_core::stft::FFT_SIZE;
+use sidebox_core::{mode, Processor, Settings};
+
+/// Learning the offset takes longer than most tests run for.
+const LEN: usize = 96000;
+/// The learned offset and the delays have settled well before this point.
+const SETTLED: usize = LEN / 2;
+
+/// `signal` delayed by a whole number of samples.
+fn delay(signal: &Stereo, samples: usize) -> Stereo {
+    signal.clone().map(|channel| {
+        (0..channel.len())
+            .map(|idx| idx.checked_sub(samples).map_or(0.0, |idx| channel[idx]))
+            .collect()
+    })
+}
+
+fn samples_to_ms(samples: f32) -> f32 {
+    samples / SAMPLE_RATE * 1000.0
+}
+
+/// Learn the alignment while rendering, and return the latency compensated output along with the
+/// learned offset in samples.
+fn learn(main: &Stereo, side: &Stereo) -> (Stereo, f32) {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(Settings {
+        alignment: AlignmentSettings {
+            learn: true,
+            ..AlignmentSettings::default()
+        },
+        ..with_mode(mode::ADDITION)
+    });
+    processor.reset();
+
+    // The latency can grow with the learned offset, so the inputs are padded by the most it can be
+    // and the output is shifted back by the final latency like in `render()`
+    let len = LEN
+        + (processor.latency_samples() as usize).max(alignment::max_delay_samples(SAMPLE_RATE) + 1);
+    let mut output = main.clone();
+    let mut side = side.clone();
+    for channel in output.iter_mut().chain(side.iter_mut()) {
+        channel.resize(len, 0.0);
+    }
+
+    for block_start in (0..len).step_by(BLOCK_SIZE) {
+        // Learning stops halfway through, and the learned offset should stay in use
+        if block_start == SETTLED {
+            processor.set_settings(with_mode(mode::ADDITION));
+        }
+
+        let block_end = (block_start + BLOCK_SIZE).min(len);
+        let (left, right) = output.split_at_mut(1);
+        processor.process(
+            &mut [
+                &mut left[0][block_start..block_end],
+                &mut right[0][block_start..block_end],
+            ],
+            &[
+                &side[0][block_start..block_end],
+                &side[1][block_start..block_end],
+            ],
+        );
+    }
+
+    let latency = processor.latency_samples() as usize;
+    for channel in output.iter_mut() {
+        channel.drain(..latency);
+        channel.truncate(LEN);
+    }
+
+    let offset = processor.learned_alignment_ms() / 1000.0 * SAMPLE_RATE;
+    (output, offset)
+}
+
+/// The RMS level of the difference between `output` and twice `expected` within `range`, in
+/// decibels relative to `expected`'s RMS level.
+fn sum_error_db(output: &Stereo, expected: &Stereo, range: Range<usize>) -> f32 {
+    let (error, level) = output
+        .iter()
+        .zip(expected)
+        .flat_map(|(output, expected)| output[range.clone()].iter().zip(&expected[range.clone()]))
+        .fold((0.0, 0.0), |(error, level), (output, expected)| {
+            (
+                error + (output - expected * 2.0).powi(2),
+                level + expected * expected,
+            )
+        });
+
+    10.0 * (error / level).log10()
+}
+
+#[test]
+fn learns_a_late_sidechain() {
+    let main = noise(1, 0.5, LEN);
+    let side = delay(&main, 37);
+    let (output, offset) = learn(&main, &side);
+
+    assert!((offset - 37.0).abs() < 0.05, "learned {offset} samples");
+    // The sidechain is moved to line up with the main signal. Past the end of its input, the
+    // sidechain is silent.
+    let error_db = sum_error_db(&output, &main, SETTLED..LEN - 37);
+    assert!(error_db < -40.0, "the sum is off by {error_db} dB");
+}
+
+#[test]
+fn learns_a_late_main_signal() {
+    let side = noise(1, 0.5, LEN);
+    let main = delay(&side, 12);
+    let (output, offset) = learn(&main, &side);
+
+    assert!((offset + 12.0).abs() < 0.05, "learned {offset} samples");
+    let error_db = sum_error_db(&output, &main, SETTLED..LEN);
+    assert!(error_db < -40.0, "the sum is off by {error_db} dB");
+}
+
+#[test]
+fn manual_offset_moves_the_sidechain() {
+    let signal = noise(1, 0.5, LEN);
+    let late = delay(&signal, 48);
+    let offset_ms = samples_to_ms(48.0);
+
+    // A positive offset moves the sidechain earlier, a negative one moves it later
+    for (main, side, offset_ms) in [(&signal, &late, offset_ms), (&late, &signal, -offset_ms)] {
+        let output = render(
+            Settings {
+                alignment: AlignmentSettings {
+                    manual: true,
+                    offset_ms,
+                    ..AlignmentSettings::default()
+                },
+                ..with_mode(mode::ADDITION)
+            },
+            main,
+            side,
+            BLOCK_SIZE,
+        );
+
+        let error_db = sum_error_db(&output, main, 0..LEN - 48);
+        assert!(error_db < -100.0, "the sum is off by {error_db} dB");
+    }
+}
+
+#[test]
+fn manual_offset_can_be_fractional() {
+    let frequency = 1000.0;
+    let offset = 10.5;
+    let sine = |delay: f32| {
+        let channel = (0..LEN)
+            .map(|idx| (2.0 * PI * frequency * (idx as f32 - delay) / SAMPLE_RATE).sin() * 0.5)
+            .collect::<Vec<_>>();
+        [channel.clone(), channel]
+    };
+    let main = sine(0.0);
+    let output = render(
+        Settings {
+            alignment: AlignmentSettings {
+                manual: true,
+                offset_ms: samples_to_ms(offset),
+                ..AlignmentSettings::default()
+            },
+            ..with_mode(mode::ADDITION)
+        },
+        &main,
+        &sine(offset),
+        BLOCK_SIZE,
+    );
+
+    // The interpolation reaches a couple of samples past the end of the sidechain
+    let error_db = sum_error_db(&output, &main, 0..LEN - 16);
+    assert!(error_db < -60.0, "the sum is off by {error_db} dB");
+}
+
+#[test]
+fn latency_only_changes_with_the_main_signal_delay() {
+    // At this sample rate the summing modes can delay the main signal by more than the spectral
+    // modes' latency
+    let sample_rate = 192000.0;
+    let samples_to_ms = |samples: f32| samples / sample_rate * 1000.0;
+    let mut processor = Processor::new();
+    processor.prepare(sample_rate, BLOCK_SIZE);
+
+    for (offset, latency) in [
+        // The reported latency covers every mode, so without an offset that's the spectral modes'
+        (0.0, FFT_SIZE as u32),
+        // A late sidechain delays the main signal by whole samples, with a sample to spare
+        (3000.5, 3002),
+        (3072.0, 3073),
+        (1000.0, FFT_SIZE as u32),
+        // An early sidechain gets delayed instead
+        (-3072.0, FFT_SIZE as u32),
+    ] {
+        // Automating the mode should not make the host's delay compensation jump around
+        for mode in [
+            mode::ADDITION,
+            mode::SMART_SUM,
+            mode::MULTIPLICATION,
+            mode::GATE,
+        ] {
+            processor.set_settings(Settings {
+                alignment: AlignmentSettings {
+                    manual: true,
+                    offset_ms: samples_to_ms(offset),
+                    ..AlignmentSettings::default()
+                },
+                ..with_mode(mode)
+            });
+            assert_eq!(
+                processor.latency_samples(),
+                latency,
+                "mode {mode} with an offset of {offset} samples"
+            );
+        }
+    }
+
+    // The same goes for learned offsets
+    processor.set_settings(with_mode(mode::ADDITION));
+    processor.set_learned_alignment_ms(samples_to_ms(3072.0));
+    assert_eq!(processor.latency_samples(), 3073);
+}
+
+#[test]
+fn changing_the_offset_does_not_click() {
+    // Like above, at this sample rate changing the offset changes the main signal's overall delay
+    let sample_rate = 192000.0;
+    let manual_offset = |samples: f32| Settings {
+        alignment: AlignmentSettings {
+            manual: true,
+            offset_ms: samples / sample_rate * 1000.0,
+            ..AlignmentSettings::default()
+        },
+        ..with_mode(mode::ADDITION)
+    };
+    let len = LEN;
+    let change_at = BLOCK_SIZE * 200;
+    let channel = (0..len)
+        .map(|idx| (2.0 * PI * 100.0 * idx as f32 / sample_rate).sin() * 0.5)
+        .collect::<Vec<_>>();
+    let main = [channel.clone(), channel];
+
+    let mut processor = Processor::new();
+    processor.prepare(sample_rate, BLOCK_SIZE);
+    processor.set_settings(manual_offset(3000.0));
+    processor.reset();
+
+    let mut output = main.clone();
+    for block_start in (0..len).step_by(BLOCK_SIZE) {
+        if block_start == change_at {
+            processor.set_settings(manual_offset(3100.0));
+        }
+
+        let block_end = (block_start + BLOCK_SIZE).min(len);
+        let (left, right) = output.split_at_mut(1);
+        processor.process(
+            &mut [
+                &mut left[0][block_start..block_end],
+                &mut right[0][block_start..block_end],
+            ],
+            &[
+                &main[0][block_start..block_end],
+                &main[1][block_start..block_end],
+            ],
+        );
+    }
+
+    // The sine moves by less than 0.001 per sample. Jumping straight to the new delay moves the
+    // output by dozens of times that at once.
+    let max_step = output[0][change_at - BLOCK_SIZE..change_at + BLOCK_SIZE * 4]
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max);
+    assert!(max_step < 0.005, "the output jumped by {max_step}");
+}
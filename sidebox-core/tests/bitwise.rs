@@ -0,0 +1,168 @@
+// The bitwise modes should combine the quantized signals like integers would, and the dither and
+// smoothing should behave like their regular counterparts
+
+mod common;
+
+use std::f32::consts::TAU;
+
+use common::{
+    amplitude_at, noise, render, silence, sine, with_mode, Stereo, BLOCK_SIZE, LEN, SAMPLE_RATE,
+};
+use sidebox_core::bitwise::{self, BitwiseSettings};
+use sidebox_core::svf::{Svf, BUTTERWORTH_Q};
+use sidebox_core::{mode, Settings};
+
+const FREQUENCY: f32 = 440.0;
+
+/// The expected integer result for a quantized main and sidechain sample.
+type Operation = fn(i32, i32) -> i32;
+
+/// NAND's DC blocker, which starts from the first sample.
+fn block_dc(channel: Vec<f32>) -> Vec<f32> {
+    let coefficient = (-TAU * bitwise::NAND_DC_BLOCKER_HZ / SAMPLE_RATE).exp();
+    let mut previous_input = channel[0];
+    let mut output = 0.0;
+    channel
+        .into_iter()
+        .map(|input| {
+            output = input - previous_input + coefficient * output;
+            previous_input = input;
+            output
+        })
+        .collect()
+}
+
+#[test]
+fn operations_match_integer_math() {
+    let main = noise(1, 0.7, LEN);
+    let side = noise(2, 0.7, LEN);
+    let scale = 128.0;
+    let quantize = |sample: f32| (sample * scale).round().clamp(-scale, scale - 1.0) as i32;
+    let cases: [(i32, Operation); 4] = [
+        (mode::BITWISE_AND, |main, side| main & side),
+        (mode::BITWISE_OR, |main, side| main | side),
+        (mode::BITWISE_XOR, |main, side| main ^ side),
+        (mode::BITWISE_NAND, |main, side| !(main & side)),
+    ];
+
+    for (mode, operation) in cases {
+        let output = render(with_mode(mode), &main, &side, BLOCK_SIZE);
+        for channel in 0..output.len() {
+            let expected: Vec<f32> = (0..LEN)
+                .map(|idx| {
+                    operation(quantize(main[channel][idx]), quantize(side[channel][idx])) as f32
+                        / scale
+                })
+                .collect();
+            let expected = if mode == mode::BITWISE_NAND {
+                block_dc(expected)
+            } else {
+                expected
+            };
+            for idx in 0..LEN {
+                assert_eq!(
+                    output[channel][idx], expected[idx],
+                    "mode {mode}, channel {channel}, sample {idx}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn outputs_are_whole_steps_within_full_scale() {
+    // The inputs go well past full scale, and the dither pushes them around some more
+    let main = noise(1, 2.0, LEN);
+    let side = noise(2, 2.0, LEN);
+
+    for bits in [bitwise::MIN_BITS, 12, bitwise::MAX_BITS] {
+        let scale = (1 << (bits - 1)) as f32;
+        let output = render(
+            Settings {
+                bitwise: BitwiseSettings {
+                    bits,
+                    dither: bitwise::DITHER_TRIANGULAR,
+                    ..BitwiseSettings::default()
+                },
+                ..with_mode(mode::BITWISE_XOR)
+            },
+            &main,
+            &side,
+            BLOCK_SIZE,
+        );
+
+        for &sample in output.iter().flatten() {
+            let steps = sample * scale;
+            assert_eq!(
+                steps,
+                steps.round(),
+                "{sample} is between steps at {bits} bits"
+            );
+            assert!(
+                (-1.0..1.0).contains(&sample),
+                "{sample} is past full scale at {bits} bits"
+            );
+        }
+    }
+}
+
+#[test]
+fn dither_keeps_signals_below_a_step() {
+    // Half a step at four bits is 1/16, so without dither this rounds to silence
+    let main = sine(FREQUENCY, 0.05, LEN);
+    let dithered = |dither: i32| {
+        render(
+            Settings {
+                bitwise: BitwiseSettings {
+                    bits: 4,
+                    dither,
+                    ..BitwiseSettings::default()
+                },
+                ..with_mode(mode::BITWISE_OR)
+            },
+            &main,
+            &silence(LEN),
+            BLOCK_SIZE,
+        )
+    };
+
+    let output = dithered(bitwise::DITHER_NONE);
+    assert!(output.iter().flatten().all(|&sample| sample == 0.0));
+
+    // Rectangular dither never moves the silent sidechain off zero, so the OR passes the dithered
+    // main signal through
+    let amplitude = amplitude_at(&dithered(bitwise::DITHER_RECTANGULAR), FREQUENCY, 0);
+    assert!(
+        (amplitude - 0.05).abs() < 0.005,
+        "the tone's amplitude is {amplitude}"
+    );
+}
+
+#[test]
+fn smoothing_low_passes_the_result() {
+    let main = noise(1, 0.5, LEN);
+    let side = noise(2, 0.5, LEN);
+    let unsmoothed = render(with_mode(mode::BITWISE_AND), &main, &side, BLOCK_SIZE);
+    let smoothed = render(
+        Settings {
+            bitwise: BitwiseSettings {
+                smoothing_hz: 1000.0,
+                ..BitwiseSettings::default()
+            },
+            ..with_mode(mode::BITWISE_AND)
+        },
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    let expected: Stereo = unsmoothed.map(|channel| {
+        let mut filter = Svf::default();
+        filter.set(1000.0, BUTTERWORTH_Q, SAMPLE_RATE);
+        channel
+            .into_iter()
+            .map(|sample| filter.process(sample).low)
+            .collect()
+    });
+    assert_eq!(smoothed, expected);
+}
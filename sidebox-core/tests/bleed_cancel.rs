@@ -0,0 +1,152 @@
+// The bleed canceller should learn how the sidechain bleeds into the main signal and remove it,
+// without touching the rest of the main signal
+
+mod common;
+
+use common::{mix, noise, render, silence, sine, with_mode, Stereo, BLOCK_SIZE, SAMPLE_RATE};
+use sidebox_core::bleed_cancel::BleedCancelSettings;
+use sidebox_core::{mode, Processor, Settings};
+
+/// The settings change halfway through one of the tests, so half of this is a multiple of the block
+/// size.
+const LEN: usize = 49152;
+/// The filter has converged well before this point.
+const CONVERGED: usize = LEN / 2;
+
+/// The sidechain as it arrives at the main signal's mic: delayed, quieter, and with a reflection.
+fn bleed(side: &Stereo) -> Stereo {
+    side.clone().map(|channel| {
+        (0..channel.len())
+            .map(|idx| {
+                let tap = |delay: usize| idx.checked_sub(delay).map_or(0.0, |idx| channel[idx]);
+                tap(37) * 0.5 - tap(180) * 0.2
+            })
+            .collect()
+    })
+}
+
+/// The RMS level of the difference between the two signals after `start`, in decibels.
+fn difference_db(a: &Stereo, b: &Stereo, start: usize) -> f32 {
+    let (sum, count) = a
+        .iter()
+        .zip(b)
+        .flat_map(|(a, b)| a[start..].iter().zip(&b[start..]))
+        .fold((0.0, 0), |(sum, count), (a, b)| {
+            (sum + (a - b) * (a - b), count + 1)
+        });
+
+    10.0 * (sum / count as f32).log10()
+}
+
+#[test]
+fn learns_and_removes_the_bleed() {
+    let side = noise(1, 0.5, LEN);
+    let output = render(
+        with_mode(mode::BLEED_CANCEL),
+        &bleed(&side),
+        &side,
+        BLOCK_SIZE,
+    );
+
+    let after_db = difference_db(&output, &silence(LEN), CONVERGED);
+    assert!(after_db < -60.0, "the bleed is still at {after_db} dB");
+}
+
+#[test]
+fn keeps_the_main_signal() {
+    let side = noise(1, 0.5, LEN);
+    let signal = sine(220.0, 0.25, LEN);
+    let main = mix(&signal, &bleed(&side));
+    let output = render(with_mode(mode::BLEED_CANCEL), &main, &side, BLOCK_SIZE);
+
+    // The main signal's own source keeps nudging the filter around, so some bleed remains
+    let before_db = difference_db(&main, &signal, CONVERGED);
+    let after_db = difference_db(&output, &signal, CONVERGED);
+    assert!(
+        after_db < before_db - 10.0,
+        "the bleed went from {before_db} dB to {after_db} dB"
+    );
+}
+
+#[test]
+fn frozen_filter_passes_the_main_signal_through() {
+    let main = noise(1, 0.5, LEN);
+    let output = render(
+        Settings {
+            bleed_cancel: BleedCancelSettings {
+                freeze: true,
+                ..BleedCancelSettings::default()
+            },
+            ..with_mode(mode::BLEED_CANCEL)
+        },
+        &main,
+        &noise(2, 0.5, LEN),
+        BLOCK_SIZE,
+    );
+
+    assert_eq!(output, main);
+}
+
+#[test]
+fn frozen_filter_keeps_cancelling() {
+    let side = noise(1, 0.5, LEN);
+    // The bleed is learned while the source in the main signal is silent, after which the filter is
+    // frozen so the source can't throw it off
+    let signal = sine(220.0, 0.25, LEN).map(|mut channel| {
+        channel[..CONVERGED].fill(0.0);
+        channel
+    });
+    let main = mix(&signal, &bleed(&side));
+
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(Settings {
+        bleed_cancel: BleedCancelSettings {
+            step_size: 0.5,
+            ..BleedCancelSettings::default()
+        },
+        ..with_mode(mode::BLEED_CANCEL)
+    });
+    processor.reset();
+
+    // The output is shifted back by the processor's latency like in `render()`
+    let latency = processor.latency_samples() as usize;
+    let len = LEN + latency;
+    let mut output = main.clone();
+    let mut side = side.clone();
+    for channel in output.iter_mut().chain(side.iter_mut()) {
+        channel.resize(len, 0.0);
+    }
+    for block_start in (0..len).step_by(BLOCK_SIZE) {
+        if block_start == CONVERGED {
+            processor.set_settings(Settings {
+                bleed_cancel: BleedCancelSettings {
+                    step_size: 0.5,
+                    freeze: true,
+                    ..BleedCancelSettings::default()
+                },
+                ..with_mode(mode::BLEED_CANCEL)
+            });
+        }
+
+        let block_end = (block_start + BLOCK_SIZE).min(len);
+        let (left, right) = output.split_at_mut(1);
+        processor.process(
+            &mut [
+                &mut left[0][block_start..block_end],
+                &mut right[0][block_start..block_end],
+            ],
+            &[
+                &side[0][block_start..block_end],
+                &side[1][block_start..block_end],
+            ],
+        );
+    }
+    for channel in output.iter_mut() {
+        channel.drain(..latency);
+        channel.truncate(LEN);
+    }
+
+    let after_db = difference_db(&output, &signal, CONVERGED);
+    assert!(after_db < -60.0, "the bleed is still at {after_db} dB");
+}
@@ -0,0 +1,143 @@
+// Deterministic test signals and a helper for rendering them through a `Processor`
+
+#![allow(dead_code)]
+
+use std::f32::consts::PI;
+
+use sidebox_core::{Processor, Settings, MAX_CHANNELS};
+
+pub const SAMPLE_RATE: f32 = 48000.0;
+/// One second of audio, which is long enough for most tests.
+pub const LEN: usize = 48000;
+pub const BLOCK_SIZE: usize = 256;
+
+/// Stereo audio, one `Vec` per channel.
+pub type Stereo = [Vec<f32>; MAX_CHANNELS];
+
+/// A sine wave with the right channel slightly out of phase with the left channel.
+pub fn sine(frequency: f32, amplitude: f32, len: usize) -> Stereo {
+    let channel = |phase_offset: f32| {
+        (0..len)
+            .map(|idx| {
+                (2.0 * PI * frequency * idx as f32 / SAMPLE_RATE + phase_offset).sin() * amplitude
+            })
+            .collect()
+    };
+
+    [channel(0.0), channel(PI / 4.0)]
+}
+
+/// Uniform white noise in `[-amplitude, amplitude]` from a fixed seed, so the same seed always
+/// produces the same samples on every platform.
+pub fn noise(seed: u32, amplitude: f32, len: usize) -> Stereo {
+    let channel = |mut state: u32| {
+        (0..len)
+            .map(|_| {
+                // xorshift32
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+
+                (state as f32 / u32::MAX as f32 * 2.0 - 1.0) * amplitude
+            })
+            .collect()
+    };
+
+    [
+        channel(seed.max(1)),
+        channel(seed.wrapping_mul(7919).max(1)),
+    ]
+}
+
+/// Single-sample impulses every `period` samples, starting at the first sample.
+pub fn impulses(period: usize, amplitude: f32, len: usize) -> Stereo {
+    let channel = (0..len)
+        .map(|idx| if idx % period == 0 { amplitude } else { 0.0 })
+        .collect::<Vec<_>>();
+
+    [channel.clone(), channel]
+}
+
+pub fn silence(len: usize) -> Stereo {
+    [vec![0.0; len], vec![0.0; len]]
+}
+
+/// Add two signals together sample by sample.
+pub fn mix(a: &Stereo, b: &Stereo) -> Stereo {
+    let channel = |idx: usize| a[idx].iter().zip(&b[idx]).map(|(a, b)| a + b).collect();
+
+    [channel(0), channel(1)]
+}
+
+/// The amplitude of `frequency` in the left channel, skipping the first `start` samples while
+/// whatever is being tested settles.
+pub fn amplitude_at(signal: &Stereo, frequency: f32, start: usize) -> f32 {
+    let samples = &signal[0][start..];
+    let (re, im) = samples
+        .iter()
+        .enumerate()
+        .fold((0.0, 0.0), |(re, im), (idx, sample)| {
+            let phase = 2.0 * PI * frequency * idx as f32 / SAMPLE_RATE;
+            (re + sample * phase.cos(), im + sample * phase.sin())
+        });
+
+    (re * re + im * im).sqrt() * 2.0 / samples.len() as f32
+}
+
+/// The highest absolute sample value.
+pub fn peak(signal: &[f32]) -> f32 {
+    signal
+        .iter()
+        .fold(0.0, |peak, sample| f32::max(peak, sample.abs()))
+}
+
+/// The default settings with `mode` selected. The mode's own settings can be filled in with struct
+/// update syntax.
+pub fn with_mode(mode: i32) -> Settings {
+    Settings {
+        mode,
+        ..Settings::default()
+    }
+}
+
+/// Run `main` and `side` through a freshly prepared processor in blocks of `block_size` samples.
+/// Like the offline renderer, this compensates for the processor's latency so the output lines up
+/// with the inputs.
+pub fn render(settings: Settings, main: &Stereo, side: &Stereo, block_size: usize) -> Stereo {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, block_size);
+    processor.set_settings(settings);
+    processor.reset();
+
+    let latency = processor.latency_samples() as usize;
+    let len = main[0].len() + latency;
+    let mut output = main.clone();
+    let mut side = side.clone();
+    for channel in output.iter_mut().chain(side.iter_mut()) {
+        channel.resize(len, 0.0);
+    }
+
+    let (left, right) = output.split_at_mut(1);
+    let mut block_start = 0;
+    while block_start < len {
+        let block_end = (block_start + block_size).min(len);
+
+        let mut main_block = [
+            &mut left[0][block_start..block_end],
+            &mut right[0][block_start..block_end],
+        ];
+        let side_block = [
+            &side[0][block_start..block_end],
+            &side[1][block_start..block_end],
+        ];
+        processor.process(&mut main_block, &side_block);
+
+        block_start = block_end;
+    }
+
+    for channel in output.iter_mut() {
+        channel.drain(..latency);
+    }
+
+    output
+}
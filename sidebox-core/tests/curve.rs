@@ -0,0 +1,74 @@
+// Breakpoint curves as drawn in the editor
+
+use sidebox_core::curve::{shape, Breakpoint, Curve, MAX_POINTS};
+
+fn ramp(tension: f32) -> Curve {
+    Curve::from_points(&[
+        Breakpoint::new(0.0, 0.0, tension),
+        Breakpoint::new(1.0, 1.0, 0.0),
+    ])
+}
+
+#[test]
+fn passes_through_every_point() {
+    let points = [
+        Breakpoint::new(0.0, 0.2, 0.5),
+        Breakpoint::new(0.3, 1.0, -0.8),
+        Breakpoint::new(0.7, 0.0, 0.0),
+        Breakpoint::new(1.0, 0.6, 0.0),
+    ];
+    let curve = Curve::from_points(&points);
+
+    for point in points {
+        assert!((curve.value_at(point.x) - point.y).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn holds_the_outer_points() {
+    let curve = Curve::from_points(&[
+        Breakpoint::new(0.25, 0.4, 0.0),
+        Breakpoint::new(0.75, 0.9, 0.0),
+    ]);
+
+    assert_eq!(curve.value_at(0.0), 0.4);
+    assert_eq!(curve.value_at(1.0), 0.9);
+    assert_eq!(Curve::from_points(&[]).value_at(0.5), 0.0);
+}
+
+#[test]
+fn tension_bends_the_segment() {
+    assert!((ramp(0.0).value_at(0.5) - 0.5).abs() < 1e-6);
+    assert!(ramp(0.5).value_at(0.5) < 0.5);
+    assert!(ramp(-0.5).value_at(0.5) > 0.5);
+
+    // The bend keeps the segment monotonic
+    for tension in [-1.0, -0.3, 0.3, 1.0] {
+        let curve = ramp(tension);
+        let values: Vec<f32> = (0..=100)
+            .map(|idx| curve.value_at(idx as f32 / 100.0))
+            .collect();
+        assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    assert_eq!(shape(0.0, 0.7), 0.0);
+    assert_eq!(shape(1.0, -0.7), 1.0);
+}
+
+#[test]
+fn invalid_points_are_clamped() {
+    let points: Vec<_> = (0..MAX_POINTS * 2)
+        .map(|idx| Breakpoint::new(1.5 - idx as f32 * 0.1, 2.0, 5.0))
+        .collect();
+    let curve = Curve::from_points(&points);
+
+    assert_eq!(curve.points().len(), MAX_POINTS);
+    for pair in curve.points().windows(2) {
+        assert!(pair[0].x <= pair[1].x);
+    }
+    for point in curve.points() {
+        assert!((0.0..=1.0).contains(&point.x));
+        assert_eq!(point.y, 1.0);
+        assert_eq!(point.tension, 1.0);
+    }
+}
@@ -0,0 +1,134 @@
+// Spectral denoising should remove the noise the sidechain picks up from the main signal, and leave
+// everything else alone
+
+mod common;
+
+use common::{amplitude_at, mix, noise, render, silence, sine, with_mode, Stereo, BLOCK_SIZE};
+use sidebox_core::spectral_denoise::{self, SpectralDenoiseSettings};
+use sidebox_core::stft::FFT_SIZE;
+use sidebox_core::{mode, Settings};
+
+const LEN: usize = FFT_SIZE * 16;
+/// The noise profile and the gains need a couple of frames to settle.
+const SETTLE: usize = FFT_SIZE * 4;
+
+/// The RMS difference between the left channels after the settling time, in decibels relative to
+/// `reference`'s RMS level.
+fn residual_db(output: &Stereo, expected: &Stereo, reference: &Stereo) -> f32 {
+    let rms = |samples: &mut dyn Iterator<Item = f32>| {
+        let (sum, count) = samples.fold((0.0, 0), |(sum, count), sample| {
+            (sum + sample * sample, count + 1)
+        });
+        (sum / count as f32).sqrt()
+    };
+
+    let difference = rms(&mut output[0][SETTLE..]
+        .iter()
+        .zip(&expected[0][SETTLE..])
+        .map(|(output, expected)| output - expected));
+    let reference = rms(&mut reference[0][SETTLE..].iter().copied());
+
+    20.0 * (difference / reference).log10()
+}
+
+#[test]
+fn silent_sidechain_reconstructs_the_input() {
+    let main = mix(&sine(440.0, 0.5, LEN), &noise(1, 0.1, LEN));
+    for method in [
+        spectral_denoise::METHOD_SUBTRACTION,
+        spectral_denoise::METHOD_WIENER,
+    ] {
+        let output = render(
+            Settings {
+                spectral_denoise: SpectralDenoiseSettings {
+                    method,
+                    ..SpectralDenoiseSettings::default()
+                },
+                ..with_mode(mode::SPECTRAL_DENOISE)
+            },
+            &main,
+            &silence(LEN),
+            BLOCK_SIZE,
+        );
+
+        for (output, main) in output.iter().zip(&main) {
+            for (idx, (output, main)) in output.iter().zip(main).enumerate().skip(FFT_SIZE) {
+                assert!(
+                    (output - main).abs() < 1e-4,
+                    "sample {idx}: expected {main}, got {output}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn removes_the_noise_and_keeps_the_signal() {
+    // The sidechain picks up noise with the same spectrum as the noise in the main signal, but not
+    // the same samples
+    let signal = sine(1000.0, 0.5, LEN);
+    let main_noise = noise(1, 0.1, LEN);
+    let main = mix(&signal, &main_noise);
+    let side = noise(2, 0.1, LEN);
+
+    for method in [
+        spectral_denoise::METHOD_SUBTRACTION,
+        spectral_denoise::METHOD_WIENER,
+    ] {
+        let output = render(
+            Settings {
+                spectral_denoise: SpectralDenoiseSettings {
+                    method,
+                    reduction_db: 40.0,
+                    ..SpectralDenoiseSettings::default()
+                },
+                ..with_mode(mode::SPECTRAL_DENOISE)
+            },
+            &main,
+            &side,
+            BLOCK_SIZE,
+        );
+
+        // Everything that's left apart from the sine is noise. The noise profile is only an
+        // average, so noise that randomly rises above it in a frame gets through.
+        let name = spectral_denoise::method_name(method);
+        let noise_db = residual_db(&output, &signal, &main_noise);
+        assert!(noise_db < -6.0, "{name}: the noise is at {noise_db} dB");
+        let signal_db = 20.0 * (amplitude_at(&output, 1000.0, SETTLE) / 0.5).log10();
+        assert!(
+            signal_db.abs() < 1.0,
+            "{name}: the sine changed by {signal_db} dB"
+        );
+    }
+}
+
+#[test]
+fn reduction_limits_the_attenuation() {
+    // With a much louder noise reference every bin is attenuated as much as possible
+    let main = noise(1, 0.1, LEN);
+    let side = noise(2, 1.0, LEN);
+    let reduction_db = 12.0;
+    let output = render(
+        Settings {
+            spectral_denoise: SpectralDenoiseSettings {
+                reduction_db,
+                ..SpectralDenoiseSettings::default()
+            },
+            ..with_mode(mode::SPECTRAL_DENOISE)
+        },
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    let gain = 10.0f32.powf(-reduction_db / 20.0);
+    for (output, main) in output.iter().zip(&main) {
+        for (idx, (output, main)) in output.iter().zip(main).enumerate().skip(SETTLE) {
+            assert!(
+                (output - main * gain).abs() < 1e-4,
+                "sample {idx}: expected {}, got {output}",
+                main * gain
+            );
+        }
+    }
+}
@@ -0,0 +1,97 @@
+// Linked detection should key every channel by the same level so the stereo image stays put
+
+mod common;
+
+use common::{noise, peak, render, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::{detection_link, mode, Settings};
+
+/// The gate has settled well before this point.
+const SETTLED: usize = LEN / 2;
+
+fn linked_gate(link: i32, amount: f32) -> Settings {
+    Settings {
+        detection_link: link,
+        detection_link_amount: amount,
+        gate: GateSettings {
+            threshold_db: -20.0,
+            range_db: -80.0,
+            ..GateSettings::default()
+        },
+        ..with_mode(mode::GATE)
+    }
+}
+
+/// Noise on the main input, and a sidechain that's only active on the left channel.
+fn inputs() -> (Stereo, Stereo) {
+    let main = noise(1, 0.5, LEN);
+    let side = [noise(2, 1.0, LEN)[0].clone(), vec![0.0; LEN]];
+
+    (main, side)
+}
+
+#[test]
+fn independent_detection_keeps_channels_separate() {
+    let (main, side) = inputs();
+    let output = render(
+        linked_gate(detection_link::INDEPENDENT, 1.0),
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    assert!(peak(&output[0][SETTLED..]) > 0.4);
+    assert!(peak(&output[1][SETTLED..]) < 1e-3);
+}
+
+#[test]
+fn linked_detection_opens_both_channels() {
+    let (main, side) = inputs();
+    for link in [
+        detection_link::MAX,
+        detection_link::AVERAGE,
+        detection_link::SUM,
+    ] {
+        let output = render(linked_gate(link, 1.0), &main, &side, BLOCK_SIZE);
+
+        let name = detection_link::name(link);
+        assert!(
+            peak(&output[0][SETTLED..]) > 0.4,
+            "{name}: left channel is closed"
+        );
+        assert!(
+            peak(&output[1][SETTLED..]) > 0.4,
+            "{name}: right channel is closed"
+        );
+    }
+}
+
+#[test]
+fn link_amount_fades_between_own_and_linked_levels() {
+    let mut keys = [0.8, -0.2];
+    detection_link::apply(detection_link::MAX, 1.0, &mut keys);
+    assert_eq!(keys, [0.8, 0.8]);
+
+    let mut keys = [0.8, -0.2];
+    detection_link::apply(detection_link::AVERAGE, 1.0, &mut keys);
+    assert_eq!(keys, [0.5, 0.5]);
+
+    let mut keys = [0.8, -0.2];
+    detection_link::apply(detection_link::SUM, 0.5, &mut keys);
+    assert_eq!(keys, [0.9, 0.6]);
+
+    // Without any linking the keys are left untouched, including their sign
+    let mut keys = [0.8, -0.2];
+    detection_link::apply(detection_link::INDEPENDENT, 1.0, &mut keys);
+    assert_eq!(keys, [0.8, -0.2]);
+
+    // The same goes for an amount of zero, apart from rectification
+    let (main, side) = inputs();
+    let output = render(
+        linked_gate(detection_link::MAX, 0.0),
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+    assert!(peak(&output[1][SETTLED..]) < 1e-3);
+}
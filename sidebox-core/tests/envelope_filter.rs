@@ -0,0 +1,83 @@
+// The envelope filter should open up as the sidechain gets louder
+
+mod common;
+
+use common::{peak, render, silence, sine, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::envelope_filter::{self, EnvelopeFilterSettings};
+use sidebox_core::{mode, Settings};
+
+/// A full scale sidechain that keeps the envelope at one.
+fn full_scale(len: usize) -> Stereo {
+    [vec![1.0; len], vec![1.0; len]]
+}
+
+#[test]
+fn sidechain_opens_the_low_pass() {
+    let main = sine(4000.0, 0.5, LEN);
+    let settings = Settings {
+        envelope_filter: EnvelopeFilterSettings {
+            cutoff_hz: 200.0,
+            depth_octaves: 7.0,
+            resonance: 0.7,
+            ..EnvelopeFilterSettings::default()
+        },
+        ..with_mode(mode::ENVELOPE_FILTER)
+    };
+
+    let closed = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+    assert!(peak(&closed[0][LEN / 2..]) < 0.5 * 0.01);
+
+    let open = render(settings, &main, &full_scale(LEN), BLOCK_SIZE);
+    assert!(peak(&open[0][LEN / 2..]) > 0.5 * 0.9);
+}
+
+#[test]
+fn negative_depth_sweeps_down() {
+    let main = sine(200.0, 0.5, LEN);
+    let settings = Settings {
+        envelope_filter: EnvelopeFilterSettings {
+            filter: envelope_filter::FILTER_HIGH_PASS,
+            cutoff_hz: 6400.0,
+            depth_octaves: -7.0,
+            resonance: 0.7,
+            ..EnvelopeFilterSettings::default()
+        },
+        ..with_mode(mode::ENVELOPE_FILTER)
+    };
+
+    let closed = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+    assert!(peak(&closed[0][LEN / 2..]) < 0.5 * 0.01);
+
+    let open = render(settings, &main, &full_scale(LEN), BLOCK_SIZE);
+    assert!(peak(&open[0][LEN / 2..]) > 0.5 * 0.9);
+}
+
+#[test]
+fn release_closes_the_filter_gradually() {
+    let main = sine(4000.0, 0.5, LEN);
+    // The sidechain stops halfway through
+    let mut side = full_scale(LEN);
+    for channel in side.iter_mut() {
+        channel[LEN / 2..].fill(0.0);
+    }
+
+    let level_after_release = |release_ms: f32| {
+        let settings = Settings {
+            envelope_filter: EnvelopeFilterSettings {
+                cutoff_hz: 200.0,
+                depth_octaves: 7.0,
+                resonance: 0.7,
+                release_ms,
+                ..EnvelopeFilterSettings::default()
+            },
+            ..with_mode(mode::ENVELOPE_FILTER)
+        };
+        let output = render(settings, &main, &side, BLOCK_SIZE);
+
+        // 20 ms after the sidechain stopped
+        peak(&output[0][LEN / 2 + 960..LEN / 2 + 1200])
+    };
+
+    assert!(level_after_release(500.0) > 0.5 * 0.5);
+    assert!(level_after_release(1.0) < 0.5 * 0.01);
+}
@@ -0,0 +1,42 @@
+// The gate should never click, even with the shortest attack time
+
+mod common;
+
+use common::{render, sine, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::{mode, Settings};
+
+#[test]
+fn instant_attack_still_fades_in() {
+    let main: Stereo = [vec![1.0; LEN], vec![1.0; LEN]];
+    // The sidechain comes in at full scale halfway through
+    let side = sine(1000.0, 1.0, LEN).map(|mut channel| {
+        channel[..LEN / 2].fill(0.0);
+        channel
+    });
+    let output = render(
+        Settings {
+            gate: GateSettings {
+                attack_ms: 0.0,
+                ..GateSettings::default()
+            },
+            ..with_mode(mode::GATE)
+        },
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    // The main signal is a constant one, so the output is the gate's gain
+    assert_eq!(output[0][LEN / 2 - 1], 0.0);
+    assert!(output[0][LEN - 1] > 0.99);
+    // At 48 kHz the one millisecond minimum attack moves the gain by about 2% per sample at most
+    let max_slope = output[0]
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).abs())
+        .fold(0.0, f32::max);
+    assert!(
+        max_slope < 0.025,
+        "the gain jumps by {max_slope} in one sample"
+    );
+}
@@ -0,0 +1,255 @@
+// The ghost key source should follow the host's tempo and song position
+
+mod common;
+
+use common::{sine, Stereo, SAMPLE_RATE};
+use sidebox_core::curve::{Breakpoint, Curve};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::ghost::{division_from_name, GhostSettings, BAR, SHAPE_DRAWN};
+use sidebox_core::{key_source, mode, Processor, Settings, Transport};
+
+const BLOCK_SIZE: usize = 64;
+const TEMPO: f64 = 120.0;
+/// How many samples it takes for the gate to open fully with its shortest attack time.
+const OPENING: usize = 240;
+
+/// A gate keyed by the ghost curve. It's open for a bit less than the first half of every division
+/// and fully closed for the rest.
+fn ghost_gate(division: &str) -> Settings {
+    Settings {
+        mode: mode::GATE,
+        key_source: key_source::GHOST,
+        gate: GateSettings {
+            threshold_db: -20.0,
+            hysteresis_db: 0.0,
+            attack_ms: 0.0,
+            hold_ms: 0.0,
+            release_ms: 0.0,
+            range_db: -80.0,
+        },
+        ghost: GhostSettings {
+            division: division_from_name(division).unwrap(),
+            length: 0.5,
+            ..GhostSettings::default()
+        },
+        ..Settings::default()
+    }
+}
+
+/// Render a constant signal with the transport starting at `start_beats`. Returns the left channel,
+/// shifted back by the processor's latency like in `render()`.
+fn render_with_transport(
+    settings: Settings,
+    playing: bool,
+    start_beats: f64,
+    len: usize,
+) -> Vec<f32> {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(settings);
+    processor.reset();
+
+    let latency = processor.latency_samples() as usize;
+    let main: Stereo = [vec![1.0; len + latency], vec![1.0; len + latency]];
+    let side = sine(100.0, 1.0, len + latency);
+    let mut output = main.clone();
+    let (left, right) = output.split_at_mut(1);
+    for block_start in (0..len + latency).step_by(BLOCK_SIZE) {
+        let block_end = (block_start + BLOCK_SIZE).min(len + latency);
+        processor.set_transport(Transport {
+            playing,
+            tempo: Some(TEMPO),
+            pos_beats: Some(start_beats + block_start as f64 * TEMPO / 60.0 / SAMPLE_RATE as f64),
+            ..Transport::default()
+        });
+
+        let mut main_block = [
+            &mut left[0][block_start..block_end],
+            &mut right[0][block_start..block_end],
+        ];
+        let side_block = [
+            &side[0][block_start..block_end],
+            &side[1][block_start..block_end],
+        ];
+        processor.process(&mut main_block, &side_block);
+    }
+
+    let [mut left, _] = output;
+    left.drain(..latency);
+    left.truncate(len);
+    left
+}
+
+/// Process a constant signal in a single block of `len` samples, returning the left channel lined
+/// up with the input. The rest of the block's output only comes out after the processor's latency,
+/// so the processor keeps going for that long with the transport moving along.
+fn process_block(processor: &mut Processor, transport: Transport, len: usize) -> Vec<f32> {
+    let latency = processor.latency_samples() as usize;
+    let mut output = Vec::with_capacity(len + latency);
+    let mut block_start = 0;
+    while block_start < len + latency {
+        let block_len = len.min(len + latency - block_start);
+        processor.set_transport(Transport {
+            pos_beats: transport
+                .pos_beats
+                .map(|pos| pos + block_start as f64 * TEMPO / 60.0 / SAMPLE_RATE as f64),
+            ..transport
+        });
+
+        let mut left = vec![1.0; block_len];
+        let mut right = vec![1.0; block_len];
+        let side = sine(100.0, 1.0, block_len);
+        processor.process(&mut [&mut left, &mut right], &[&side[0], &side[1]]);
+        output.extend(left);
+        block_start += block_len;
+    }
+    output.drain(..latency);
+
+    output
+}
+
+/// The length of a quarter note in samples.
+fn quarter_note() -> usize {
+    (SAMPLE_RATE as f64 * 60.0 / TEMPO) as usize
+}
+
+#[test]
+fn gate_opens_on_every_division() {
+    let sixteenth = quarter_note() / 4;
+    let output = render_with_transport(ghost_gate("1/16"), true, 0.0, sixteenth * 8);
+
+    for division_start in (0..output.len()).step_by(sixteenth) {
+        // The first half of every division is open, the second half is closed. The curve reaches
+        // the threshold just before the halfway point, so the edges are skipped.
+        let open = &output[division_start + OPENING..division_start + sixteenth * 4 / 10];
+        let closed = &output[division_start + sixteenth * 6 / 10..division_start + sixteenth];
+        assert!(
+            open.iter().all(|&sample| sample > 0.99),
+            "closed near the start of the division at {division_start}"
+        );
+        assert!(
+            closed.iter().all(|&sample| sample == 0.0),
+            "open near the end of the division at {division_start}"
+        );
+    }
+}
+
+#[test]
+fn follows_the_song_position() {
+    // Starting halfway through a quarter note, the gate only opens on the next one
+    let quarter = quarter_note();
+    let output = render_with_transport(ghost_gate("1/4"), true, 0.5, quarter);
+
+    assert!(output[..quarter / 2 - BLOCK_SIZE]
+        .iter()
+        .all(|&sample| sample == 0.0));
+    assert!(output[quarter / 2 + OPENING..quarter * 9 / 10]
+        .iter()
+        .all(|&sample| sample > 0.99));
+}
+
+#[test]
+fn triplets_divide_a_half_note_into_three() {
+    let half = quarter_note() * 2;
+    let output = render_with_transport(ghost_gate("1/4 triplet"), true, 0.0, half);
+
+    let opening_edges = output
+        .windows(2)
+        .filter(|pair| pair[0] == 0.0 && pair[1] > 0.0)
+        .count();
+    // The first opening happens at the very first sample, which has no rising edge
+    assert_eq!(opening_edges, 2);
+}
+
+#[test]
+fn free_runs_while_stopped() {
+    // The position reported while stopped is ignored, so this starts on a division
+    let sixteenth = quarter_note() / 4;
+    let output = render_with_transport(ghost_gate("1/16"), false, 0.5, sixteenth * 2);
+
+    assert!(output[OPENING..sixteenth * 4 / 10]
+        .iter()
+        .all(|&sample| sample > 0.99));
+    assert!(output[sixteenth + OPENING..sixteenth + sixteenth * 4 / 10]
+        .iter()
+        .all(|&sample| sample > 0.99));
+}
+
+#[test]
+fn sidechain_source_ignores_the_transport() {
+    let settings = Settings {
+        key_source: key_source::SIDECHAIN,
+        ..ghost_gate("1/16")
+    };
+    let sixteenth = quarter_note() / 4;
+    let output = render_with_transport(settings, true, 0.0, sixteenth * 2);
+
+    // The sine on the sidechain keeps the gate open the whole time
+    assert!(output[OPENING..].iter().all(|&sample| sample > 0.99));
+}
+
+#[test]
+fn bars_follow_the_time_signature() {
+    let settings = Settings {
+        ghost: GhostSettings {
+            division: BAR,
+            ..ghost_gate("1/4").ghost
+        },
+        ..ghost_gate("1/4")
+    };
+
+    // In 3/4 the second bar starts on the fourth quarter note
+    let len = quarter_note() / 2;
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, len);
+    processor.set_settings(settings);
+    processor.reset();
+    let left = process_block(
+        &mut processor,
+        Transport {
+            playing: true,
+            tempo: Some(TEMPO),
+            pos_beats: Some(2.9),
+            time_sig_numerator: Some(3),
+            time_sig_denominator: Some(4),
+        },
+        len,
+    );
+
+    let bar_start = quarter_note() / 10;
+    assert!(left[..bar_start - 10].iter().all(|&sample| sample == 0.0));
+    assert!(left[bar_start + OPENING..]
+        .iter()
+        .all(|&sample| sample > 0.99));
+}
+
+#[test]
+fn drawn_shape_follows_the_curve() {
+    // Open for the second half of every division only
+    let settings = Settings {
+        ghost: GhostSettings {
+            shape: SHAPE_DRAWN,
+            ..ghost_gate("1/8").ghost
+        },
+        ..ghost_gate("1/8")
+    };
+    let curve = Curve::from_points(&[
+        Breakpoint::new(0.0, 0.0, 0.0),
+        Breakpoint::new(0.5, 0.0, 0.0),
+        Breakpoint::new(0.5, 1.0, 0.0),
+        Breakpoint::new(1.0, 1.0, 0.0),
+    ]);
+
+    let eighth = quarter_note() / 2;
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, eighth);
+    processor.set_settings(settings);
+    processor.set_curve(&curve);
+    processor.reset();
+    let left = process_block(&mut processor, Transport::default(), eighth);
+
+    assert!(left[..eighth / 2].iter().all(|&sample| sample == 0.0));
+    assert!(left[eighth / 2 + OPENING..]
+        .iter()
+        .all(|&sample| sample > 0.99));
+}
@@ -0,0 +1,151 @@
+// Renders deterministic signals through every mode and compares the output to the reference files
+// in `tests/golden`. After an intentional change in behavior, regenerate the references with:
+//
+//     SIDEBOX_BLESS=1 cargo test -p sidebox-core --test golden
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::{impulses, noise, render, silence, sine, Stereo};
+use sidebox_core::envelope_filter::EnvelopeFilterSettings;
+use sidebox_core::gate::GateSettings;
+use sidebox_core::stutter::StutterSettings;
+use sidebox_core::{mode, Settings};
+
+/// The length of every test case, in samples.
+const LEN: usize = 2048;
+/// Deliberately not a power of two so block boundaries land in different places for every case.
+const BLOCK_SIZE: usize = 100;
+/// Differences this small are rounding noise from different compilers and platforms.
+const TOLERANCE: f32 = 1e-5;
+
+/// The main and sidechain signals rendered through every mode. The reference files contain the
+/// output of these cases concatenated in this order, with both channels of a case stored back to
+/// back.
+fn cases() -> Vec<(&'static str, Stereo, Stereo)> {
+    vec![
+        ("sines", sine(220.0, 0.5, LEN), sine(55.0, 0.8, LEN)),
+        ("noise", noise(1, 0.5, LEN), noise(2, 0.5, LEN)),
+        ("impulses", impulses(441, 1.0, LEN), sine(1000.0, 0.5, LEN)),
+        (
+            "sidechain impulses",
+            noise(3, 0.25, LEN),
+            impulses(300, 1.0, LEN),
+        ),
+        ("silent sidechain", sine(440.0, 0.5, LEN), silence(LEN)),
+    ]
+}
+
+/// The settings used for a mode. Modes with additional parameters can adjust them here so the
+/// interesting parts of the mode actually get exercised.
+fn settings_for(mode: i32) -> Settings {
+    let mut settings = Settings {
+        mode,
+        ..Settings::default()
+    };
+
+    // This only affects detection-based modes
+    settings.lookahead_ms = 2.0;
+
+    if mode == mode::GATE {
+        settings.gate = GateSettings {
+            threshold_db: -20.0,
+            hold_ms: 5.0,
+            release_ms: 20.0,
+            range_db: -60.0,
+            ..GateSettings::default()
+        };
+    }
+
+    if mode == mode::ENVELOPE_FILTER {
+        settings.envelope_filter = EnvelopeFilterSettings {
+            resonance: 4.0,
+            release_ms: 30.0,
+            ..EnvelopeFilterSettings::default()
+        };
+    }
+
+    if mode == mode::STUTTER {
+        // The cases are too short for the default loop length
+        settings.stutter = StutterSettings {
+            length_ms: 5.0,
+            crossfade_ms: 0.5,
+            ..StutterSettings::default()
+        };
+    }
+
+    settings
+}
+
+fn golden_path(mode: i32) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("mode-{mode:02}.bin"))
+}
+
+#[test]
+fn golden_outputs() {
+    let bless = std::env::var_os("SIDEBOX_BLESS").is_some();
+    let cases = cases();
+
+    let mut failures = Vec::new();
+    for mode in 0..=mode::MAX {
+        let output: Vec<f32> = cases
+            .iter()
+            .flat_map(|(_, main, side)| render(settings_for(mode), main, side, BLOCK_SIZE))
+            .flatten()
+            .collect();
+
+        let path = golden_path(mode);
+        if bless {
+            let bytes: Vec<u8> = output
+                .iter()
+                .flat_map(|sample| sample.to_le_bytes())
+                .collect();
+            fs::write(&path, bytes).unwrap();
+            continue;
+        }
+
+        let Ok(bytes) = fs::read(&path) else {
+            failures.push(format!("mode {mode}: '{}' is missing", path.display()));
+            continue;
+        };
+        let expected: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|sample| f32::from_le_bytes(sample.try_into().unwrap()))
+            .collect();
+        if expected.len() != output.len() {
+            failures.push(format!(
+                "mode {mode}: expected {} samples, got {}",
+                expected.len(),
+                output.len()
+            ));
+            continue;
+        }
+
+        let first_mismatch = output
+            .iter()
+            .zip(&expected)
+            .position(|(actual, expected)| (actual - expected).abs() > TOLERANCE);
+        if let Some(idx) = first_mismatch {
+            let (case, _, _) = &cases[idx / (LEN * 2)];
+            let channel = (idx / LEN) % 2;
+            failures.push(format!(
+                "mode {mode}, case '{case}', channel {channel}, sample {}: expected {}, got {}",
+                idx % LEN,
+                expected[idx],
+                output[idx]
+            ));
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "the output no longer matches the reference files, rerun with SIDEBOX_BLESS=1 if this is \
+         intentional:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,107 @@
+// The logic modes should pick between the two signals sample by sample, and the threshold switch
+// should follow whichever signal is louder
+
+mod common;
+
+use common::{noise, render, silence, sine, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::logic::ThresholdSwitchSettings;
+use sidebox_core::{mode, Settings};
+
+/// The crossfades have settled well within this many samples.
+const SETTLE: usize = 4800;
+
+/// The expected output for a main and a sidechain sample.
+type Operation = fn(f32, f32) -> f32;
+
+/// Check that every sample in `output[range]` is exactly `expected(main, side)`.
+fn assert_samplewise(
+    output: &Stereo,
+    main: &Stereo,
+    side: &Stereo,
+    range: std::ops::Range<usize>,
+    expected: impl Fn(f32, f32) -> f32,
+) {
+    for channel in 0..output.len() {
+        for idx in range.clone() {
+            let expected = expected(main[channel][idx], side[channel][idx]);
+            assert_eq!(
+                output[channel][idx], expected,
+                "channel {channel}, sample {idx}"
+            );
+        }
+    }
+}
+
+#[test]
+fn sample_wise_modes() {
+    let main = noise(1, 0.5, LEN);
+    let side = noise(2, 0.5, LEN);
+    let cases: [(i32, Operation); 5] = [
+        (mode::MINIMUM, |main, side| main.min(side)),
+        (mode::MAXIMUM, |main, side| main.max(side)),
+        (mode::ABS_MINIMUM, |main, side| {
+            if side.abs() < main.abs() {
+                side
+            } else {
+                main
+            }
+        }),
+        (mode::ABS_MAXIMUM, |main, side| {
+            if side.abs() > main.abs() {
+                side
+            } else {
+                main
+            }
+        }),
+        (mode::SIGN_SELECT, |main, side| main.abs().copysign(side)),
+    ];
+
+    for (mode, expected) in cases {
+        let output = render(with_mode(mode), &main, &side, BLOCK_SIZE);
+        assert_samplewise(&output, &main, &side, 0..LEN, expected);
+    }
+}
+
+#[test]
+fn threshold_switch_follows_the_louder_signal() {
+    // The sidechain only comes in for the second half
+    let main = sine(440.0, 0.1, LEN);
+    let side = noise(1, 0.5, LEN).map(|mut channel| {
+        channel[..LEN / 2].fill(0.0);
+        channel
+    });
+    let output = render(with_mode(mode::THRESHOLD_SWITCH), &main, &side, BLOCK_SIZE);
+
+    assert_samplewise(&output, &main, &side, 0..LEN / 2, |main, _| main);
+    assert_samplewise(&output, &main, &side, LEN / 2 + SETTLE..LEN, |_, side| side);
+}
+
+#[test]
+fn threshold_switch_needs_the_sidechain_to_be_louder_by_the_threshold() {
+    // The sidechain's peaks are about 8 dB above the main signal's
+    let main = sine(440.0, 0.1, LEN);
+    let side = noise(1, 0.25, LEN);
+    let output = render(
+        Settings {
+            threshold_switch: ThresholdSwitchSettings {
+                threshold_db: 12.0,
+                ..ThresholdSwitchSettings::default()
+            },
+            ..with_mode(mode::THRESHOLD_SWITCH)
+        },
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    // Until the main signal's level detector has caught up, the sidechain is louder
+    assert_samplewise(&output, &main, &side, SETTLE..LEN, |main, _| main);
+
+    let output = render(
+        with_mode(mode::THRESHOLD_SWITCH),
+        &main,
+        &silence(LEN),
+        BLOCK_SIZE,
+    );
+    assert_eq!(output, main);
+}
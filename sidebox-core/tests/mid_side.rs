@@ -0,0 +1,109 @@
+// Mid/side processing should only touch the components it's set up for, and key them correctly
+
+mod common;
+
+use common::{noise, render, silence, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::mid_side::{self, MidSideSettings};
+use sidebox_core::multiband::BandSettings;
+use sidebox_core::{mode, Settings};
+
+fn assert_close(output: &Stereo, expected: impl Fn(usize, usize) -> f32) {
+    for (channel_idx, channel) in output.iter().enumerate() {
+        for (idx, &sample) in channel.iter().enumerate() {
+            let expected = expected(channel_idx, idx);
+            assert!(
+                (sample - expected).abs() < 1e-6,
+                "channel {channel_idx}, sample {idx}: expected {expected}, got {sample}"
+            );
+        }
+    }
+}
+
+#[test]
+fn mid_only_leaves_the_side_alone() {
+    // Multiplying by a silent sidechain removes whatever gets processed
+    let main = noise(1, 0.5, LEN);
+    let settings = Settings {
+        mid_side: MidSideSettings {
+            channels: mid_side::CHANNELS_MID,
+            ..MidSideSettings::default()
+        },
+        ..with_mode(mode::MULTIPLICATION)
+    };
+    let output = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+
+    assert_close(&output, |channel_idx, idx| {
+        let side = (main[0][idx] - main[1][idx]) * 0.5;
+        if channel_idx == 0 {
+            side
+        } else {
+            -side
+        }
+    });
+}
+
+#[test]
+fn side_only_leaves_the_mid_alone() {
+    let main = noise(1, 0.5, LEN);
+    let settings = Settings {
+        mid_side: MidSideSettings {
+            channels: mid_side::CHANNELS_SIDE,
+            ..MidSideSettings::default()
+        },
+        ..with_mode(mode::MULTIPLICATION)
+    };
+    let output = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+
+    assert_close(&output, |_, idx| (main[0][idx] + main[1][idx]) * 0.5);
+}
+
+#[test]
+fn side_component_uses_its_own_settings() {
+    let main = noise(1, 0.5, LEN);
+    let settings = Settings {
+        mid_side: MidSideSettings {
+            channels: mid_side::CHANNELS_MID_SIDE,
+            side: BandSettings {
+                mode: mode::ADDITION,
+                output_gain: 0.0,
+                ..BandSettings::default()
+            },
+            ..MidSideSettings::default()
+        },
+        ..with_mode(mode::ADDITION)
+    };
+    let output = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+
+    assert_close(&output, |_, idx| (main[0][idx] + main[1][idx]) * 0.5);
+}
+
+#[test]
+fn key_can_come_from_the_sidechains_mid_or_side() {
+    let main = noise(1, 0.5, LEN);
+    // A sidechain without any mid component
+    let side_only = noise(2, 0.5, LEN)[0].clone();
+    let side = [side_only.clone(), side_only.iter().map(|s| -s).collect()];
+
+    let mid_key = Settings {
+        mid_side: MidSideSettings {
+            key: mid_side::KEY_MID,
+            ..MidSideSettings::default()
+        },
+        ..with_mode(mode::ADDITION)
+    };
+    let output = render(mid_key, &main, &side, BLOCK_SIZE);
+    assert_close(&output, |channel_idx, idx| main[channel_idx][idx]);
+
+    // Both channels get the same key, even though the sidechain's channels are out of phase
+    let side_key = Settings {
+        mid_side: MidSideSettings {
+            key: mid_side::KEY_SIDE,
+            ..MidSideSettings::default()
+        },
+        ..with_mode(mode::ADDITION)
+    };
+    let output = render(side_key, &main, &side, BLOCK_SIZE);
+    assert_close(&output, |channel_idx, idx| {
+        main[channel_idx][idx] + side_only[idx]
+    });
+}
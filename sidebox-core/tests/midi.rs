@@ -0,0 +1,228 @@
+// The MIDI key source should trigger its envelope at the exact sample a note was placed at
+
+mod common;
+
+use common::{noise, SAMPLE_RATE};
+use sidebox_core::adsr::{Adsr, AdsrSettings};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::{key_source, mode, NoteEvent, Processor, Settings};
+
+const BLOCK_SIZE: usize = 512;
+/// How many samples it takes for the gate to open fully with its shortest attack time.
+const OPENING: usize = 240;
+
+/// A gate that is fully open while any note is held.
+fn midi_gate() -> Settings {
+    Settings {
+        mode: mode::GATE,
+        key_source: key_source::MIDI,
+        gate: GateSettings {
+            threshold_db: -20.0,
+            hysteresis_db: 0.0,
+            attack_ms: 0.0,
+            hold_ms: 0.0,
+            release_ms: 0.0,
+            range_db: -80.0,
+        },
+        midi: AdsrSettings {
+            attack_ms: 0.0,
+            decay_ms: 0.0,
+            sustain: 1.0,
+            release_ms: 0.0,
+            velocity_amount: 1.0,
+        },
+        ..Settings::default()
+    }
+}
+
+/// Process a single block of a constant main signal with `events`, returning the left channel lined
+/// up with the input. The rest of the block's output only comes out after the processor's latency,
+/// so a copy of the processor keeps going for that long without any further events.
+fn process_block(processor: &mut Processor, events: &[NoteEvent]) -> Vec<f32> {
+    for &event in events {
+        processor.queue_note_event(event);
+    }
+
+    let mut output = run_block(processor);
+    let latency = processor.latency_samples() as usize;
+    let mut tail = processor.clone();
+    while output.len() < latency + BLOCK_SIZE {
+        output.extend(run_block(&mut tail));
+    }
+    output.drain(..latency);
+    output.truncate(BLOCK_SIZE);
+
+    output
+}
+
+/// Process a block of a constant main signal, returning the left channel.
+fn run_block(processor: &mut Processor) -> Vec<f32> {
+    let mut left = vec![1.0; BLOCK_SIZE];
+    let mut right = vec![1.0; BLOCK_SIZE];
+    let side = noise(1, 1.0, BLOCK_SIZE);
+    processor.process(&mut [&mut left, &mut right], &[&side[0], &side[1]]);
+
+    left
+}
+
+fn prepared(settings: Settings) -> Processor {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(settings);
+    processor.reset();
+
+    processor
+}
+
+#[test]
+fn notes_open_the_gate_at_their_timing() {
+    let mut processor = prepared(midi_gate());
+    let output = process_block(
+        &mut processor,
+        &[
+            NoteEvent::On {
+                timing: 100,
+                note: 36,
+                velocity: 1.0,
+            },
+            NoteEvent::Off {
+                timing: 500,
+                note: 36,
+            },
+        ],
+    );
+
+    // The gate's minimum attack time takes a couple hundred samples, and the gate's detector keeps
+    // it open for a while after the note has ended
+    assert!(output[..100].iter().all(|&sample| sample == 0.0));
+    assert!(output[100 + OPENING..500]
+        .iter()
+        .all(|&sample| sample > 0.99));
+    process_block(&mut processor, &[]);
+    process_block(&mut processor, &[]);
+    process_block(&mut processor, &[]);
+    let output = process_block(&mut processor, &[]);
+    assert!(output.iter().all(|&sample| sample < 0.01));
+}
+
+#[test]
+fn release_waits_for_all_notes() {
+    let mut processor = prepared(midi_gate());
+    let output = process_block(
+        &mut processor,
+        &[
+            NoteEvent::On {
+                timing: 0,
+                note: 36,
+                velocity: 1.0,
+            },
+            NoteEvent::On {
+                timing: 50,
+                note: 38,
+                velocity: 1.0,
+            },
+            NoteEvent::Off {
+                timing: 100,
+                note: 36,
+            },
+        ],
+    );
+    assert!(output[OPENING..].iter().all(|&sample| sample > 0.99));
+
+    // Notes that are still held carry over into the next block
+    let output = process_block(&mut processor, &[]);
+    assert!(output.iter().all(|&sample| sample > 0.99));
+
+    process_block(
+        &mut processor,
+        &[NoteEvent::Off {
+            timing: 0,
+            note: 38,
+        }],
+    );
+    process_block(&mut processor, &[]);
+    process_block(&mut processor, &[]);
+    let output = process_block(&mut processor, &[]);
+    assert!(output.iter().all(|&sample| sample < 0.01));
+}
+
+#[test]
+fn sidechain_source_ignores_notes() {
+    let settings = Settings {
+        key_source: key_source::SIDECHAIN,
+        ..midi_gate()
+    };
+    let mut processor = prepared(settings);
+    let output = process_block(
+        &mut processor,
+        &[NoteEvent::On {
+            timing: 0,
+            note: 36,
+            velocity: 0.0,
+        }],
+    );
+
+    // The noise on the sidechain keeps the gate open, even though a silent note is held
+    assert!(output[OPENING..].iter().all(|&sample| sample > 0.99));
+}
+
+#[test]
+fn velocity_scales_the_envelope() {
+    let settings = AdsrSettings {
+        attack_ms: 1.0,
+        decay_ms: 10.0,
+        sustain: 0.5,
+        release_ms: 10.0,
+        velocity_amount: 1.0,
+    };
+    let peak = |velocity: f32| {
+        let mut adsr = Adsr::default();
+        adsr.set_settings(&settings, SAMPLE_RATE);
+        adsr.note_on(60, velocity);
+        (0..1000).map(|_| adsr.next_sample()).fold(0.0f32, f32::max)
+    };
+
+    assert!((peak(1.0) - 1.0).abs() < 1e-6);
+    assert!((peak(0.25) - 0.25).abs() < 1e-6);
+
+    // Without velocity sensitivity every note peaks at full scale
+    let mut adsr = Adsr::default();
+    adsr.set_settings(
+        &AdsrSettings {
+            velocity_amount: 0.0,
+            ..settings
+        },
+        SAMPLE_RATE,
+    );
+    adsr.note_on(60, 0.1);
+    let peak = (0..1000).map(|_| adsr.next_sample()).fold(0.0f32, f32::max);
+    assert!((peak - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn envelope_goes_through_every_stage() {
+    let mut adsr = Adsr::default();
+    adsr.set_settings(
+        &AdsrSettings {
+            attack_ms: 10.0,
+            decay_ms: 10.0,
+            sustain: 0.5,
+            release_ms: 10.0,
+            velocity_amount: 0.0,
+        },
+        SAMPLE_RATE,
+    );
+
+    adsr.note_on(60, 1.0);
+    let attack: Vec<f32> = (0..480).map(|_| adsr.next_sample()).collect();
+    assert!(attack.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!((attack[479] - 1.0).abs() < 1e-3);
+
+    // Sustain
+    let sustained = (0..48000).map(|_| adsr.next_sample()).last().unwrap();
+    assert!((sustained - 0.5).abs() < 1e-6);
+
+    adsr.note_off(60);
+    let released = (0..48000).map(|_| adsr.next_sample()).last().unwrap();
+    assert_eq!(released, 0.0);
+}
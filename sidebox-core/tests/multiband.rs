@@ -0,0 +1,119 @@
+// Multiband processing should split the signals without coloring them, and keep every band's mode to
+// its own band
+
+mod common;
+
+use common::{amplitude_at, mix, render, silence, sine, Stereo, BLOCK_SIZE, LEN, SAMPLE_RATE};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::multiband::{BandSettings, MultibandSettings};
+use sidebox_core::stft::FFT_SIZE;
+use sidebox_core::{mode, Processor, Settings};
+
+fn multiband(num_bands: usize, modes: &[i32]) -> Settings {
+    let mut settings = MultibandSettings {
+        num_bands,
+        crossovers_hz: [200.0, 1000.0, 5000.0],
+        ..MultibandSettings::default()
+    };
+    for (band, &mode) in settings.bands.iter_mut().zip(modes) {
+        band.mode = mode;
+    }
+
+    Settings {
+        multiband: settings,
+        ..Settings::default()
+    }
+}
+
+/// The level of `frequency` in the left channel relative to `amplitude`, in decibels. The first
+/// part of the signal is skipped while the filters settle.
+fn level_db(signal: &Stereo, frequency: f32, amplitude: f32) -> f32 {
+    20.0 * (amplitude_at(signal, frequency, LEN / 4) / amplitude).log10()
+}
+
+#[test]
+fn bands_sum_to_a_flat_response() {
+    for frequency in [50.0, 200.0, 700.0, 1000.0, 3000.0, 5000.0, 12000.0] {
+        let output = render(
+            multiband(4, &[mode::ADDITION; 4]),
+            &sine(frequency, 0.5, LEN),
+            &silence(LEN),
+            BLOCK_SIZE,
+        );
+
+        let change_db = level_db(&output, frequency, 0.5);
+        assert!(
+            change_db.abs() < 0.1,
+            "{frequency} Hz changed by {change_db} dB"
+        );
+    }
+}
+
+#[test]
+fn every_band_uses_its_own_mode() {
+    // A gate with a silent key stays closed, so only the upper band should make it through
+    let mut settings = multiband(2, &[mode::GATE, mode::ADDITION]);
+    settings.multiband.crossovers_hz[0] = 1000.0;
+    settings.gate = GateSettings {
+        range_db: -80.0,
+        ..GateSettings::default()
+    };
+    let main = mix(&sine(100.0, 0.5, LEN), &sine(5000.0, 0.5, LEN));
+    let output = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+
+    let gated_db = level_db(&output, 100.0, 0.5);
+    let untouched_db = level_db(&output, 5000.0, 0.5);
+    assert!(
+        gated_db < -40.0,
+        "100 Hz was only attenuated by {gated_db} dB"
+    );
+    assert!(
+        untouched_db.abs() < 0.1,
+        "5 kHz changed by {untouched_db} dB"
+    );
+}
+
+#[test]
+fn band_gains_only_affect_their_band() {
+    let mut settings = multiband(2, &[mode::ADDITION; 2]);
+    settings.multiband.crossovers_hz[0] = 1000.0;
+    settings.multiband.bands[1] = BandSettings {
+        output_gain: 0.5,
+        ..BandSettings::default()
+    };
+    let main = mix(&sine(100.0, 0.5, LEN), &sine(5000.0, 0.5, LEN));
+    let output = render(settings, &main, &silence(LEN), BLOCK_SIZE);
+
+    let low_db = level_db(&output, 100.0, 0.5);
+    let high_db = level_db(&output, 5000.0, 0.5);
+    assert!(low_db.abs() < 0.1, "100 Hz changed by {low_db} dB");
+    assert!(
+        (high_db + 6.02).abs() < 0.1,
+        "5 kHz changed by {high_db} dB instead of -6 dB"
+    );
+}
+
+#[test]
+fn bands_are_delayed_to_match_the_slowest_band() {
+    let settings = multiband(3, &[mode::ADDITION, mode::SPECTRAL_DUCK, mode::ADDITION]);
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(settings);
+    assert_eq!(processor.latency_samples(), FFT_SIZE as u32);
+
+    // If the bands were misaligned they would no longer sum to a flat response
+    for frequency in [200.0, 1000.0, 5000.0] {
+        let output = render(
+            settings,
+            &sine(frequency, 0.5, LEN),
+            &silence(LEN),
+            BLOCK_SIZE,
+        );
+
+        let change_db = level_db(&output, frequency, 0.5);
+        assert!(
+            change_db.abs() < 0.1,
+            "{frequency} Hz changed by {change_db} dB"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// Onsets should be found at the right sample no matter how the sidechain is split into blocks, and
+// the onset-triggered modes should line up with them
+
+mod common;
+
+use common::{noise, render, silence, Stereo, LEN, SAMPLE_RATE};
+use sidebox_core::onset::{self, OnsetSettings};
+use sidebox_core::stutter::{self, StutterSettings};
+use sidebox_core::{mode, Processor, Settings};
+
+const BURSTS: [usize; 4] = [4000, 14000, 26000, 37000];
+
+/// Percussive hits at the given positions: a full scale click followed by decaying noise.
+fn hits(positions: &[usize]) -> Stereo {
+    let noise = noise(7, 0.5, LEN);
+    let mut channel = vec![0.0; LEN];
+    for &position in positions {
+        channel[position] = 1.0;
+        for idx in position + 1..(position + 4000).min(LEN) {
+            channel[idx] = noise[0][idx] * (-((idx - position) as f32) / 600.0).exp();
+        }
+    }
+
+    [channel.clone(), channel]
+}
+
+/// The positions of the onset triggers for the whole sidechain, relative to its start.
+fn onsets(settings: Settings, side: &Stereo, block_size: usize) -> Vec<usize> {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, block_size);
+    processor.set_settings(settings);
+    processor.reset();
+
+    let mut main = silence(LEN);
+    let mut positions = Vec::new();
+    for block_start in (0..LEN).step_by(block_size) {
+        let block_end = (block_start + block_size).min(LEN);
+        let (left, right) = main.split_at_mut(1);
+        processor.process(
+            &mut [
+                &mut left[0][block_start..block_end],
+                &mut right[0][block_start..block_end],
+            ],
+            &[
+                &side[0][block_start..block_end],
+                &side[1][block_start..block_end],
+            ],
+        );
+        positions.extend(processor.onsets().iter().map(|idx| block_start + idx));
+    }
+
+    positions
+}
+
+#[test]
+fn onsets_are_sample_accurate() {
+    let side = hits(&BURSTS);
+    let expected: Vec<usize> = BURSTS.iter().map(|idx| idx + onset::LATENCY).collect();
+    for method in [onset::METHOD_SPECTRAL_FLUX, onset::METHOD_HFC] {
+        let settings = Settings {
+            onset: OnsetSettings {
+                method,
+                ..OnsetSettings::default()
+            },
+            ..Settings::default()
+        };
+        for block_size in [1, 64, 100, 512] {
+            assert_eq!(
+                onsets(settings, &side, block_size),
+                expected,
+                "{}, {block_size} sample blocks",
+                onset::method_name(method)
+            );
+        }
+    }
+}
+
+#[test]
+fn lookahead_delays_the_triggers() {
+    let side = hits(&BURSTS);
+    let settings = Settings {
+        lookahead_ms: 10.0,
+        ..Settings::default()
+    };
+
+    let expected: Vec<usize> = BURSTS.iter().map(|idx| idx + 480).collect();
+    assert_eq!(onsets(settings, &side, 128), expected);
+}
+
+#[test]
+fn onsets_closer_than_the_minimum_interval_are_ignored() {
+    // Every hit is followed by another one 20 ms later
+    let positions: Vec<usize> = BURSTS.iter().flat_map(|&idx| [idx, idx + 960]).collect();
+    let side = hits(&positions);
+
+    let settings = |min_interval_ms: f32| Settings {
+        onset: OnsetSettings {
+            min_interval_ms,
+            ..OnsetSettings::default()
+        },
+        ..Settings::default()
+    };
+    assert_eq!(onsets(settings(10.0), &side, 128).len(), positions.len());
+    assert_eq!(onsets(settings(50.0), &side, 128).len(), BURSTS.len());
+}
+
+#[test]
+fn stutter_loops_start_at_onsets() {
+    let main = noise(1, 0.5, LEN);
+    let side = hits(&BURSTS[..1]);
+    let loop_len = 480;
+    let settings = Settings {
+        mode: mode::STUTTER,
+        stutter: StutterSettings {
+            trigger: stutter::TRIGGER_ONSET,
+            length_ms: 10.0,
+            crossfade_ms: 0.0,
+            release_db: -100.0,
+            ..StutterSettings::default()
+        },
+        ..Settings::default()
+    };
+    let output = render(settings, &main, &side, 128);
+
+    let start = BURSTS[0];
+    for (channel, main) in output.iter().zip(&main) {
+        assert_eq!(channel[..start + loop_len], main[..start + loop_len]);
+        for idx in start + loop_len..start + 4000 {
+            assert_eq!(channel[idx], main[start + (idx - start) % loop_len]);
+        }
+    }
+}
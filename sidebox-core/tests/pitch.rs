@@ -0,0 +1,127 @@
+// The pitch tracker should find the fundamental of periodic signals, and the ring modulator's
+// carrier should follow it
+
+mod common;
+
+use common::{noise, render, silence, sine, Stereo, BLOCK_SIZE, LEN, SAMPLE_RATE};
+use sidebox_core::pitch::PitchTracker;
+use sidebox_core::ring_mod::RingModSettings;
+use sidebox_core::{mode, Processor, Settings};
+
+fn track(signal: &[f32]) -> PitchTracker {
+    let mut tracker = PitchTracker::default();
+    tracker.prepare(SAMPLE_RATE);
+    for &sample in signal {
+        tracker.process(sample);
+    }
+
+    tracker
+}
+
+/// Estimate the frequency of the second half of a signal by counting its upwards zero crossings.
+fn zero_crossing_frequency(signal: &[f32]) -> f32 {
+    let second_half = &signal[signal.len() / 2..];
+    let crossings = second_half
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+
+    crossings as f32 / (second_half.len() as f32 / SAMPLE_RATE)
+}
+
+#[test]
+fn detects_sine_frequencies() {
+    for frequency in [55.0, 110.0, 220.0, 440.0, 1000.0, 1800.0] {
+        let pitch = track(&sine(frequency, 0.5, 4800)[0]).pitch();
+
+        assert!(
+            (pitch.frequency_hz - frequency).abs() < frequency * 0.01,
+            "expected {frequency} Hz, got {} Hz",
+            pitch.frequency_hz
+        );
+        assert!(
+            pitch.confidence > 0.9,
+            "{frequency} Hz: confidence is only {}",
+            pitch.confidence
+        );
+    }
+}
+
+#[test]
+fn detects_the_fundamental_of_harmonic_signals() {
+    // A sawtooth-like signal with a strong second harmonic shouldn't be detected an octave up
+    let fundamental = sine(150.0, 0.3, 4800);
+    let second = sine(300.0, 0.5, 4800);
+    let third = sine(450.0, 0.2, 4800);
+    let signal: Vec<f32> = (0..4800)
+        .map(|idx| fundamental[0][idx] + second[0][idx] + third[0][idx])
+        .collect();
+    let pitch = track(&signal).pitch();
+
+    assert!(
+        (pitch.frequency_hz - 150.0).abs() < 1.5,
+        "got {} Hz",
+        pitch.frequency_hz
+    );
+}
+
+#[test]
+fn silence_and_noise_have_low_confidence() {
+    assert_eq!(track(&silence(4800)[0]).pitch().confidence, 0.0);
+    assert!(track(&noise(1, 0.5, 4800)[0]).pitch().confidence < 0.8);
+}
+
+#[test]
+fn processor_tracks_the_sidechain() {
+    let mut processor = Processor::new();
+    processor.prepare(SAMPLE_RATE, BLOCK_SIZE);
+    processor.set_settings(Settings::default());
+    processor.reset();
+
+    let mut main = silence(4800);
+    let side = sine(330.0, 0.5, 4800);
+    for block_start in (0..4800).step_by(BLOCK_SIZE) {
+        let block_end = (block_start + BLOCK_SIZE).min(4800);
+        let (left, right) = main.split_at_mut(1);
+        processor.process(
+            &mut [
+                &mut left[0][block_start..block_end],
+                &mut right[0][block_start..block_end],
+            ],
+            &[
+                &side[0][block_start..block_end],
+                &side[1][block_start..block_end],
+            ],
+        );
+    }
+
+    let pitch = processor.sidechain_pitch();
+    assert!((pitch.frequency_hz - 330.0).abs() < 3.3);
+    assert!(pitch.confidence > 0.9);
+}
+
+#[test]
+fn ring_modulation_follows_the_sidechain() {
+    // Ring modulating a constant signal leaves just the carrier
+    let main: Stereo = [vec![0.5; LEN], vec![0.5; LEN]];
+    let side = sine(330.0, 0.5, LEN);
+    for (transpose_semitones, expected) in [(0.0, 330.0), (12.0, 660.0), (-12.0, 165.0)] {
+        let settings = Settings {
+            mode: mode::RING_MODULATION,
+            ring_mod: RingModSettings {
+                transpose_semitones,
+                ..RingModSettings::default()
+            },
+            ..Settings::default()
+        };
+        let output = render(settings, &main, &side, BLOCK_SIZE);
+
+        for channel in &output {
+            let frequency = zero_crossing_frequency(channel);
+            assert!(
+                (frequency - expected).abs() < expected * 0.02,
+                "transposed by {transpose_semitones}: expected {expected} Hz, got {frequency} Hz"
+            );
+        }
+    }
+}
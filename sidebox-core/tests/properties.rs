@@ -0,0 +1,449 @@
+// Properties that should hold for every mode regardless of the input
+
+mod common;
+
+use proptest::prelude::*;
+
+use common::{render, silence, Stereo};
+use sidebox_core::adsr::AdsrSettings;
+use sidebox_core::alignment::AlignmentSettings;
+use sidebox_core::bitwise::{self, BitwiseSettings};
+use sidebox_core::bleed_cancel::BleedCancelSettings;
+use sidebox_core::envelope_filter::{self, EnvelopeFilterSettings};
+use sidebox_core::gate::GateSettings;
+use sidebox_core::ghost::{GhostSettings, MAX_DIVISION, SHAPE_DRAWN};
+use sidebox_core::logic::ThresholdSwitchSettings;
+use sidebox_core::mid_side::{self, MidSideSettings};
+use sidebox_core::multiband::{BandSettings, MultibandSettings, MAX_BANDS};
+use sidebox_core::onset::{self, OnsetSettings};
+use sidebox_core::ring_mod::RingModSettings;
+use sidebox_core::smart_sum::SmartSumSettings;
+use sidebox_core::spectral_denoise::{self, SpectralDenoiseSettings};
+use sidebox_core::spectral_duck::SpectralDuckSettings;
+use sidebox_core::spectral_morph::{self, SpectralMorphSettings};
+use sidebox_core::stutter::{self, StutterSettings};
+use sidebox_core::{detection_link, key_source, mode, Settings, MAX_LOOKAHEAD_MS};
+
+fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+fn stereo(len: usize) -> impl Strategy<Value = Stereo> {
+    (
+        prop::collection::vec(-2.0f32..2.0, len),
+        prop::collection::vec(-2.0f32..2.0, len),
+    )
+        .prop_map(|(left, right)| [left, right])
+}
+
+fn alignment_settings() -> impl Strategy<Value = AlignmentSettings> {
+    (any::<bool>(), any::<bool>(), -50.0f32..50.0).prop_map(|(learn, manual, offset_ms)| {
+        AlignmentSettings {
+            learn,
+            manual,
+            offset_ms,
+        }
+    })
+}
+
+fn smart_sum_settings() -> impl Strategy<Value = SmartSumSettings> {
+    (
+        any::<bool>(),
+        -720.0f32..720.0,
+        0.0f32..30000.0,
+        0.0f32..2000.0,
+    )
+        .prop_map(
+            |(auto, rotation_degrees, cutoff_hz, response_ms)| SmartSumSettings {
+                auto,
+                rotation_degrees,
+                cutoff_hz,
+                response_ms,
+            },
+        )
+}
+
+fn gate_settings() -> impl Strategy<Value = GateSettings> {
+    (
+        -60.0f32..0.0,
+        0.0f32..20.0,
+        0.0f32..50.0,
+        0.0f32..500.0,
+        1.0f32..2000.0,
+        -80.0f32..0.0,
+    )
+        .prop_map(
+            |(threshold_db, hysteresis_db, attack_ms, hold_ms, release_ms, range_db)| {
+                GateSettings {
+                    threshold_db,
+                    hysteresis_db,
+                    attack_ms,
+                    hold_ms,
+                    release_ms,
+                    range_db,
+                }
+            },
+        )
+}
+
+fn threshold_switch_settings() -> impl Strategy<Value = ThresholdSwitchSettings> {
+    (-60.0f32..60.0, 0.0f32..2000.0, 0.0f32..100.0).prop_map(
+        |(threshold_db, release_ms, crossfade_ms)| ThresholdSwitchSettings {
+            threshold_db,
+            release_ms,
+            crossfade_ms,
+        },
+    )
+}
+
+fn bitwise_settings() -> impl Strategy<Value = BitwiseSettings> {
+    (
+        bitwise::MIN_BITS..=bitwise::MAX_BITS,
+        0..bitwise::DITHER_NAMES.len() as i32,
+        20.0f32..=bitwise::MAX_SMOOTHING_HZ,
+    )
+        .prop_map(|(bits, dither, smoothing_hz)| BitwiseSettings {
+            bits,
+            dither,
+            smoothing_hz,
+        })
+}
+
+fn ghost_settings() -> impl Strategy<Value = GhostSettings> {
+    (0..=MAX_DIVISION, 0..=SHAPE_DRAWN, 0.0f32..=1.0).prop_map(|(division, shape, length)| {
+        GhostSettings {
+            division,
+            shape,
+            length,
+        }
+    })
+}
+
+fn midi_settings() -> impl Strategy<Value = AdsrSettings> {
+    (
+        0.0f32..100.0,
+        0.0f32..2000.0,
+        0.0f32..=1.0,
+        0.0f32..2000.0,
+        0.0f32..=1.0,
+    )
+        .prop_map(
+            |(attack_ms, decay_ms, sustain, release_ms, velocity_amount)| AdsrSettings {
+                attack_ms,
+                decay_ms,
+                sustain,
+                release_ms,
+                velocity_amount,
+            },
+        )
+}
+
+fn spectral_duck_settings() -> impl Strategy<Value = SpectralDuckSettings> {
+    (
+        0.0f32..48.0,
+        20.0f32..20000.0,
+        20.0f32..20000.0,
+        0.0f32..500.0,
+        0.0f32..2000.0,
+        0.0f32..2.0,
+    )
+        .prop_map(
+            |(depth_db, low_hz, high_hz, attack_ms, release_ms, smoothing_octaves)| {
+                SpectralDuckSettings {
+                    depth_db,
+                    low_hz,
+                    high_hz,
+                    attack_ms,
+                    release_ms,
+                    smoothing_octaves,
+                }
+            },
+        )
+}
+
+fn spectral_morph_settings() -> impl Strategy<Value = SpectralMorphSettings> {
+    (-0.5f32..1.5, 0..spectral_morph::MORPH_NAMES.len() as i32)
+        .prop_map(|(amount, morph)| SpectralMorphSettings { amount, morph })
+}
+
+fn spectral_denoise_settings() -> impl Strategy<Value = SpectralDenoiseSettings> {
+    (
+        0..spectral_denoise::METHOD_NAMES.len() as i32,
+        -10.0f32..80.0,
+        0.0f32..2000.0,
+        -0.5f32..1.5,
+    )
+        .prop_map(|(method, reduction_db, smoothing_ms, musical_noise)| {
+            SpectralDenoiseSettings {
+                method,
+                reduction_db,
+                smoothing_ms,
+                musical_noise,
+            }
+        })
+}
+
+fn ring_mod_settings() -> impl Strategy<Value = RingModSettings> {
+    (-48.0f32..48.0, 0.0f32..2000.0, 0.0f32..=1.0).prop_map(
+        |(transpose_semitones, glide_ms, min_confidence)| RingModSettings {
+            transpose_semitones,
+            glide_ms,
+            min_confidence,
+        },
+    )
+}
+
+fn envelope_filter_settings() -> impl Strategy<Value = EnvelopeFilterSettings> {
+    (
+        0..envelope_filter::FILTER_NAMES.len() as i32,
+        0.0f32..30000.0,
+        -10.0f32..10.0,
+        0.1f32..20.0,
+        0.0f32..500.0,
+        0.0f32..2000.0,
+    )
+        .prop_map(
+            |(filter, cutoff_hz, depth_octaves, resonance, attack_ms, release_ms)| {
+                EnvelopeFilterSettings {
+                    filter,
+                    cutoff_hz,
+                    depth_octaves,
+                    resonance,
+                    attack_ms,
+                    release_ms,
+                }
+            },
+        )
+}
+
+fn stutter_settings() -> impl Strategy<Value = StutterSettings> {
+    (
+        0..stutter::TRIGGER_NAMES.len() as i32,
+        -80.0f32..0.0,
+        any::<bool>(),
+        0.0f32..5000.0,
+        0..=MAX_DIVISION,
+        0.0f32..100.0,
+        -100.0f32..0.0,
+    )
+        .prop_map(
+            |(trigger, threshold_db, sync, length_ms, division, crossfade_ms, release_db)| {
+                StutterSettings {
+                    trigger,
+                    threshold_db,
+                    sync,
+                    length_ms,
+                    division,
+                    crossfade_ms,
+                    release_db,
+                }
+            },
+        )
+}
+
+fn bleed_cancel_settings() -> impl Strategy<Value = BleedCancelSettings> {
+    (-0.5f32..1.5, 0.0f32..100.0, any::<bool>()).prop_map(|(step_size, length_ms, freeze)| {
+        BleedCancelSettings {
+            step_size,
+            length_ms,
+            freeze,
+        }
+    })
+}
+
+fn onset_settings() -> impl Strategy<Value = OnsetSettings> {
+    (
+        0..onset::METHOD_NAMES.len() as i32,
+        0.0f32..10.0,
+        0.0f32..500.0,
+    )
+        .prop_map(|(method, threshold, min_interval_ms)| OnsetSettings {
+            method,
+            threshold,
+            min_interval_ms,
+        })
+}
+
+fn band_settings() -> impl Strategy<Value = BandSettings> {
+    (
+        0..=mode::MAX,
+        -30.0f32..30.0,
+        -30.0f32..30.0,
+        -30.0f32..30.0,
+    )
+        .prop_map(
+            |(mode, input_gain, sidechain_input_gain, output_gain)| BandSettings {
+                mode,
+                input_gain: db_to_gain(input_gain),
+                sidechain_input_gain: db_to_gain(sidechain_input_gain),
+                output_gain: db_to_gain(output_gain),
+            },
+        )
+}
+
+fn multiband_settings() -> impl Strategy<Value = MultibandSettings> {
+    (
+        1..=MAX_BANDS,
+        prop::array::uniform3(0.0f32..30000.0),
+        prop::array::uniform4(band_settings()),
+    )
+        .prop_map(|(num_bands, crossovers_hz, bands)| MultibandSettings {
+            num_bands,
+            crossovers_hz,
+            bands,
+        })
+}
+
+fn mid_side_settings() -> impl Strategy<Value = MidSideSettings> {
+    (
+        0..mid_side::CHANNELS_NAMES.len() as i32,
+        0..mid_side::KEY_NAMES.len() as i32,
+        band_settings(),
+    )
+        .prop_map(|(channels, key, side)| MidSideSettings {
+            channels,
+            key,
+            side,
+        })
+}
+
+fn settings() -> impl Strategy<Value = Settings> {
+    // Proptest only implements `Strategy` for tuples of up to twelve elements, so the settings for
+    // the individual modes are grouped together
+    let spectral_settings = (
+        spectral_duck_settings(),
+        spectral_morph_settings(),
+        spectral_denoise_settings(),
+    );
+    let summing_settings = (alignment_settings(), smart_sum_settings());
+    let level_settings = (
+        gate_settings(),
+        threshold_switch_settings(),
+        bitwise_settings(),
+    );
+    let mode_settings = (
+        summing_settings,
+        level_settings,
+        ghost_settings(),
+        midi_settings(),
+        spectral_settings,
+        ring_mod_settings(),
+        envelope_filter_settings(),
+        stutter_settings(),
+        bleed_cancel_settings(),
+        onset_settings(),
+        multiband_settings(),
+        mid_side_settings(),
+    );
+
+    (
+        0..=mode::MAX,
+        -30.0f32..30.0,
+        -30.0f32..30.0,
+        -30.0f32..30.0,
+        any::<bool>(),
+        5..=1000i32,
+        0.0f32..MAX_LOOKAHEAD_MS,
+        0..=key_source::MAX,
+        0..=detection_link::SUM,
+        0.0f32..=1.0,
+        mode_settings,
+    )
+        .prop_map(
+            |(
+                mode,
+                input_gain,
+                sidechain_input_gain,
+                output_gain,
+                sidechain_phase_flip,
+                smoothing,
+                lookahead_ms,
+                key_source,
+                detection_link,
+                detection_link_amount,
+                (
+                    (alignment, smart_sum),
+                    (gate, threshold_switch, bitwise),
+                    ghost,
+                    midi,
+                    (spectral_duck, spectral_morph, spectral_denoise),
+                    ring_mod,
+                    envelope_filter,
+                    stutter,
+                    bleed_cancel,
+                    onset,
+                    multiband,
+                    mid_side,
+                ),
+            )| Settings {
+                mode,
+                input_gain: db_to_gain(input_gain),
+                sidechain_input_gain: db_to_gain(sidechain_input_gain),
+                output_gain: db_to_gain(output_gain),
+                sidechain_phase_flip,
+                envelope_follower_smoothing: smoothing,
+                lookahead_ms,
+                key_source,
+                detection_link,
+                detection_link_amount,
+                alignment,
+                smart_sum,
+                gate,
+                ghost,
+                midi,
+                spectral_duck,
+                spectral_morph,
+                spectral_denoise,
+                ring_mod,
+                envelope_filter,
+                stutter,
+                bleed_cancel,
+                threshold_switch,
+                bitwise,
+                onset,
+                multiband,
+                mid_side,
+            },
+        )
+}
+
+/// A main and a sidechain signal of the same length.
+fn inputs() -> impl Strategy<Value = (Stereo, Stereo)> {
+    (1..1024usize).prop_flat_map(|len| (stereo(len), stereo(len)))
+}
+
+proptest! {
+    #[test]
+    fn output_is_finite(settings in settings(), (main, side) in inputs(), block_size in 1..512usize) {
+        let output = render(settings, &main, &side, block_size);
+        for (channel_idx, channel) in output.iter().enumerate() {
+            if let Some(idx) = channel.iter().position(|sample| !sample.is_finite()) {
+                prop_assert!(false, "mode {}: sample {idx} of channel {channel_idx} is {}", settings.mode, channel[idx]);
+            }
+        }
+    }
+
+    #[test]
+    fn unity_addition_with_silent_sidechain_is_identity(main in (1..1024usize).prop_flat_map(stereo), block_size in 1..512usize) {
+        let settings = Settings { mode: mode::ADDITION, ..Settings::default() };
+        let output = render(settings, &main, &silence(main[0].len()), block_size);
+        prop_assert_eq!(output, main);
+    }
+}
+
+#[test]
+fn silence_in_silence_out() {
+    const LEN: usize = 4096;
+
+    for mode in 0..=mode::MAX {
+        let settings = Settings {
+            mode,
+            ..Settings::default()
+        };
+        let output = render(settings, &silence(LEN), &silence(LEN), 128);
+        assert!(
+            output.iter().flatten().all(|&sample| sample == 0.0),
+            "mode {mode} does not output silence for silent inputs"
+        );
+    }
+}
@@ -0,0 +1,383 @@
+// Drives the processor through the same `prepare()`/`reset()`/`process()` lifecycle the plugin goes
+// through in `initialize()`/`reset()`/`process()` with an allocation counting global allocator, and
+// fails if anything on the audio thread path allocates, reallocates, or frees memory. The core
+// crate has no locking primitives, so allocations are the main real-time hazard to check for. The
+// plugin's own `process()` has a similar test for allocations and locks in `src/tests.rs`.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use common::{noise, SAMPLE_RATE};
+use sidebox_core::adsr::AdsrSettings;
+use sidebox_core::alignment::{self, AlignmentSettings};
+use sidebox_core::bitwise::BitwiseSettings;
+use sidebox_core::bleed_cancel::{self, BleedCancelSettings};
+use sidebox_core::curve::{Breakpoint, Curve, MAX_POINTS};
+use sidebox_core::envelope::{SimpleEnvelopeFollower, MAX_ENVELOPE_FOLLOWER_SIZE};
+use sidebox_core::envelope_filter::EnvelopeFilterSettings;
+use sidebox_core::gate::GateSettings;
+use sidebox_core::ghost::{GhostSettings, MAX_DIVISION};
+use sidebox_core::logic::ThresholdSwitchSettings;
+use sidebox_core::mid_side::MidSideSettings;
+use sidebox_core::multiband::{BandSettings, MultibandSettings, MAX_BANDS};
+use sidebox_core::onset::OnsetSettings;
+use sidebox_core::ring_mod::RingModSettings;
+use sidebox_core::smart_sum::SmartSumSettings;
+use sidebox_core::spectral_denoise::SpectralDenoiseSettings;
+use sidebox_core::spectral_duck::SpectralDuckSettings;
+use sidebox_core::spectral_morph::SpectralMorphSettings;
+use sidebox_core::stutter::StutterSettings;
+use sidebox_core::{
+    key_source, mode, NoteEvent, Processor, Settings, Transport, MAX_LOOKAHEAD_MS, MAX_NOTE_EVENTS,
+};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+thread_local! {
+    /// Allocations are only counted on the thread running the closure passed to
+    /// `assert_no_allocations()`, since the test harness itself allocates on other threads.
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+    static NUM_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+struct CountingAllocator;
+
+impl CountingAllocator {
+    fn count() {
+        // `try_with()` because this may be called while the thread locals are being destroyed
+        let _ = COUNTING.try_with(|counting| {
+            if counting.get() {
+                let _ = NUM_ALLOCATIONS.try_with(|num| num.set(num.get() + 1));
+            }
+        });
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Self::count();
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Run `f` and panic if it allocated or freed any memory.
+fn assert_no_allocations<T>(what: &str, f: impl FnOnce() -> T) -> T {
+    NUM_ALLOCATIONS.with(|num| num.set(0));
+    COUNTING.with(|counting| counting.set(true));
+    let result = f();
+    COUNTING.with(|counting| counting.set(false));
+
+    let num_allocations = NUM_ALLOCATIONS.with(|num| num.get());
+    assert_eq!(
+        num_allocations, 0,
+        "{what} allocated {num_allocations} times"
+    );
+
+    result
+}
+
+/// Block sizes hosts commonly use, plus some awkward ones. The last one is also the maximum block
+/// size the processor gets prepared for.
+const BLOCK_SIZES: &[usize] = &[1, 17, 64, 441, 512, 4096];
+const SAMPLE_RATES: &[f32] = &[22050.0, SAMPLE_RATE, 192000.0];
+/// The number of blocks processed for every combination of mode, sample rate, and block size.
+const NUM_BLOCKS: usize = 8;
+
+/// Values across every parameter's range, including the edges and some values past them.
+const GAINS: &[f32] = &[0.0, 0.0316, 0.5, 1.0, 31.6];
+const TIMES_MS: &[f32] = &[0.0, 1.0, 100.0, 2000.0];
+const LEVELS_DB: &[f32] = &[-100.0, -80.0, -40.0, -10.0, 0.0];
+const FREQUENCIES_HZ: &[f32] = &[0.0, 20.0, 150.0, 1000.0, 20000.0, 30000.0];
+const AMOUNTS: &[f32] = &[0.0, 0.3, 1.0];
+
+/// An xorshift32 generator that picks the parameter values in `random_settings()`.
+struct Rng(u32);
+
+impl Rng {
+    /// A generator that's seeded from the combination of settings under test, so failures can be
+    /// reproduced.
+    fn new(seed: usize) -> Self {
+        Self(0x9e37_79b9 ^ (seed as u32).wrapping_mul(0x85eb_ca6b))
+    }
+
+    fn next(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn pick<T: Copy>(&mut self, values: &[T]) -> T {
+        values[self.next() as usize % values.len()]
+    }
+
+    fn int(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next() % (max - min + 1) as u32) as i32
+    }
+
+    fn bool(&mut self) -> bool {
+        self.next() & 1 == 0
+    }
+}
+
+/// Settings for `mode` with every other parameter picked independently of the others. The settings
+/// change between blocks the same way they would under automation.
+fn random_settings(mode: i32, rng: &mut Rng) -> Settings {
+    let band = |rng: &mut Rng| BandSettings {
+        mode: rng.int(0, mode::MAX),
+        input_gain: rng.pick(GAINS),
+        sidechain_input_gain: rng.pick(GAINS),
+        output_gain: rng.pick(GAINS),
+    };
+
+    Settings {
+        mode,
+        input_gain: rng.pick(GAINS),
+        sidechain_input_gain: rng.pick(GAINS),
+        output_gain: rng.pick(GAINS),
+        sidechain_phase_flip: rng.bool(),
+        envelope_follower_smoothing: rng.pick(&[0, 5, 10, 500, MAX_ENVELOPE_FOLLOWER_SIZE as i32]),
+        lookahead_ms: rng.pick(&[0.0, 1.0, MAX_LOOKAHEAD_MS, MAX_LOOKAHEAD_MS * 2.0]),
+        key_source: rng.int(0, key_source::MAX),
+        detection_link: rng.int(0, 3),
+        detection_link_amount: rng.pick(AMOUNTS),
+        alignment: AlignmentSettings {
+            learn: rng.bool(),
+            manual: rng.bool(),
+            offset_ms: rng.pick(&[-100.0, -alignment::MAX_OFFSET_MS, -0.5, 0.0, 2.3, 100.0]),
+        },
+        smart_sum: SmartSumSettings {
+            auto: rng.bool(),
+            rotation_degrees: rng.pick(&[-180.0, 0.0, 90.0, 720.0]),
+            cutoff_hz: rng.pick(FREQUENCIES_HZ),
+            response_ms: rng.pick(TIMES_MS),
+        },
+        gate: GateSettings {
+            threshold_db: rng.pick(LEVELS_DB),
+            hysteresis_db: rng.pick(&[0.0, 6.0, 20.0]),
+            attack_ms: rng.pick(TIMES_MS),
+            hold_ms: rng.pick(TIMES_MS),
+            release_ms: rng.pick(TIMES_MS),
+            range_db: rng.pick(LEVELS_DB),
+        },
+        ghost: GhostSettings {
+            division: rng.int(0, MAX_DIVISION),
+            shape: rng.int(0, 1),
+            length: rng.pick(AMOUNTS),
+        },
+        midi: AdsrSettings {
+            attack_ms: rng.pick(TIMES_MS),
+            decay_ms: rng.pick(TIMES_MS),
+            sustain: rng.pick(AMOUNTS),
+            release_ms: rng.pick(TIMES_MS),
+            velocity_amount: rng.pick(AMOUNTS),
+        },
+        spectral_duck: SpectralDuckSettings {
+            depth_db: rng.pick(&[0.0, 24.0, 48.0]),
+            low_hz: rng.pick(FREQUENCIES_HZ),
+            high_hz: rng.pick(FREQUENCIES_HZ),
+            attack_ms: rng.pick(TIMES_MS),
+            release_ms: rng.pick(TIMES_MS),
+            smoothing_octaves: rng.pick(&[0.0, 0.5, 2.0]),
+        },
+        spectral_morph: SpectralMorphSettings {
+            amount: rng.pick(AMOUNTS),
+            morph: rng.int(0, 2),
+        },
+        spectral_denoise: SpectralDenoiseSettings {
+            method: rng.int(0, 1),
+            reduction_db: rng.pick(&[0.0, 10.0, 40.0, 100.0]),
+            smoothing_ms: rng.pick(TIMES_MS),
+            musical_noise: rng.pick(AMOUNTS),
+        },
+        ring_mod: RingModSettings {
+            transpose_semitones: rng.pick(&[-48.0, -24.0, 0.0, 7.0, 48.0]),
+            glide_ms: rng.pick(TIMES_MS),
+            min_confidence: rng.pick(AMOUNTS),
+        },
+        envelope_filter: EnvelopeFilterSettings {
+            filter: rng.int(0, 2),
+            cutoff_hz: rng.pick(FREQUENCIES_HZ),
+            depth_octaves: rng.pick(&[-10.0, 0.0, 4.0, 10.0]),
+            resonance: rng.pick(&[0.0, 0.5, 2.0, 20.0]),
+            attack_ms: rng.pick(TIMES_MS),
+            release_ms: rng.pick(TIMES_MS),
+        },
+        stutter: StutterSettings {
+            trigger: rng.int(0, 1),
+            threshold_db: rng.pick(LEVELS_DB),
+            sync: rng.bool(),
+            length_ms: rng.pick(TIMES_MS),
+            division: rng.int(0, MAX_DIVISION),
+            crossfade_ms: rng.pick(TIMES_MS),
+            release_db: rng.pick(LEVELS_DB),
+        },
+        bleed_cancel: BleedCancelSettings {
+            step_size: rng.pick(AMOUNTS),
+            length_ms: rng.pick(&[0.0, 1.0, 10.0, bleed_cancel::MAX_LENGTH_MS]),
+            freeze: rng.bool(),
+        },
+        threshold_switch: ThresholdSwitchSettings {
+            threshold_db: rng.pick(LEVELS_DB),
+            release_ms: rng.pick(TIMES_MS),
+            crossfade_ms: rng.pick(TIMES_MS),
+        },
+        bitwise: BitwiseSettings {
+            bits: rng.pick(&[0, 4, 8, 16, 24, 32]),
+            dither: rng.int(0, 2),
+            smoothing_hz: rng.pick(FREQUENCIES_HZ),
+        },
+        onset: OnsetSettings {
+            method: rng.int(0, 1),
+            threshold: rng.pick(&[0.0, 1.0, 2.0, 10.0]),
+            min_interval_ms: rng.pick(TIMES_MS),
+        },
+        multiband: MultibandSettings {
+            num_bands: rng.int(1, MAX_BANDS as i32) as usize,
+            crossovers_hz: [
+                rng.pick(FREQUENCIES_HZ),
+                rng.pick(FREQUENCIES_HZ),
+                rng.pick(FREQUENCIES_HZ),
+            ],
+            bands: std::array::from_fn(|_| band(rng)),
+        },
+        mid_side: MidSideSettings {
+            channels: rng.int(0, 3),
+            key: rng.int(0, 2),
+            side: band(rng),
+        },
+    }
+}
+
+#[test]
+fn processing_does_not_allocate() {
+    let max_block_size = *BLOCK_SIZES.last().unwrap();
+    let main_input = noise(1, 0.5, max_block_size);
+    let side_input = noise(2, 0.5, max_block_size);
+    let curves = [
+        Curve::default(),
+        Curve::from_points(&[]),
+        Curve::from_points(
+            &(0..MAX_POINTS)
+                .map(|idx| Breakpoint::new(idx as f32 / MAX_POINTS as f32, (idx % 2) as f32, 0.7))
+                .collect::<Vec<_>>(),
+        ),
+    ];
+
+    // Preparing is allowed to allocate, just like the plugin's `initialize()`
+    let mut processors = SAMPLE_RATES
+        .iter()
+        .map(|&sample_rate| {
+            let mut processor = Processor::new();
+            processor.prepare(sample_rate, max_block_size);
+            processor
+        })
+        .collect::<Vec<_>>();
+
+    for mode in 0..=mode::MAX {
+        for (rate_idx, (&sample_rate, processor)) in
+            SAMPLE_RATES.iter().zip(&mut processors).enumerate()
+        {
+            for (size_idx, &block_size) in BLOCK_SIZES.iter().enumerate() {
+                let seed =
+                    (mode as usize * SAMPLE_RATES.len() + rate_idx) * BLOCK_SIZES.len() + size_idx;
+                let mut rng = Rng::new(seed);
+                // Picking the settings allocates, so that happens up front
+                let sweep: Vec<Settings> = (0..NUM_BLOCKS)
+                    .map(|_| random_settings(mode, &mut rng))
+                    .collect();
+
+                let mut main = main_input.clone();
+                let (left, right) = main.split_at_mut(1);
+                let mut main_block = [&mut left[0][..block_size], &mut right[0][..block_size]];
+                let side_block = [&side_input[0][..block_size], &side_input[1][..block_size]];
+
+                assert_no_allocations(
+                    &format!(
+                        "mode {mode} at {sample_rate} Hz with {block_size} samples (seed {seed})"
+                    ),
+                    || {
+                        processor.set_settings(sweep[0]);
+                        processor.reset();
+
+                        for (block_idx, &settings) in sweep.iter().enumerate() {
+                            processor.set_settings(settings);
+                            processor.set_curve(&curves[block_idx % curves.len()]);
+                            // Some blocks get more events than fit in the queue
+                            let num_events = block_idx * MAX_NOTE_EVENTS / 3;
+                            for event_idx in 0..num_events {
+                                let timing = (event_idx * block_size / num_events) as u32;
+                                let note = (event_idx % 128) as u8;
+                                processor.queue_note_event(if event_idx % 3 == 2 {
+                                    NoteEvent::Off { timing, note }
+                                } else {
+                                    NoteEvent::On {
+                                        timing,
+                                        note,
+                                        velocity: 0.8,
+                                    }
+                                });
+                            }
+                            processor.set_transport(Transport {
+                                playing: block_idx % 2 == 0,
+                                tempo: Some(60.0 + block_idx as f64 * 30.0),
+                                pos_beats: Some(block_idx as f64 * 0.3),
+                                time_sig_numerator: Some(3 + block_idx as i32 % 5),
+                                time_sig_denominator: Some(4),
+                            });
+                            processor.process(&mut main_block, &side_block);
+                        }
+                    },
+                );
+            }
+        }
+    }
+
+    // Switching modes mid-stream should not allocate either
+    for (sample_rate, processor) in SAMPLE_RATES.iter().zip(&mut processors) {
+        assert_no_allocations(&format!("switching modes at {sample_rate} Hz"), || {
+            let mut main_block = [&mut [0.0; 64][..], &mut [0.0; 64][..]];
+            let side_block = [&side_input[0][..64], &side_input[1][..64]];
+            for mode in (0..=mode::MAX).chain((0..=mode::MAX).rev()) {
+                processor.set_settings(Settings {
+                    mode,
+                    ..Settings::default()
+                });
+                processor.process(&mut main_block, &side_block);
+            }
+        });
+    }
+}
+
+#[test]
+fn envelope_follower_does_not_allocate() {
+    let input = noise(3, 1.0, 4096);
+    let mut follower = SimpleEnvelopeFollower::new(10);
+
+    assert_no_allocations("SimpleEnvelopeFollower", || {
+        for (idx, sample) in input[0].iter().enumerate() {
+            if idx % 256 == 0 {
+                follower.set_size(5 + (idx as i32 * 7) % MAX_ENVELOPE_FOLLOWER_SIZE as i32);
+            }
+            follower.process(*sample);
+        }
+    });
+}
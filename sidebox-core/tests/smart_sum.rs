@@ -0,0 +1,79 @@
+// The smart sum should rotate the sidechain's phase so its low end adds up with the main signal's
+// instead of cancelling it
+
+mod common;
+
+use std::f32::consts::PI;
+
+use common::{amplitude_at, render, with_mode, Stereo, BLOCK_SIZE, SAMPLE_RATE};
+use sidebox_core::smart_sum::SmartSumSettings;
+use sidebox_core::{mode, Settings};
+
+/// Learning the rotation takes longer than most tests run for.
+const LEN: usize = 96000;
+/// The rotation has settled well before this point.
+const SETTLED: usize = LEN / 2;
+const FREQUENCY: f32 = 60.0;
+
+/// A low sine with both channels starting at `phase`.
+fn tone(phase: f32) -> Stereo {
+    let channel = (0..LEN)
+        .map(|idx| (2.0 * PI * FREQUENCY * idx as f32 / SAMPLE_RATE + phase).sin() * 0.5)
+        .collect::<Vec<_>>();
+
+    [channel.clone(), channel]
+}
+
+/// The level of the tone in the left channel after the rotation has settled, in decibels relative
+/// to the two tones adding up perfectly.
+fn level_db(output: &Stereo) -> f32 {
+    20.0 * amplitude_at(output, FREQUENCY, SETTLED).log10()
+}
+
+#[test]
+fn lines_up_the_low_end() {
+    // Plain addition would cancel the inverted sidechain completely
+    for phase in [PI / 2.0, -2.0, PI] {
+        let output = render(
+            with_mode(mode::SMART_SUM),
+            &tone(0.0),
+            &tone(phase),
+            BLOCK_SIZE,
+        );
+
+        let level_db = level_db(&output);
+        assert!(
+            level_db.abs() < 0.5,
+            "with the sidechain at {phase} radians the sum is at {level_db} dB"
+        );
+    }
+}
+
+#[test]
+fn manual_rotation_is_used_without_auto() {
+    let manual = |rotation_degrees: f32| {
+        let output = render(
+            Settings {
+                smart_sum: SmartSumSettings {
+                    auto: false,
+                    rotation_degrees,
+                    ..SmartSumSettings::default()
+                },
+                ..with_mode(mode::SMART_SUM)
+            },
+            &tone(0.0),
+            &tone(0.0),
+            BLOCK_SIZE,
+        );
+
+        level_db(&output)
+    };
+
+    let unrotated_db = manual(0.0);
+    assert!(unrotated_db.abs() < 0.5, "the sum is at {unrotated_db} dB");
+    let flipped_db = manual(180.0);
+    assert!(
+        flipped_db < -60.0,
+        "the flipped sidechain is at {flipped_db} dB"
+    );
+}
@@ -0,0 +1,180 @@
+// The spectral modes should only touch the parts of the spectrum they're supposed to
+
+mod common;
+
+use common::{amplitude_at, mix, noise, render, silence, sine, with_mode, Stereo, BLOCK_SIZE};
+use sidebox_core::spectral_duck::SpectralDuckSettings;
+use sidebox_core::spectral_morph::{self, SpectralMorphSettings};
+use sidebox_core::stft::FFT_SIZE;
+use sidebox_core::{mode, Settings};
+
+const LEN: usize = FFT_SIZE * 8;
+/// The envelopes have settled after the first couple of frames.
+const SETTLE: usize = FFT_SIZE * 2;
+
+fn spectral_morph(amount: f32, morph: i32) -> Settings {
+    Settings {
+        spectral_morph: SpectralMorphSettings { amount, morph },
+        ..with_mode(mode::SPECTRAL_MORPH)
+    }
+}
+
+fn scale(signal: &Stereo, gain: f32) -> Stereo {
+    let channel = |idx: usize| signal[idx].iter().map(|sample| sample * gain).collect();
+
+    [channel(0), channel(1)]
+}
+
+/// Check that `output` matches `expected` after the first frames, which are still fading in.
+fn assert_reconstructs(output: &Stereo, expected: &Stereo, tolerance: f32) {
+    for (channel_idx, (output, expected)) in output.iter().zip(expected).enumerate() {
+        for (idx, (output, expected)) in output.iter().zip(expected).enumerate().skip(FFT_SIZE) {
+            assert!(
+                (output - expected).abs() < tolerance,
+                "channel {channel_idx}, sample {idx}: expected {expected}, got {output}"
+            );
+        }
+    }
+}
+
+#[test]
+fn silent_sidechain_reconstructs_the_input() {
+    let main = mix(&sine(440.0, 0.5, LEN), &sine(3000.0, 0.25, LEN));
+    let output = render(
+        with_mode(mode::SPECTRAL_DUCK),
+        &main,
+        &silence(LEN),
+        BLOCK_SIZE,
+    );
+
+    assert_reconstructs(&output, &main, 1e-4);
+}
+
+#[test]
+fn only_attenuates_where_the_sidechain_has_energy() {
+    let main = mix(&sine(1000.0, 0.5, LEN), &sine(5000.0, 0.5, LEN));
+    let side = sine(1000.0, 1.0, LEN);
+    let output = render(
+        Settings {
+            spectral_duck: SpectralDuckSettings {
+                depth_db: 24.0,
+                smoothing_octaves: 0.0,
+                ..SpectralDuckSettings::default()
+            },
+            ..with_mode(mode::SPECTRAL_DUCK)
+        },
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+
+    let ducked_db = 20.0 * (amplitude_at(&output, 1000.0, SETTLE) / 0.5).log10();
+    let untouched_db = 20.0 * (amplitude_at(&output, 5000.0, SETTLE) / 0.5).log10();
+    assert!(
+        ducked_db < -12.0,
+        "1 kHz was only attenuated by {ducked_db} dB"
+    );
+    assert!(
+        untouched_db.abs() < 0.5,
+        "5 kHz changed by {untouched_db} dB"
+    );
+}
+
+#[test]
+fn frequency_range_limits_the_attenuation() {
+    let main = sine(1000.0, 0.5, LEN);
+    let output = render(
+        Settings {
+            spectral_duck: SpectralDuckSettings {
+                depth_db: 24.0,
+                low_hz: 2000.0,
+                high_hz: 20000.0,
+                ..SpectralDuckSettings::default()
+            },
+            ..with_mode(mode::SPECTRAL_DUCK)
+        },
+        &main,
+        &sine(1000.0, 1.0, LEN),
+        BLOCK_SIZE,
+    );
+
+    let change_db = 20.0 * (amplitude_at(&output, 1000.0, SETTLE) / 0.5).log10();
+    assert!(change_db.abs() < 0.5, "1 kHz changed by {change_db} dB");
+}
+
+#[test]
+fn morph_amount_goes_from_main_to_sidechain() {
+    let main = mix(&sine(440.0, 0.5, LEN), &sine(3000.0, 0.25, LEN));
+    let side = noise(1, 0.5, LEN);
+    for morph in [
+        spectral_morph::MORPH_BOTH,
+        spectral_morph::MORPH_MAGNITUDE,
+        spectral_morph::MORPH_PHASE,
+    ] {
+        let output = render(spectral_morph(0.0, morph), &main, &side, BLOCK_SIZE);
+        assert_reconstructs(&output, &main, 1e-4);
+    }
+
+    let output = render(
+        spectral_morph(1.0, spectral_morph::MORPH_BOTH),
+        &main,
+        &side,
+        BLOCK_SIZE,
+    );
+    assert_reconstructs(&output, &side, 1e-4);
+}
+
+#[test]
+fn magnitudes_are_morphed_in_the_log_domain() {
+    // Halfway between two copies of a signal is their geometric mean
+    let main = noise(1, 0.5, LEN);
+    let output = render(
+        spectral_morph(0.5, spectral_morph::MORPH_BOTH),
+        &main,
+        &scale(&main, 0.25),
+        BLOCK_SIZE,
+    );
+
+    assert_reconstructs(&output, &scale(&main, 0.5), 1e-4);
+}
+
+#[test]
+fn magnitude_morph_keeps_the_main_phases() {
+    let main = mix(&sine(1000.0, 0.5, LEN), &sine(5000.0, 0.5, LEN));
+    let output = render(
+        spectral_morph(1.0, spectral_morph::MORPH_MAGNITUDE),
+        &main,
+        &sine(1000.0, 1.0, LEN),
+        BLOCK_SIZE,
+    );
+
+    // The sidechain's spectrum, which has nothing at 5 kHz
+    let kept_db = 20.0 * amplitude_at(&output, 1000.0, SETTLE).log10();
+    let removed_db = 20.0 * amplitude_at(&output, 5000.0, SETTLE).log10();
+    assert!(kept_db.abs() < 0.5, "1 kHz is at {kept_db} dB");
+    assert!(removed_db < -40.0, "5 kHz is still at {removed_db} dB");
+}
+
+#[test]
+fn phase_morph_keeps_the_main_magnitudes() {
+    // With the sidechain's channels swapped, its left channel has the main signal's right
+    // channel's phase and the other way around
+    let main = sine(1000.0, 0.5, LEN);
+    let [left, right] = sine(1000.0, 1.0, LEN);
+    let output = render(
+        spectral_morph(1.0, spectral_morph::MORPH_PHASE),
+        &main,
+        &[right, left],
+        BLOCK_SIZE,
+    );
+
+    // Leakage from the negative frequencies doesn't rotate the same way as the sine itself, so this
+    // is only approximately the main signal's other channel. The last frames, where the signals
+    // stop, are skipped as well.
+    let end = LEN - FFT_SIZE;
+    assert_reconstructs(
+        &[output[0][..end].to_vec(), output[1][..end].to_vec()],
+        &[main[1][..end].to_vec(), main[0][..end].to_vec()],
+        2e-3,
+    );
+}
@@ -0,0 +1,122 @@
+// The stutter should capture the main signal at sidechain transients and loop it until released
+
+mod common;
+
+use common::{noise, render, with_mode, Stereo, BLOCK_SIZE, LEN};
+use sidebox_core::ghost;
+use sidebox_core::stutter::StutterSettings;
+use sidebox_core::{mode, Settings};
+
+/// Where the sidechain's first transient is.
+const TRIGGER: usize = 1000;
+
+/// A sidechain at `level` within each of the ranges, and silent everywhere else.
+fn sidechain(ranges: &[(usize, usize, f32)]) -> Stereo {
+    let mut channel = vec![0.0; LEN];
+    for &(start, end, level) in ranges {
+        channel[start..end].fill(level);
+    }
+
+    [channel.clone(), channel]
+}
+
+#[test]
+fn transient_captures_and_loops_the_main_signal() {
+    let main = noise(1, 0.5, LEN);
+    let side = sidechain(&[(TRIGGER, LEN, 0.5)]);
+    let loop_len = 480;
+    let settings = Settings {
+        stutter: StutterSettings {
+            length_ms: 10.0,
+            crossfade_ms: 0.0,
+            ..StutterSettings::default()
+        },
+        ..with_mode(mode::STUTTER)
+    };
+    let output = render(settings, &main, &side, BLOCK_SIZE);
+
+    for (channel, main) in output.iter().zip(&main) {
+        assert_eq!(channel[..TRIGGER + loop_len], main[..TRIGGER + loop_len]);
+        for idx in TRIGGER + loop_len..LEN {
+            assert_eq!(
+                channel[idx],
+                main[TRIGGER + (idx - TRIGGER) % loop_len],
+                "sample {idx}"
+            );
+        }
+    }
+}
+
+#[test]
+fn silent_sidechain_releases_the_loop() {
+    let main = noise(1, 0.5, LEN);
+    let release = 10000;
+    let side = sidechain(&[(TRIGGER, release, 0.5)]);
+    let output = render(with_mode(mode::STUTTER), &main, &side, BLOCK_SIZE);
+
+    for (channel, main) in output.iter().zip(&main) {
+        // Still looping while the sidechain's level is falling off
+        assert_ne!(
+            channel[release + 100..release + 200],
+            main[release + 100..release + 200]
+        );
+        // The main signal passes through again 150 ms after the sidechain went silent
+        assert_eq!(channel[release + 7200..], main[release + 7200..]);
+    }
+}
+
+#[test]
+fn new_transients_capture_a_new_loop() {
+    let main = noise(1, 0.5, LEN);
+    let retrigger = 10000;
+    // The sidechain drops below the rearm threshold without going silent in between
+    let side = sidechain(&[
+        (TRIGGER, 5000, 0.5),
+        (5000, retrigger, 0.02),
+        (retrigger, LEN, 0.5),
+    ]);
+    let loop_len = 480;
+    let settings = Settings {
+        stutter: StutterSettings {
+            length_ms: 10.0,
+            crossfade_ms: 0.0,
+            ..StutterSettings::default()
+        },
+        ..with_mode(mode::STUTTER)
+    };
+    let output = render(settings, &main, &side, BLOCK_SIZE);
+
+    for (channel, main) in output.iter().zip(&main) {
+        assert_eq!(
+            channel[retrigger..retrigger + loop_len],
+            main[retrigger..retrigger + loop_len]
+        );
+        for idx in retrigger + loop_len..LEN {
+            assert_eq!(channel[idx], main[retrigger + (idx - retrigger) % loop_len]);
+        }
+    }
+}
+
+#[test]
+fn synced_loops_follow_the_tempo() {
+    let main = noise(1, 0.5, LEN);
+    let side = sidechain(&[(TRIGGER, LEN, 0.5)]);
+    // Without a tempo from the host this uses 120 BPM, so an eighth note is 250 ms
+    let loop_len = 12000;
+    let settings = Settings {
+        stutter: StutterSettings {
+            sync: true,
+            division: ghost::division_from_name("1/8").unwrap(),
+            crossfade_ms: 0.0,
+            ..StutterSettings::default()
+        },
+        ..with_mode(mode::STUTTER)
+    };
+    let output = render(settings, &main, &side, BLOCK_SIZE);
+
+    for (channel, main) in output.iter().zip(&main) {
+        for idx in TRIGGER + loop_len..LEN {
+            assert_eq!(channel[idx], main[TRIGGER + (idx - TRIGGER) % loop_len]);
+        }
+    }
+}
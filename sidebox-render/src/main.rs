@@ -0,0 +1,635 @@
+// Renders a main and a sidechain WAV file through Sidebox's combination modes without a DAW
+
+use std::f64::consts::PI;
+use std::fs;
+use std::process::ExitCode;
+
+use sidebox_core::alignment;
+use sidebox_core::multiband::MAX_BANDS;
+use sidebox_core::{Processor, Settings, Transport};
+
+const USAGE: &str = "\
+usage: sidebox-render <main.wav> <sidechain.wav> <output.wav> [options]
+
+options:
+  --preset <file>        read parameters from a file with one `name = value` pair per line
+  --param <name=value>   set a single parameter, overrides values from the preset
+  --length <mode>        what to do when the inputs have different lengths:
+                           pad   extend the shorter input with silence (default)
+                           loop  loop the sidechain for the length of the main input
+                           trim  stop at the end of the shorter input
+  --block-size <n>       number of samples processed at a time (default 512)
+  --tempo <bpm>          the tempo the ghost key source and synced stutter follow, starting at the
+                         first sample (default 120)
+  --float                write 32-bit floating point samples, which keeps anything past full
+                         scale, instead of using the main input's sample format
+
+parameters:
+  mode                          a mode number or name, e.g. `mode=gate`
+  input_gain                    in dB
+  sidechain_input_gain          in dB
+  output_gain                   in dB
+  sidechain_phase_flip          0 or 1
+  envelope_follower_smoothing   5-1000, smooths the envelope filter's envelope
+  lookahead                     in ms, only used by detection modes like the gate
+  key_source                    `sidechain` or `ghost`, the renderer has no MIDI input
+  detection_link                `independent`, `max`, `average`, or `sum`
+  detection_link_amount         in percent
+  align_learn                   0 or 1, learns the summing modes' alignment while rendering
+  align_manual                  0 or 1, 1 uses `align_offset` instead of the learned offset
+  align_offset                  in ms, positive when the sidechain is late, negative when it's early
+  smart_auto                    0 or 1, 1 finds the phase rotation automatically
+  smart_rotation                in degrees, used when `smart_auto=0`
+  smart_cutoff                  in Hz, only frequencies below this are lined up
+  smart_response                in ms, how quickly the automatic rotation follows the signals
+  gate_threshold                in dB
+  gate_hysteresis               in dB
+  gate_attack                   in ms
+  gate_hold                     in ms
+  gate_release                  in ms
+  gate_range                    in dB
+  ghost_division                a division like `1/8`, `1/8 dotted`, `1/16 triplet` or `1 bar`
+  ghost_shape                   `ramp` or `drawn`, drawn uses the plugin's default curve
+  ghost_length                  in percent of the division
+  spectral_depth                in dB
+  spectral_low                  in Hz
+  spectral_high                 in Hz
+  spectral_attack               in ms
+  spectral_release              in ms
+  spectral_smoothing            in octaves
+  morph_amount                  in percent, from the main signal to the sidechain
+  morph_type                    `magnitude and phase`, `magnitude` or `phase`
+  denoise_method                `subtraction` or `wiener`
+  denoise_reduction             in dB, the most any frequency is attenuated by
+  denoise_smoothing             in ms, how quickly the noise profile follows the sidechain
+  denoise_musical_noise         in percent
+  ring_transpose                in semitones, relative to the sidechain's pitch
+  ring_glide                    in ms
+  ring_min_confidence           in percent
+  filter_type                   `low-pass`, `band-pass` or `high-pass`
+  filter_cutoff                 in Hz, while the sidechain is silent
+  filter_depth                  in octaves at full scale, negative depths sweep down
+  filter_resonance              the filter's Q
+  filter_attack                 in ms
+  filter_release                in ms
+  stutter_trigger               `level` or `onset`, onsets use the `onset_*` settings
+  stutter_threshold             in dB, for the level trigger
+  stutter_sync                  0 or 1, 1 uses `stutter_division` instead of `stutter_length`
+  stutter_length                in ms
+  stutter_division              a division like `ghost_division`
+  stutter_crossfade             in ms
+  stutter_release               in dB, loops end once the sidechain stays below this level
+  bleed_step                    the adaptive filter's step size, from 0 to 1
+  bleed_length                  in ms
+  bleed_freeze                  0 or 1, 1 stops the adaptation
+  switch_threshold              in dB, how much louder the sidechain needs to be to switch to it
+  switch_release                in ms
+  switch_crossfade              in ms
+  bitwise_bits                  4-24, the bit depth for the bitwise modes
+  bitwise_dither                `none`, `rectangular` or `triangular`
+  bitwise_smoothing             in Hz, 20000 and above turns the smoothing off
+  onset_method                  `spectral flux` or `high frequency content`
+  onset_threshold               a factor over the detection function's recent median
+  onset_min_interval            in ms
+  bands                         1-4, more than one band splits the inputs and uses the band modes
+                                instead of `mode`
+  crossover_1, crossover_2, crossover_3
+                                in Hz, from low to high
+  band_<n>_mode                 the mode for band n, counting from 1 at the lowest band
+  band_<n>_input_gain           in dB
+  band_<n>_sidechain_input_gain in dB
+  band_<n>_output_gain          in dB
+  mid_side_channels             `left/right`, `mid`, `side`, or `mid/side` to process both components
+  mid_side_key                  `matching`, `mid`, or `side`
+  side_mode                     the side component's mode with `mid_side_channels=mid/side`
+  side_input_gain               in dB
+  side_sidechain_input_gain     in dB
+  side_output_gain              in dB
+
+The sidechain is resampled to the main input's sample rate. Mono inputs are processed as stereo,
+and the output has the same number of channels as the main input. Unless `--float` is used, the
+output also has the main input's bit depth, and integer output is clipped at full scale.";
+
+/// The plugin only has a stereo layout, so everything gets processed as stereo.
+const NUM_CHANNELS: usize = 2;
+
+/// The number of zero crossings on either side of the resampling filter's center.
+const RESAMPLER_ZERO_CROSSINGS: f64 = 32.0;
+/// The resampling filter's cutoff relative to the lower of the two Nyquist frequencies. The rest is
+/// the filter's transition band.
+const RESAMPLER_CUTOFF: f64 = 0.95;
+/// The resampling filter is tabulated at this many points per input sample and linearly
+/// interpolated in between.
+const RESAMPLER_OVERSAMPLING: f64 = 512.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LengthMode {
+    Pad,
+    Loop,
+    Trim,
+}
+
+struct Args {
+    main_path: String,
+    sidechain_path: String,
+    output_path: String,
+    settings: Settings,
+    length_mode: LengthMode,
+    block_size: usize,
+    tempo: f64,
+    /// Write 32-bit float samples instead of using the main input's sample format.
+    float_output: bool,
+}
+
+/// Deinterleaved audio with `NUM_CHANNELS` channels.
+struct Audio {
+    sample_rate: u32,
+    /// The number of channels in the original file.
+    num_file_channels: u16,
+    /// The original file's sample format, which the output uses as well.
+    sample_format: hound::SampleFormat,
+    bits_per_sample: u16,
+    channels: Vec<Vec<f32>>,
+}
+
+fn main() -> ExitCode {
+    if std::env::args().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+
+    match parse_args(std::env::args().skip(1)).and_then(|args| render(&args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}\n\n{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut paths = Vec::new();
+    let mut preset_path = None;
+    let mut overrides = Vec::new();
+    let mut length_mode = LengthMode::Pad;
+    let mut block_size = 512;
+    let mut tempo = 120.0;
+    let mut float_output = false;
+
+    while let Some(arg) = args.next() {
+        let mut value_for = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("'{option}' requires a value"))
+        };
+
+        match arg.as_str() {
+            "--preset" => preset_path = Some(value_for("--preset")?),
+            "--param" => overrides.push(value_for("--param")?),
+            "--length" => {
+                length_mode = match value_for("--length")?.as_str() {
+                    "pad" => LengthMode::Pad,
+                    "loop" => LengthMode::Loop,
+                    "trim" => LengthMode::Trim,
+                    other => return Err(format!("unknown length mode '{other}'")),
+                }
+            }
+            "--block-size" => {
+                block_size = value_for("--block-size")?
+                    .parse()
+                    .ok()
+                    .filter(|&size| size > 0)
+                    .ok_or("the block size must be a positive integer")?
+            }
+            "--tempo" => {
+                tempo = value_for("--tempo")?
+                    .parse()
+                    .ok()
+                    .filter(|&tempo: &f64| tempo > 0.0)
+                    .ok_or("the tempo must be a positive number")?
+            }
+            "--float" => float_output = true,
+            option if option.starts_with("--") => return Err(format!("unknown option '{option}'")),
+            _ => paths.push(arg),
+        }
+    }
+
+    let [main_path, sidechain_path, output_path]: [String; 3] = paths
+        .try_into()
+        .map_err(|_| "expected a main input, a sidechain input, and an output path")?;
+
+    let mut settings = Settings::default();
+    if let Some(preset_path) = preset_path {
+        let preset = fs::read_to_string(&preset_path)
+            .map_err(|err| format!("could not read '{preset_path}': {err}"))?;
+        for line in preset.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if !line.is_empty() {
+                set_param(&mut settings, line)?;
+            }
+        }
+    }
+    for assignment in &overrides {
+        set_param(&mut settings, assignment)?;
+    }
+
+    Ok(Args {
+        main_path,
+        sidechain_path,
+        output_path,
+        settings,
+        length_mode,
+        block_size,
+        tempo,
+        float_output,
+    })
+}
+
+/// Apply a `name=value` assignment to `settings`. Gains are specified in decibels.
+fn set_param(settings: &mut Settings, assignment: &str) -> Result<(), String> {
+    let (name, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| format!("expected 'name=value', got '{assignment}'"))?;
+    let (name, value) = (name.trim(), value.trim());
+
+    let float = || {
+        value
+            .parse::<f32>()
+            .map_err(|_| format!("'{value}' is not a valid value for '{name}'"))
+    };
+    let int = || {
+        value
+            .parse::<i32>()
+            .map_err(|_| format!("'{value}' is not a valid value for '{name}'"))
+    };
+    let db_to_gain = |db: f32| 10.0f32.powf(db / 20.0);
+
+    if let Some(band_param) = name.strip_prefix("band_") {
+        let (number, param) = band_param
+            .split_once('_')
+            .ok_or_else(|| format!("unknown parameter '{name}'"))?;
+        let band = number
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| settings.multiband.bands.get_mut(number.checked_sub(1)?))
+            .ok_or_else(|| format!("'{number}' is not a valid band number in '{name}'"))?;
+        match param {
+            "mode" => {
+                band.mode = sidebox_core::mode::from_name(value)
+                    .ok_or_else(|| format!("'{value}' is not a valid mode"))?
+            }
+            "input_gain" => band.input_gain = db_to_gain(float()?),
+            "sidechain_input_gain" => band.sidechain_input_gain = db_to_gain(float()?),
+            "output_gain" => band.output_gain = db_to_gain(float()?),
+            _ => return Err(format!("unknown parameter '{name}'")),
+        }
+
+        return Ok(());
+    }
+
+    match name {
+        "mode" => {
+            settings.mode = sidebox_core::mode::from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid mode"))?
+        }
+        "input_gain" => settings.input_gain = db_to_gain(float()?),
+        "sidechain_input_gain" => settings.sidechain_input_gain = db_to_gain(float()?),
+        "output_gain" => settings.output_gain = db_to_gain(float()?),
+        "sidechain_phase_flip" => settings.sidechain_phase_flip = int()? != 0,
+        "envelope_follower_smoothing" => settings.envelope_follower_smoothing = int()?,
+        "lookahead" => settings.lookahead_ms = float()?,
+        "key_source" => {
+            settings.key_source = sidebox_core::key_source::from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid key source"))?
+        }
+        "detection_link" => {
+            settings.detection_link = sidebox_core::detection_link::from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid detection link"))?
+        }
+        "detection_link_amount" => settings.detection_link_amount = float()? / 100.0,
+        "align_learn" => settings.alignment.learn = int()? != 0,
+        "align_manual" => settings.alignment.manual = int()? != 0,
+        "align_offset" => settings.alignment.offset_ms = float()?,
+        "smart_auto" => settings.smart_sum.auto = int()? != 0,
+        "smart_rotation" => settings.smart_sum.rotation_degrees = float()?,
+        "smart_cutoff" => settings.smart_sum.cutoff_hz = float()?,
+        "smart_response" => settings.smart_sum.response_ms = float()?,
+        "gate_threshold" => settings.gate.threshold_db = float()?,
+        "gate_hysteresis" => settings.gate.hysteresis_db = float()?,
+        "gate_attack" => settings.gate.attack_ms = float()?,
+        "gate_hold" => settings.gate.hold_ms = float()?,
+        "gate_release" => settings.gate.release_ms = float()?,
+        "gate_range" => settings.gate.range_db = float()?,
+        "ghost_division" => {
+            settings.ghost.division = sidebox_core::ghost::division_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid division"))?
+        }
+        "ghost_shape" => {
+            settings.ghost.shape = sidebox_core::ghost::shape_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid shape"))?
+        }
+        "ghost_length" => settings.ghost.length = float()? / 100.0,
+        "spectral_depth" => settings.spectral_duck.depth_db = float()?,
+        "spectral_low" => settings.spectral_duck.low_hz = float()?,
+        "spectral_high" => settings.spectral_duck.high_hz = float()?,
+        "spectral_attack" => settings.spectral_duck.attack_ms = float()?,
+        "spectral_release" => settings.spectral_duck.release_ms = float()?,
+        "spectral_smoothing" => settings.spectral_duck.smoothing_octaves = float()?,
+        "morph_amount" => settings.spectral_morph.amount = float()? / 100.0,
+        "morph_type" => {
+            settings.spectral_morph.morph = sidebox_core::spectral_morph::morph_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid morph type"))?
+        }
+        "denoise_method" => {
+            settings.spectral_denoise.method =
+                sidebox_core::spectral_denoise::method_from_name(value)
+                    .ok_or_else(|| format!("'{value}' is not a valid denoise method"))?
+        }
+        "denoise_reduction" => settings.spectral_denoise.reduction_db = float()?,
+        "denoise_smoothing" => settings.spectral_denoise.smoothing_ms = float()?,
+        "denoise_musical_noise" => settings.spectral_denoise.musical_noise = float()? / 100.0,
+        "ring_transpose" => settings.ring_mod.transpose_semitones = float()?,
+        "ring_glide" => settings.ring_mod.glide_ms = float()?,
+        "ring_min_confidence" => settings.ring_mod.min_confidence = float()? / 100.0,
+        "filter_type" => {
+            settings.envelope_filter.filter = sidebox_core::envelope_filter::filter_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid filter type"))?
+        }
+        "filter_cutoff" => settings.envelope_filter.cutoff_hz = float()?,
+        "filter_depth" => settings.envelope_filter.depth_octaves = float()?,
+        "filter_resonance" => settings.envelope_filter.resonance = float()?,
+        "filter_attack" => settings.envelope_filter.attack_ms = float()?,
+        "filter_release" => settings.envelope_filter.release_ms = float()?,
+        "stutter_trigger" => {
+            settings.stutter.trigger = sidebox_core::stutter::trigger_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid stutter trigger"))?
+        }
+        "stutter_threshold" => settings.stutter.threshold_db = float()?,
+        "stutter_sync" => settings.stutter.sync = int()? != 0,
+        "stutter_length" => settings.stutter.length_ms = float()?,
+        "stutter_division" => {
+            settings.stutter.division = sidebox_core::ghost::division_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid division"))?
+        }
+        "stutter_crossfade" => settings.stutter.crossfade_ms = float()?,
+        "stutter_release" => settings.stutter.release_db = float()?,
+        "bleed_step" => settings.bleed_cancel.step_size = float()?,
+        "bleed_length" => settings.bleed_cancel.length_ms = float()?,
+        "bleed_freeze" => settings.bleed_cancel.freeze = int()? != 0,
+        "switch_threshold" => settings.threshold_switch.threshold_db = float()?,
+        "switch_release" => settings.threshold_switch.release_ms = float()?,
+        "switch_crossfade" => settings.threshold_switch.crossfade_ms = float()?,
+        "bitwise_bits" => settings.bitwise.bits = int()?,
+        "bitwise_dither" => {
+            settings.bitwise.dither = sidebox_core::bitwise::dither_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid dither"))?
+        }
+        "bitwise_smoothing" => settings.bitwise.smoothing_hz = float()?,
+        "onset_method" => {
+            settings.onset.method = sidebox_core::onset::method_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid onset method"))?
+        }
+        "onset_threshold" => settings.onset.threshold = float()?,
+        "onset_min_interval" => settings.onset.min_interval_ms = float()?,
+        "bands" => {
+            settings.multiband.num_bands = usize::try_from(int()?)
+                .ok()
+                .filter(|num_bands| (1..=MAX_BANDS).contains(num_bands))
+                .ok_or_else(|| format!("'{value}' is not a valid number of bands"))?
+        }
+        "crossover_1" => settings.multiband.crossovers_hz[0] = float()?,
+        "crossover_2" => settings.multiband.crossovers_hz[1] = float()?,
+        "crossover_3" => settings.multiband.crossovers_hz[2] = float()?,
+        "mid_side_channels" => {
+            settings.mid_side.channels = sidebox_core::mid_side::channels_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid channel setting"))?
+        }
+        "mid_side_key" => {
+            settings.mid_side.key = sidebox_core::mid_side::key_from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid mid/side key"))?
+        }
+        "side_mode" => {
+            settings.mid_side.side.mode = sidebox_core::mode::from_name(value)
+                .ok_or_else(|| format!("'{value}' is not a valid mode"))?
+        }
+        "side_input_gain" => settings.mid_side.side.input_gain = db_to_gain(float()?),
+        "side_sidechain_input_gain" => {
+            settings.mid_side.side.sidechain_input_gain = db_to_gain(float()?)
+        }
+        "side_output_gain" => settings.mid_side.side.output_gain = db_to_gain(float()?),
+        _ => return Err(format!("unknown parameter '{name}'")),
+    }
+
+    Ok(())
+}
+
+fn render(args: &Args) -> Result<(), String> {
+    let mut main = read_wav(&args.main_path)?;
+    let mut sidechain = read_wav(&args.sidechain_path)?;
+    if sidechain.sample_rate != main.sample_rate {
+        for channel in sidechain.channels.iter_mut() {
+            *channel = resample(channel, sidechain.sample_rate, main.sample_rate);
+        }
+        sidechain.sample_rate = main.sample_rate;
+    }
+
+    let main_len = main.channels[0].len();
+    let sidechain_len = sidechain.channels[0].len();
+    let output_len = match args.length_mode {
+        LengthMode::Pad => main_len.max(sidechain_len),
+        LengthMode::Loop => main_len,
+        LengthMode::Trim => main_len.min(sidechain_len),
+    };
+    for channel in main.channels.iter_mut() {
+        channel.resize(output_len, 0.0);
+    }
+    for channel in sidechain.channels.iter_mut() {
+        if args.length_mode == LengthMode::Loop && sidechain_len > 0 {
+            *channel = channel.iter().copied().cycle().take(output_len).collect();
+        } else {
+            channel.resize(output_len, 0.0);
+        }
+    }
+
+    let mut processor = Processor::new();
+    processor.prepare(main.sample_rate as f32, args.block_size);
+    processor.set_settings(args.settings);
+    processor.reset();
+
+    // Lookahead delays the output, so the inputs are extended by that many samples and the start of
+    // the output is cut off again afterwards to keep everything lined up with the input files.
+    // Learning the alignment can change the latency halfway through, in which case the output lines
+    // up with the inputs as of the end of the files.
+    let max_latency = processor.latency_samples() as usize
+        + alignment::max_delay_samples(main.sample_rate as f32);
+    let processed_len = output_len + max_latency;
+    for channel in main
+        .channels
+        .iter_mut()
+        .chain(sidechain.channels.iter_mut())
+    {
+        channel.resize(processed_len, 0.0);
+    }
+
+    let (main_left, main_right) = main.channels.split_at_mut(1);
+    let mut block_start = 0;
+    while block_start < processed_len {
+        let block_end = (block_start + args.block_size).min(processed_len);
+
+        let mut main_block = [
+            &mut main_left[0][block_start..block_end],
+            &mut main_right[0][block_start..block_end],
+        ];
+        let sidechain_block = [
+            &sidechain.channels[0][block_start..block_end],
+            &sidechain.channels[1][block_start..block_end],
+        ];
+        // Like the sidechain input, the transport isn't delayed by the lookahead
+        let seconds = block_start as f64 / main.sample_rate as f64;
+        processor.set_transport(Transport {
+            playing: true,
+            tempo: Some(args.tempo),
+            pos_beats: Some(seconds * args.tempo / 60.0),
+            ..Transport::default()
+        });
+        processor.process(&mut main_block, &sidechain_block);
+
+        block_start = block_end;
+    }
+
+    let latency = processor.latency_samples() as usize;
+    for channel in main.channels.iter_mut() {
+        channel.drain(..latency);
+        channel.truncate(output_len);
+    }
+    if args.float_output {
+        main.sample_format = hound::SampleFormat::Float;
+        main.bits_per_sample = 32;
+    }
+
+    write_wav(&args.output_path, &main)
+}
+
+fn read_wav(path: &str) -> Result<Audio, String> {
+    let error = |err: hound::Error| format!("could not read '{path}': {err}");
+
+    let mut reader = hound::WavReader::open(path).map_err(error)?;
+    let spec = reader.spec();
+    let num_file_channels = spec.channels as usize;
+    if !(1..=NUM_CHANNELS).contains(&num_file_channels) {
+        return Err(format!(
+            "'{path}' has {num_file_channels} channels, only mono and stereo files are supported"
+        ));
+    }
+
+    let interleaved: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>(),
+        hound::SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 / scale))
+                .collect()
+        }
+    }
+    .map_err(error)?;
+
+    let mut channels = vec![Vec::new(); NUM_CHANNELS];
+    for frame in interleaved.chunks_exact(num_file_channels) {
+        for (channel_idx, channel) in channels.iter_mut().enumerate() {
+            channel.push(frame[channel_idx.min(num_file_channels - 1)]);
+        }
+    }
+
+    Ok(Audio {
+        sample_rate: spec.sample_rate,
+        num_file_channels: spec.channels,
+        sample_format: spec.sample_format,
+        bits_per_sample: spec.bits_per_sample,
+        channels,
+    })
+}
+
+fn write_wav(path: &str, audio: &Audio) -> Result<(), String> {
+    let error = |err: hound::Error| format!("could not write '{path}': {err}");
+
+    let spec = hound::WavSpec {
+        channels: audio.num_file_channels,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: audio.bits_per_sample,
+        sample_format: audio.sample_format,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(error)?;
+    let scale = (1u32 << (audio.bits_per_sample - 1)) as f32;
+    for sample_idx in 0..audio.channels[0].len() {
+        for channel in audio.channels.iter().take(audio.num_file_channels as usize) {
+            let sample = channel[sample_idx];
+            match audio.sample_format {
+                hound::SampleFormat::Float => writer.write_sample(sample),
+                hound::SampleFormat::Int => {
+                    writer.write_sample((sample * scale).round().clamp(-scale, scale - 1.0) as i32)
+                }
+            }
+            .map_err(error)?;
+        }
+    }
+
+    writer.finalize().map_err(error)
+}
+
+/// Band limited sample rate conversion with a Blackman windowed sinc filter. When downsampling, the
+/// filter's cutoff follows the output's Nyquist frequency so nothing above it aliases. The input is
+/// silent before its first and after its last sample.
+fn resample(input: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if input.is_empty() {
+        return Vec::new();
+    }
+
+    let ratio = from_rate as f64 / to_rate as f64;
+    // Relative to the input's Nyquist frequency, and the filter's half width in input samples
+    let cutoff = RESAMPLER_CUTOFF * ratio.recip().min(1.0);
+    let half_width = RESAMPLER_ZERO_CROSSINGS / cutoff;
+
+    // The filter is symmetric, so only the right half is stored
+    let table_len = (half_width * RESAMPLER_OVERSAMPLING).ceil() as usize + 2;
+    let table: Vec<f64> = (0..table_len)
+        .map(|idx| {
+            let x = idx as f64 / RESAMPLER_OVERSAMPLING;
+            if x >= half_width {
+                return 0.0;
+            }
+
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (PI * cutoff * x).sin() / (PI * cutoff * x)
+            };
+            let t = x / half_width;
+            let window = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+
+            cutoff * sinc * window
+        })
+        .collect();
+    let filter = |x: f64| {
+        let position = x.abs() * RESAMPLER_OVERSAMPLING;
+        let idx = position as usize;
+        if idx + 1 >= table.len() {
+            return 0.0;
+        }
+
+        let t = position - idx as f64;
+        table[idx] + (table[idx + 1] - table[idx]) * t
+    };
+
+    let output_len = ((input.len() as f64) / ratio).round() as usize;
+    (0..output_len)
+        .map(|output_idx| {
+            let position = output_idx as f64 * ratio;
+            let first = (position - half_width).ceil().max(0.0) as usize;
+            let last = ((position + half_width).floor() as usize).min(input.len() - 1);
+
+            (first..=last)
+                .map(|idx| input[idx] as f64 * filter(idx as f64 - position))
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
@@ -1,27 +1,439 @@
 // Editor
 
-Struct SideboxEditor {
+use nih_plug::prelude::*;
+use nih_plug_egui::{create_egui_editor, egui, widgets, EguiState};
+use sidebox_core::curve::Curve;
+use sidebox_core::stutter;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use crate::ghost_curve::GhostCurve;
+use crate::snapshots::{SnapshotBank, NUM_SLOTS};
+use crate::SideboxParams;
+
+/// The height of the ghost curve editor, in pixels.
+const CURVE_EDITOR_HEIGHT: f32 = 120.0;
+/// How close the pointer needs to be to a curve point to grab it, in pixels.
+const POINT_GRAB_RADIUS: f32 = 6.0;
+/// Set in egui's memory when a slider changed without being dragged, like when typing in a value.
+const SLIDER_ENTERED_ID: &str = "slider entered";
+
+pub(crate) fn default_state() -> Arc<EguiState> {
+    EguiState::from_size(360, 480)
+}
+
+pub(crate) fn create(
     params: Arc<SideboxParams>,
+    editor_state: Arc<EguiState>,
+    curve_input: Arc<Mutex<triple_buffer::Input<Curve>>>,
+) -> Option<Box<dyn Editor>> {
+    create_egui_editor(
+        editor_state,
+        (),
+        |_, _| {},
+        move |egui_ctx, setter, _state| {
+            // Every press of a mouse button starts a gesture, which becomes a single undo step when
+            // it changed anything, see `SnapshotBank::begin_gesture()`
+            let (pressed, released) = egui_ctx
+                .input(|input| (input.pointer.any_pressed(), input.pointer.any_released()));
+            if pressed {
+                params.snapshots.lock().unwrap().begin_gesture(&params);
+            }
+
+            egui::CentralPanel::default().show(egui_ctx, |ui| {
+                snapshot_bar(ui, &params, setter, &curve_input);
+                ui.separator();
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // Create sliders for each parameter
+                    param_slider(ui, &params.mode, setter);
+                    param_slider(ui, &params.input_gain, setter);
+                    param_slider(ui, &params.sidechain_input_gain, setter);
+                    param_slider(ui, &params.output_gain, setter);
+                    param_slider(ui, &params.sidechain_phase_flip, setter);
+                    param_slider(ui, &params.envelope_follower_smoothing, setter);
+                    param_slider(ui, &params.lookahead, setter);
+                    param_slider(ui, &params.key_source, setter);
+                    param_slider(ui, &params.detection_link, setter);
+                    param_slider(ui, &params.detection_link_amount, setter);
+
+                    egui::CollapsingHeader::new("Alignment").show(ui, |ui| {
+                        let alignment = &params.alignment;
+                        param_slider(ui, &alignment.learn, setter);
+                        param_slider(ui, &alignment.manual, setter);
+                        // The manual offset replaces the learned one
+                        if alignment.manual.value() == 1 {
+                            param_slider(ui, &alignment.offset, setter);
+                        } else {
+                            let learned_ms = params.learned_alignment.load(Ordering::Relaxed);
+                            ui.label(format!("Learned offset: {learned_ms:.2} ms"));
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("Smart sum").show(ui, |ui| {
+                        let smart_sum = &params.smart_sum;
+                        param_slider(ui, &smart_sum.auto, setter);
+                        // The manual rotation is also where the automatic rotation starts out
+                        param_slider(ui, &smart_sum.rotation, setter);
+                        param_slider(ui, &smart_sum.cutoff, setter);
+                        param_slider(ui, &smart_sum.response, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Gate").show(ui, |ui| {
+                        let gate = &params.gate;
+                        param_slider(ui, &gate.threshold, setter);
+                        param_slider(ui, &gate.hysteresis, setter);
+                        param_slider(ui, &gate.attack, setter);
+                        param_slider(ui, &gate.hold, setter);
+                        param_slider(ui, &gate.release, setter);
+                        param_slider(ui, &gate.range, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Ghost").show(ui, |ui| {
+                        let ghost = &params.ghost;
+                        param_slider(ui, &ghost.division, setter);
+                        param_slider(ui, &ghost.shape, setter);
+                        param_slider(ui, &ghost.length, setter);
+
+                        let mut ghost_curve = params.ghost_curve.lock().unwrap();
+                        if curve_editor(ui, &mut ghost_curve) {
+                            curve_input.lock().unwrap().write(ghost_curve.curve());
+                            params.curve_edited(setter);
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("MIDI").show(ui, |ui| {
+                        let midi = &params.midi;
+                        param_slider(ui, &midi.attack, setter);
+                        param_slider(ui, &midi.decay, setter);
+                        param_slider(ui, &midi.sustain, setter);
+                        param_slider(ui, &midi.release, setter);
+                        param_slider(ui, &midi.velocity_amount, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Spectral ducking").show(ui, |ui| {
+                        let spectral_duck = &params.spectral_duck;
+                        param_slider(ui, &spectral_duck.depth, setter);
+                        param_slider(ui, &spectral_duck.low, setter);
+                        param_slider(ui, &spectral_duck.high, setter);
+                        param_slider(ui, &spectral_duck.attack, setter);
+                        param_slider(ui, &spectral_duck.release, setter);
+                        param_slider(ui, &spectral_duck.smoothing, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Spectral morph").show(ui, |ui| {
+                        let spectral_morph = &params.spectral_morph;
+                        param_slider(ui, &spectral_morph.amount, setter);
+                        param_slider(ui, &spectral_morph.morph, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Spectral denoise").show(ui, |ui| {
+                        let spectral_denoise = &params.spectral_denoise;
+                        param_slider(ui, &spectral_denoise.method, setter);
+                        param_slider(ui, &spectral_denoise.reduction, setter);
+                        param_slider(ui, &spectral_denoise.smoothing, setter);
+                        param_slider(ui, &spectral_denoise.musical_noise, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Ring modulation").show(ui, |ui| {
+                        let ring_mod = &params.ring_mod;
+                        param_slider(ui, &ring_mod.transpose, setter);
+                        param_slider(ui, &ring_mod.glide, setter);
+                        param_slider(ui, &ring_mod.min_confidence, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Envelope filter").show(ui, |ui| {
+                        let envelope_filter = &params.envelope_filter;
+                        param_slider(ui, &envelope_filter.filter, setter);
+                        param_slider(ui, &envelope_filter.cutoff, setter);
+                        param_slider(ui, &envelope_filter.depth, setter);
+                        param_slider(ui, &envelope_filter.resonance, setter);
+                        param_slider(ui, &envelope_filter.attack, setter);
+                        param_slider(ui, &envelope_filter.release, setter);
+                        // The envelope is smoothed by the shared smoothing parameter first
+                        param_slider(ui, &params.envelope_follower_smoothing, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Stutter").show(ui, |ui| {
+                        let stutter = &params.stutter;
+                        param_slider(ui, &stutter.trigger, setter);
+                        if stutter.trigger.value() == stutter::TRIGGER_LEVEL {
+                            param_slider(ui, &stutter.threshold, setter);
+                        }
+                        param_slider(ui, &stutter.sync, setter);
+                        // Only the length setting that's in use is shown
+                        if stutter.sync.value() == 1 {
+                            param_slider(ui, &stutter.division, setter);
+                        } else {
+                            param_slider(ui, &stutter.length, setter);
+                        }
+                        param_slider(ui, &stutter.crossfade, setter);
+                        param_slider(ui, &stutter.release, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Bleed cancellation").show(ui, |ui| {
+                        let bleed_cancel = &params.bleed_cancel;
+                        param_slider(ui, &bleed_cancel.step_size, setter);
+                        param_slider(ui, &bleed_cancel.length, setter);
+                        param_slider(ui, &bleed_cancel.freeze, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Threshold switch").show(ui, |ui| {
+                        let threshold_switch = &params.threshold_switch;
+                        param_slider(ui, &threshold_switch.threshold, setter);
+                        param_slider(ui, &threshold_switch.release, setter);
+                        param_slider(ui, &threshold_switch.crossfade, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Bitwise").show(ui, |ui| {
+                        let bitwise = &params.bitwise;
+                        param_slider(ui, &bitwise.bits, setter);
+                        param_slider(ui, &bitwise.dither, setter);
+                        param_slider(ui, &bitwise.smoothing, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Onsets").show(ui, |ui| {
+                        let onset = &params.onset;
+                        param_slider(ui, &onset.method, setter);
+                        param_slider(ui, &onset.threshold, setter);
+                        param_slider(ui, &onset.min_interval, setter);
+                    });
+
+                    egui::CollapsingHeader::new("Multiband").show(ui, |ui| {
+                        let multiband = &params.multiband;
+                        param_slider(ui, &multiband.num_bands, setter);
+                        let num_bands = multiband.num_bands.value() as usize;
+                        for crossover in [
+                            &multiband.crossover_1,
+                            &multiband.crossover_2,
+                            &multiband.crossover_3,
+                        ]
+                        .into_iter()
+                        .take(num_bands - 1)
+                        {
+                            param_slider(ui, crossover, setter);
+                        }
+                        for band in multiband.bands.iter().take(num_bands) {
+                            param_slider(ui, &band.mode, setter);
+                            param_slider(ui, &band.input_gain, setter);
+                            param_slider(ui, &band.sidechain_input_gain, setter);
+                            param_slider(ui, &band.output_gain, setter);
+                        }
+                    });
+
+                    egui::CollapsingHeader::new("Mid/side").show(ui, |ui| {
+                        let mid_side = &params.mid_side;
+                        param_slider(ui, &mid_side.channels, setter);
+                        param_slider(ui, &mid_side.key, setter);
+                        // The side settings only apply when both components are processed
+                        if mid_side.channels.value() == sidebox_core::mid_side::CHANNELS_MID_SIDE {
+                            param_slider(ui, &mid_side.side.mode, setter);
+                            param_slider(ui, &mid_side.side.input_gain, setter);
+                            param_slider(ui, &mid_side.side.sidechain_input_gain, setter);
+                            param_slider(ui, &mid_side.side.output_gain, setter);
+                        }
+                    });
+                });
+            });
+
+            let slider_entered = egui_ctx.memory_mut(|memory| {
+                let entered = memory.data.get_temp(egui::Id::new(SLIDER_ENTERED_ID));
+                memory.data.remove::<bool>(egui::Id::new(SLIDER_ENTERED_ID));
+                entered.unwrap_or(false)
+            });
+            let mut bank = params.snapshots.lock().unwrap();
+            bank.update(&params);
+            if released || slider_entered {
+                bank.end_gesture(&params);
+            }
+        },
+    )
 }
 
-impl SideboxEditor {
-    fn new(params: Arc<SideboxParams>) -> Self {
-        Self { params }
+/// A label with the parameter's name above a slider.
+fn param_slider(ui: &mut egui::Ui, param: &impl Param, setter: &ParamSetter) {
+    ui.label(param.name());
+    let response = ui.add(widgets::ParamSlider::for_param(param, setter));
+    // Drags end when the mouse button is released, but typed in values and resets can happen at
+    // any time
+    if response.changed() && !response.dragged() {
+        ui.memory_mut(|memory| {
+            memory
+                .data
+                .insert_temp(egui::Id::new(SLIDER_ENTERED_ID), true)
+        });
     }
 }
 
-impl egui::Widget for SideboxEditor {
-    fn ui(&mut self, ui: &mut egui::Ui) {
-        // Create sliders for each parameter
-        ui.label("Input gain");
-        ui.add(egui::Slider::f32(&mut self.params.input_gain.value, self.params.input_gain.range.clone()).text(""));
+/// The A/B slot buttons, copy buttons, and undo/redo.
+fn snapshot_bar(
+    ui: &mut egui::Ui,
+    params: &Arc<SideboxParams>,
+    setter: &ParamSetter,
+    curve_input: &Mutex<triple_buffer::Input<Curve>>,
+) {
+    let mut bank = params.snapshots.lock().unwrap();
+    let active = bank.active_slot();
+
+    ui.horizontal(|ui| {
+        for slot in 0..NUM_SLOTS {
+            if ui
+                .selectable_label(slot == active, SnapshotBank::slot_name(slot))
+                .clicked()
+            {
+                bank.switch_to(slot, params, setter, curve_input);
+            }
+        }
 
-        ui.label("Sidechain input gain");
-        ui.add(egui::Slider::f32(&mut self.params.sidechain_input_gain.value, self.params.sidechain_input_gain.range.clone()).text(""));
+        ui.separator();
 
-        ui.label("Output gain");
-        ui.add(egui::Slider::f32(&mut self.params.output_gain.value, self.params.output_gain.range.clone()).text(""));
+        if ui.add_enabled(bank.can_undo(), egui::Button::new("Undo")).clicked() {
+            bank.undo(params, setter, curve_input);
+        }
+        if ui.add_enabled(bank.can_redo(), egui::Button::new("Redo")).clicked() {
+            bank.redo(params, setter, curve_input);
+        }
+    });
 
-        // Add more UI elements for other parameters as needed
+    ui.horizontal(|ui| {
+        for slot in (0..NUM_SLOTS).filter(|&slot| slot != active) {
+            let label = format!(
+                "Copy {} \u{2192} {}",
+                SnapshotBank::slot_name(active),
+                SnapshotBank::slot_name(slot)
+            );
+            if ui.button(label).clicked() {
+                bank.copy_to(slot, params);
+            }
+        }
+    });
+}
+
+/// What is being dragged in the curve editor.
+#[derive(Debug, Clone, Copy)]
+enum CurveDrag {
+    Point(usize),
+    /// The tension of the segment starting at this point.
+    Tension(usize),
+}
+
+/// A breakpoint editor for the ghost curve. Click to add a point, drag points to move them, double
+/// click a point to remove it, and drag a segment up or down to bend it. Returns whether the curve
+/// changed.
+fn curve_editor(ui: &mut egui::Ui, ghost_curve: &mut GhostCurve) -> bool {
+    let size = egui::vec2(ui.available_width(), CURVE_EDITOR_HEIGHT);
+    let (response, painter) = ui.allocate_painter(size, egui::Sense::click_and_drag());
+    let rect = response.rect;
+    let to_screen = |x: f32, y: f32| {
+        egui::pos2(
+            rect.left() + x * rect.width(),
+            rect.bottom() - y * rect.height(),
+        )
+    };
+    let from_screen = |pos: egui::Pos2| {
+        (
+            ((pos.x - rect.left()) / rect.width()).clamp(0.0, 1.0),
+            ((rect.bottom() - pos.y) / rect.height()).clamp(0.0, 1.0),
+        )
+    };
+    let point_at = |ghost_curve: &GhostCurve, pos: egui::Pos2| {
+        ghost_curve
+            .points()
+            .iter()
+            .position(|point| to_screen(point.x, point.y).distance(pos) <= POINT_GRAB_RADIUS)
+    };
+
+    let mut changed = false;
+    let drag_id = response.id.with("drag");
+    if response.drag_started() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            let drag = match point_at(ghost_curve, pos) {
+                Some(idx) => CurveDrag::Point(idx),
+                None => CurveDrag::Tension(ghost_curve.segment_at(from_screen(pos).0)),
+            };
+            ui.memory_mut(|memory| memory.data.insert_temp(drag_id, drag));
+        }
+    }
+    if response.dragged() {
+        match ui.memory(|memory| memory.data.get_temp::<CurveDrag>(drag_id)) {
+            Some(CurveDrag::Point(idx)) => {
+                if let Some(pos) = response.interact_pointer_pos() {
+                    let (x, y) = from_screen(pos);
+                    ghost_curve.move_point(idx, x, y);
+                    changed = true;
+                }
+            }
+            Some(CurveDrag::Tension(segment)) => {
+                // Dragging upwards should always bow the segment upwards, regardless of whether it
+                // rises or falls
+                let points = ghost_curve.points();
+                let direction = if points[segment + 1].y >= points[segment].y {
+                    1.0
+                } else {
+                    -1.0
+                };
+                let tension = points[segment].tension
+                    + direction * response.drag_delta().y / rect.height() * 2.0;
+                ghost_curve.set_tension(segment, tension);
+                changed = true;
+            }
+            None => (),
+        }
     }
-}
\ No newline at end of file
+    if response.drag_released() {
+        ui.memory_mut(|memory| memory.data.remove::<CurveDrag>(drag_id));
+    }
+
+    let clicked_point = response
+        .interact_pointer_pos()
+        .and_then(|pos| point_at(ghost_curve, pos));
+    if response.double_clicked() {
+        if let Some(idx) = clicked_point {
+            ghost_curve.remove(idx);
+            changed = true;
+        }
+    } else if response.clicked() && clicked_point.is_none() {
+        if let Some(pos) = response.interact_pointer_pos() {
+            let (x, y) = from_screen(pos);
+            changed |= ghost_curve.insert(x, y).is_some();
+        }
+    }
+
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 0.0, visuals.extreme_bg_color);
+    for idx in 1..4 {
+        let x = rect.left() + rect.width() * idx as f32 / 4.0;
+        painter.line_segment(
+            [egui::pos2(x, rect.top()), egui::pos2(x, rect.bottom())],
+            visuals.widgets.noninteractive.bg_stroke,
+        );
+    }
+
+    // The curve is drawn from the same representation the processor uses
+    let curve = ghost_curve.curve();
+    let num_steps = rect.width().max(1.0) as usize;
+    let line = (0..=num_steps)
+        .map(|step| {
+            let x = step as f32 / num_steps as f32;
+            to_screen(x, curve.value_at(x))
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        line,
+        egui::Stroke::new(2.0, visuals.selection.bg_fill),
+    ));
+
+    let hovered_point = response
+        .hover_pos()
+        .and_then(|pos| point_at(ghost_curve, pos));
+    for (idx, point) in ghost_curve.points().iter().enumerate() {
+        let color = if hovered_point == Some(idx) {
+            visuals.strong_text_color()
+        } else {
+            visuals.text_color()
+        };
+        painter.circle_filled(to_screen(point.x, point.y), 4.0, color);
+    }
+
+    changed
+}
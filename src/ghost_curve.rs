@@ -0,0 +1,118 @@
+// The ghost key source's drawn curve, as edited in the GUI and saved with the plugin state
+
+use serde::{Deserialize, Serialize};
+use sidebox_core::curve::{Breakpoint, Curve, MAX_POINTS};
+
+/// The editable version of a [`Curve`]. The first point always sits at x = 0 and the last point at
+/// x = 1, and the points are kept sorted by their x-coordinates. This is persisted with the plugin
+/// state and only ever touched from the GUI thread and in `initialize()`. The audio thread gets a
+/// copy through a triple buffer instead.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedGhostCurve")]
+pub struct GhostCurve {
+    points: Vec<Breakpoint>,
+}
+
+/// A [`GhostCurve`] as read from a saved state, before the invariants have been restored.
+#[derive(Deserialize)]
+struct SavedGhostCurve {
+    points: Vec<Breakpoint>,
+}
+
+impl From<SavedGhostCurve> for GhostCurve {
+    fn from(saved: SavedGhostCurve) -> Self {
+        let mut points: Vec<Breakpoint> = saved
+            .points
+            .into_iter()
+            .filter(|point| point.x.is_finite() && point.y.is_finite() && point.tension.is_finite())
+            .collect();
+        if points.len() < 2 {
+            return Self::default();
+        }
+
+        points.sort_by(|a, b| a.x.total_cmp(&b.x));
+        points.truncate(MAX_POINTS);
+        let last_idx = points.len() - 1;
+        points[0].x = 0.0;
+        points[last_idx].x = 1.0;
+
+        Self { points }
+    }
+}
+
+impl Default for GhostCurve {
+    fn default() -> Self {
+        Self {
+            points: Curve::default().points().to_vec(),
+        }
+    }
+}
+
+impl GhostCurve {
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points
+    }
+
+    /// The curve in the form the processor uses. This is also what the editor draws, so the drawing
+    /// always matches what the processor does.
+    pub fn curve(&self) -> Curve {
+        Curve::from_points(&self.points)
+    }
+
+    /// Add a point and return its index, or `None` if the curve is full.
+    pub fn insert(&mut self, x: f32, y: f32) -> Option<usize> {
+        if self.points.len() >= MAX_POINTS {
+            return None;
+        }
+
+        let x = x.clamp(0.0, 1.0);
+        let idx = self
+            .points
+            .iter()
+            .position(|point| point.x > x)
+            .unwrap_or(self.points.len())
+            .clamp(1, self.points.len() - 1);
+        // The new point splits a segment, so both halves keep that segment's tension
+        let tension = self.points[idx - 1].tension;
+        self.points
+            .insert(idx, Breakpoint::new(x, y.clamp(0.0, 1.0), tension));
+
+        Some(idx)
+    }
+
+    /// Remove a point. The two end points can't be removed.
+    pub fn remove(&mut self, idx: usize) {
+        if idx > 0 && idx + 1 < self.points.len() {
+            self.points.remove(idx);
+        }
+    }
+
+    /// Move a point. Points can't move past their neighbours, and the end points can only move
+    /// vertically.
+    pub fn move_point(&mut self, idx: usize, x: f32, y: f32) {
+        let last_idx = self.points.len() - 1;
+        let x = match idx {
+            0 => 0.0,
+            _ if idx == last_idx => 1.0,
+            _ => x.clamp(self.points[idx - 1].x, self.points[idx + 1].x),
+        };
+
+        self.points[idx].x = x;
+        self.points[idx].y = y.clamp(0.0, 1.0);
+    }
+
+    /// The index of the segment containing `x`, which is also the index of the point it starts at.
+    pub fn segment_at(&self, x: f32) -> usize {
+        self.points
+            .iter()
+            .rposition(|point| point.x <= x)
+            .unwrap_or(0)
+            .min(self.points.len().saturating_sub(2))
+    }
+
+    pub fn set_tension(&mut self, segment: usize, tension: f32) {
+        if let Some(point) = self.points.get_mut(segment) {
+            point.tension = tension.clamp(-1.0, 1.0);
+        }
+    }
+}
@@ -18,23 +18,80 @@ use circular_buffer::CircularBuffer;
 
 use rustfft::{FftPlanner, num_complex::Complex};
 
-mod other_stuff;
-use other_stuff::SimpleEnvelopeFollower;
+use nih_plug_egui::EguiState;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
 
-// mod buffer;
-// use buffer::RingBuffer;
+use atomic_float::AtomicF32;
 
-// mod editor;
-// use editor::SideboxEditor;
+use sidebox_core::curve::Curve;
+use sidebox_core::{Processor, Settings};
+use triple_buffer::TripleBuffer;
+
+mod editor;
+
+mod ghost_curve;
+use ghost_curve::GhostCurve;
+
+mod params;
+use params::{
+    AlignmentParams, BitwiseParams, BleedCancelParams, EnvelopeFilterParams, GateParams,
+    GhostParams, MidSideParams, MidiParams, MultibandParams, OnsetParams, RingModParams,
+    SmartSumParams, SpectralDenoiseParams, SpectralDuckParams, SpectralMorphParams, StutterParams,
+    ThresholdSwitchParams,
+};
+
+mod snapshots;
+use snapshots::SnapshotBank;
+
+// nih-plug only checks for allocations in debug builds
+#[cfg(all(test, debug_assertions))]
+mod tests;
 
 
 struct Sidebox {
     params: Arc<SideboxParams>,
+
+    /// All of the DSP lives in `sidebox-core`, this only feeds it the parameter values and buffers.
+    processor: Processor,
+    /// The latency last reported to the host. This changes with the lookahead time and the summing
+    /// modes' alignment offset, but not with the mode, see `Processor::latency_samples()`.
+    latency_samples: u32,
+
+    /// The editor writes the drawn ghost curve here whenever it changes. The audio thread reads it
+    /// from `curve_output` without locking, the mutex only keeps the editor and `initialize()` from
+    /// writing at the same time.
+    curve_input: Arc<Mutex<triple_buffer::Input<Curve>>>,
+    curve_output: triple_buffer::Output<Curve>,
 }
 
 #[derive(Params)]
 struct SideboxParams { // Plugin Parameters
 
+    #[persist = "editor-state"]
+    editor_state: Arc<EguiState>,
+
+    /// A/B slots and undo history, see [`SnapshotBank`].
+    #[persist = "snapshots"]
+    pub snapshots: Mutex<SnapshotBank>,
+
+    /// The curve for the ghost key source's drawn shape, see [`GhostCurve`].
+    #[persist = "ghost-curve"]
+    pub ghost_curve: Mutex<GhostCurve>,
+    /// Flipped by the editor whenever the ghost curve changes. The curve isn't a parameter, so
+    /// without this hosts wouldn't know the plugin's state changed and needs to be saved. Not part
+    /// of snapshots, see `Self::curve_edited()`.
+    #[id = "curve revision"]
+    pub curve_revision: IntParam,
+
+    /// The offset the summing modes learned, see `Processor::learned_alignment_ms()`. The audio
+    /// thread keeps this up to date so it's saved with the plugin's state.
+    #[persist = "learned-alignment"]
+    pub learned_alignment: AtomicF32,
+    /// A learned offset restored by a snapshot, which the audio thread hands to the processor. This
+    /// is NaN while there's nothing to restore.
+    pub restored_alignment: AtomicF32,
+
     #[id = "input gain"]
     pub input_gain: FloatParam,
 
@@ -47,28 +104,127 @@ struct SideboxParams { // Plugin Parameters
     #[id = "sidechain phase flip"]
     pub sidechain_phase_flip: IntParam,
 
+    /// Smooths the sidechain's level for the envelope filter, in samples.
     #[id = "envelope follower smoothing"]
     pub envelope_follower_smoothing: IntParam,
 
+    /// Only used by the detection based modes, see `sidebox_core::mode::uses_detection()`.
+    #[id = "lookahead"]
+    pub lookahead: FloatParam,
+
+    /// Where the detection based modes get their key signal from, see `sidebox_core::key_source`.
+    #[id = "key source"]
+    pub key_source: IntParam,
+
+    /// How the detection based modes' keys are linked between channels, see
+    /// `sidebox_core::detection_link`.
+    #[id = "detection link"]
+    pub detection_link: IntParam,
+
+    #[id = "detection link amount"]
+    pub detection_link_amount: FloatParam,
+
     #[id = "mode"]
     pub mode: IntParam,
+
+    #[nested(group = "Alignment")]
+    pub alignment: AlignmentParams,
+
+    #[nested(group = "Smart sum")]
+    pub smart_sum: SmartSumParams,
+
+    #[nested(group = "Gate")]
+    pub gate: GateParams,
+
+    #[nested(group = "Ghost")]
+    pub ghost: GhostParams,
+
+    #[nested(group = "MIDI")]
+    pub midi: MidiParams,
+
+    #[nested(group = "Spectral ducking")]
+    pub spectral_duck: SpectralDuckParams,
+
+    #[nested(group = "Spectral morph")]
+    pub spectral_morph: SpectralMorphParams,
+
+    #[nested(group = "Spectral denoise")]
+    pub spectral_denoise: SpectralDenoiseParams,
+
+    #[nested(group = "Ring modulation")]
+    pub ring_mod: RingModParams,
+
+    #[nested(group = "Envelope filter")]
+    pub envelope_filter: EnvelopeFilterParams,
+
+    #[nested(group = "Stutter")]
+    pub stutter: StutterParams,
+
+    #[nested(group = "Bleed cancellation")]
+    pub bleed_cancel: BleedCancelParams,
+
+    #[nested(group = "Threshold switch")]
+    pub threshold_switch: ThresholdSwitchParams,
+
+    #[nested(group = "Bitwise")]
+    pub bitwise: BitwiseParams,
+
+    #[nested(group = "Onsets")]
+    pub onset: OnsetParams,
+
+    #[nested(group = "Multiband")]
+    pub multiband: MultibandParams,
+
+    #[nested(group = "Mid/side")]
+    pub mid_side: MidSideParams,
 }
 
 impl Default for Sidebox {
     fn default() -> Self {
+        Self::new(SideboxParams::default())
+    }
+}
+
+impl Sidebox {
+    fn new(params: SideboxParams) -> Self {
+        let (curve_input, curve_output) = TripleBuffer::new(&Curve::default()).split();
+
         Self {
-            params: Arc::new(SideboxParams::default()),
+            params: Arc::new(params),
+
+            processor: Processor::new(),
+            latency_samples: 0,
+
+            curve_input: Arc::new(Mutex::new(curve_input)),
+            curve_output,
         }
     }
 }
 
-impl Default for SideboxParams { // Parameter Definitions
+impl Default for SideboxParams {
     fn default() -> Self {
+        Self::new(&Settings::default())
+    }
+}
+
+impl SideboxParams { // Parameter Definitions
+    /// The parameters with `defaults` as their default values.
+    fn new(defaults: &Settings) -> Self {
         Self {
+            editor_state: editor::default_state(),
+            snapshots: Mutex::new(SnapshotBank::default()),
+            ghost_curve: Mutex::new(GhostCurve::default()),
+            curve_revision: IntParam::new(
+                "Curve revision", 0, IntRange::Linear { min: 0, max: 1 }
+            )
+            .with_flags(ParamFlags::NON_AUTOMATABLE | ParamFlags::HIDE_IN_GENERIC_UI),
+            learned_alignment: AtomicF32::new(0.0),
+            restored_alignment: AtomicF32::new(f32::NAN),
+
             // This gain is stored as linear gain. NIH-plug comes with useful conversion functions to treat these kinds of parameters as if we were dealing with decibels. Storing this as decibels is easier to work with, but requires a conversion for every sample.
             input_gain: FloatParam::new(
                 "Input gain",
-                util::db_to_gain(0.0),
+                defaults.input_gain,
                 FloatRange::Skewed {
                     min: util::db_to_gain(-30.0),
                     max: util::db_to_gain(30.0),
@@ -81,7 +237,7 @@ impl Default for SideboxParams { // Parameter Definitions
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
             sidechain_input_gain: FloatParam::new(
                 "Sidechain input gain",
-                util::db_to_gain(0.0),
+                defaults.sidechain_input_gain,
                 FloatRange::Skewed {
                     min: util::db_to_gain(-30.0),
                     max: util::db_to_gain(30.0),
@@ -94,7 +250,7 @@ impl Default for SideboxParams { // Parameter Definitions
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
             output_gain: FloatParam::new(
                 "output gain",
-                util::db_to_gain(0.0),
+                defaults.output_gain,
                 FloatRange::Skewed {
                     min: util::db_to_gain(-30.0),
                     max: util::db_to_gain(30.0),
@@ -107,14 +263,103 @@ impl Default for SideboxParams { // Parameter Definitions
             .with_string_to_value(formatters::s2v_f32_gain_to_db()),
         
             mode: IntParam::new(
-                "Mode", 0, IntRange::Linear { min: (0), max: (7) } // 0: addition, 1: multiplication, 2: absolute value multiplication, 3: modulo, 4: simple envelope follower, 5: sidechain as modulator, 6: convolution, 7: analog ring modulation, etc
-            ),
+                "Mode", defaults.mode, IntRange::Linear { min: (0), max: (sidebox_core::mode::MAX) } // see `sidebox_core::mode` for the list of modes
+            )
+            .with_value_to_string(Arc::new(|value| sidebox_core::mode::name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| sidebox_core::mode::from_name(string))),
             sidechain_phase_flip: IntParam::new(
-                "Sidechain phase flip", 0, IntRange::Linear { min: (0), max: (1) }
+                "Sidechain phase flip", defaults.sidechain_phase_flip as i32, IntRange::Linear { min: (0), max: (1) }
             ),
             envelope_follower_smoothing: IntParam::new(
-                "Envelope follower smoothing", 10, IntRange::Linear { min: 5, max: 1000 },
+                "Envelope follower smoothing", defaults.envelope_follower_smoothing, IntRange::Linear { min: 5, max: 1000 },
             ),
+            lookahead: FloatParam::new(
+                "Lookahead",
+                defaults.lookahead_ms,
+                FloatRange::Linear { min: 0.0, max: sidebox_core::MAX_LOOKAHEAD_MS },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            key_source: IntParam::new(
+                "Key source", defaults.key_source, IntRange::Linear { min: 0, max: sidebox_core::key_source::MAX }
+            )
+            .with_value_to_string(Arc::new(|value| sidebox_core::key_source::name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| sidebox_core::key_source::from_name(string))),
+            detection_link: IntParam::new(
+                "Detection link", defaults.detection_link, IntRange::Linear { min: 0, max: sidebox_core::detection_link::SUM }
+            )
+            .with_value_to_string(Arc::new(|value| sidebox_core::detection_link::name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| sidebox_core::detection_link::from_name(string))),
+            detection_link_amount: FloatParam::new(
+                "Detection link amount",
+                defaults.detection_link_amount,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+
+            alignment: AlignmentParams::new(&defaults.alignment),
+            smart_sum: SmartSumParams::new(&defaults.smart_sum),
+            gate: GateParams::new(&defaults.gate),
+            ghost: GhostParams::new(&defaults.ghost),
+            midi: MidiParams::new(&defaults.midi),
+            spectral_duck: SpectralDuckParams::new(&defaults.spectral_duck),
+            spectral_morph: SpectralMorphParams::new(&defaults.spectral_morph),
+            spectral_denoise: SpectralDenoiseParams::new(&defaults.spectral_denoise),
+            ring_mod: RingModParams::new(&defaults.ring_mod),
+            envelope_filter: EnvelopeFilterParams::new(&defaults.envelope_filter),
+            stutter: StutterParams::new(&defaults.stutter),
+            bleed_cancel: BleedCancelParams::new(&defaults.bleed_cancel),
+            threshold_switch: ThresholdSwitchParams::new(&defaults.threshold_switch),
+            bitwise: BitwiseParams::new(&defaults.bitwise),
+            onset: OnsetParams::new(&defaults.onset),
+            multiband: MultibandParams::new(&defaults.multiband),
+            mid_side: MidSideParams::new(&defaults.mid_side),
+        }
+    }
+}
+
+impl SideboxParams {
+    /// Let the host know the ghost curve changed by flipping `curve_revision`. Call this from the
+    /// editor after every edit to the curve.
+    pub fn curve_edited(&self, setter: &ParamSetter) {
+        setter.begin_set_parameter(&self.curve_revision);
+        setter.set_parameter(&self.curve_revision, 1 - self.curve_revision.value());
+        setter.end_set_parameter(&self.curve_revision);
+    }
+
+    /// The current parameter values for `sidebox_core`. These are read once per block, smoothing
+    /// happens inside of the processor.
+    fn settings(&self) -> Settings {
+        Settings {
+            mode: self.mode.value(),
+            input_gain: self.input_gain.value(),
+            sidechain_input_gain: self.sidechain_input_gain.value(),
+            output_gain: self.output_gain.value(),
+            sidechain_phase_flip: self.sidechain_phase_flip.value() == 1,
+            envelope_follower_smoothing: self.envelope_follower_smoothing.value(),
+            lookahead_ms: self.lookahead.value(),
+            key_source: self.key_source.value(),
+            detection_link: self.detection_link.value(),
+            detection_link_amount: self.detection_link_amount.value(),
+            alignment: self.alignment.settings(),
+            smart_sum: self.smart_sum.settings(),
+            gate: self.gate.settings(),
+            ghost: self.ghost.settings(),
+            midi: self.midi.settings(),
+            spectral_duck: self.spectral_duck.settings(),
+            spectral_morph: self.spectral_morph.settings(),
+            spectral_denoise: self.spectral_denoise.settings(),
+            ring_mod: self.ring_mod.settings(),
+            envelope_filter: self.envelope_filter.settings(),
+            stutter: self.stutter.settings(),
+            bleed_cancel: self.bleed_cancel.settings(),
+            threshold_switch: self.threshold_switch.settings(),
+            bitwise: self.bitwise.settings(),
+            onset: self.onset.settings(),
+            multiband: self.multiband.settings(),
+            mid_side: self.mid_side.settings(),
         }
     }
 }
@@ -148,7 +393,8 @@ impl Plugin for Sidebox { // Plugin implementation
         */
     ];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
+    // Notes trigger the envelope for the MIDI key source
+    const MIDI_INPUT: MidiConfig = MidiConfig::Basic;
     const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
@@ -166,98 +412,105 @@ impl Plugin for Sidebox { // Plugin implementation
         self.params.clone()
     }
 
+    fn editor(&mut self, _async_executor: AsyncExecutor<Self>) -> Option<Box<dyn Editor>> {
+        editor::create(
+            self.params.clone(),
+            self.params.editor_state.clone(),
+            self.curve_input.clone(),
+        )
+    }
+
     fn initialize(
         &mut self,
         _audio_io_layout: &AudioIOLayout,
-        _buffer_config: &BufferConfig,
-        _context: &mut impl InitContext<Self>,
+        buffer_config: &BufferConfig,
+        context: &mut impl InitContext<Self>,
     ) -> bool {
         // Resize buffers and perform other potentially expensive initialization operations here.
-        // The `reset()` function is always called right after this function. You can remove this
-        // function if you do not need it.
+        // The `reset()` function is always called right after this function.
+        self.processor.prepare(buffer_config.sample_rate, buffer_config.max_buffer_size as usize);
+        self.processor.set_settings(self.params.settings());
+
+        // The curve may have just been loaded from a saved state, in which case the editor hasn't
+        // sent it to the audio thread yet
+        let curve = self.params.ghost_curve.lock().unwrap().curve();
+        self.curve_input.lock().unwrap().write(curve);
+        self.processor.set_curve(&curve);
+        self.processor
+            .set_learned_alignment_ms(self.params.learned_alignment.load(Ordering::Relaxed));
+
+        self.latency_samples = self.processor.latency_samples();
+        context.set_latency_samples(self.latency_samples);
 
-        // Create globabal variables and buffers here
         true
     }
 
     fn reset(&mut self) {
         // Reset buffers and envelopes here. This can be called from the audio thread and may not
-        // allocate. You can remove this function if you do not need it.
+        // allocate.
+        self.processor.reset();
     }
 
     fn process( // process one chunk of audio
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
 
     ) -> ProcessStatus {
 
-        let raw_buffer = buffer.as_slice();
-        let aux_input0 = _aux.inputs[0].as_slice();
-
         /* AuxiliaryBuffers definition
         pub struct AuxiliaryBuffers<'a> {
             pub inputs: &'a mut [Buffer<'a>],
             pub outputs: &'a mut [Buffer<'a>],
         }
         */
-    
-        // Apply sidechain operation
-        for (mut channel_samples, mut sidechain_samples) in buffer.iter_samples().zip(aux_input0.iter_mut()) {
-            let mode = self.params.mode.smoothed.next();
-            let output_gain = self.params.output_gain.smoothed.next();
-            let input_gain = self.params.input_gain.smoothed.next();
-            let sidechain_input_gain = self.params.sidechain_input_gain.smoothed.next();
-            let sidechain_phase_flip = self.params.sidechain_phase_flip.smoothed.next();
-            let num_samples = channel_samples.len();
-            let num_sidechain_samples = sidechain_samples.len();
-
-            // Do processing
-            match mode {
-                0 => { // addition (DONE)
-                    for (sample, sidechain_sample) in channel_samples.iter_mut().zip(sidechain_samples.iter_mut()) {
-                        *sidechain_sample *= sidechain_input_gain;
-                        *sample *= input_gain;
-                        *sample += *sidechain_sample;
-                        *sample *= output_gain;
-                    }
-                }
-                1 => { // mutiplication (DONE)
-                    for (sample, sidechain_sample) in channel_samples.iter_mut().zip(sidechain_samples.iter_mut()) {
-                        *sample *= input_gain;
-                        *sidechain_sample *= sidechain_input_gain;
-                        *sample *= *sidechain_sample;
-                        *sample *= output_gain;
-                    }
-                }
-                3 => { // modulo (DONE)
-                    for (sample, sidechain_sample) in channel_samples.iter_mut().zip(sidechain_samples.iter_mut()) {
-                        *sidechain_sample *= sidechain_input_gain;
-                        *sample *= input_gain;
-                        *sample %= *sidechain_sample;
-                        *sample *= output_gain;
-                    }
-                }
-                2 => { // absolute value multiplication
-                    for (sample, sidechain_sample) in channel_samples.iter_mut().zip(sidechain_samples.iter_mut()) {
-                        *sample = *sample * input_gain * (*sidechain_sample).abs() * sidechain_input_gain
-                    }
+        let sidechain = aux.inputs[0].as_slice_immutable();
+        let sidechain: [&[f32]; 2] = [&*sidechain[0], &*sidechain[1]];
+
+        // Apply sidechain operation, see `sidebox_core::mode` for the modes. Updating the settings
+        // recalculates every filter and delay, so that's skipped for blocks where nothing changed.
+        let settings = self.params.settings();
+        if settings != *self.processor.settings() {
+            self.processor.set_settings(settings);
+        }
+        let transport = context.transport();
+        self.processor.set_transport(sidebox_core::Transport {
+            playing: transport.playing,
+            tempo: transport.tempo,
+            pos_beats: transport.pos_beats(),
+            time_sig_numerator: transport.time_sig_numerator,
+            time_sig_denominator: transport.time_sig_denominator,
+        });
+        if self.curve_output.update() {
+            self.processor.set_curve(self.curve_output.output_buffer());
+        }
+        let restored_alignment = self.params.restored_alignment.swap(f32::NAN, Ordering::Relaxed);
+        if !restored_alignment.is_nan() {
+            self.processor.set_learned_alignment_ms(restored_alignment);
+        }
+        while let Some(event) = context.next_event() {
+            match event {
+                NoteEvent::NoteOn { timing, note, velocity, .. } => {
+                    self.processor.queue_note_event(sidebox_core::NoteEvent::On { timing, note, velocity })
                 }
-                _ => { // testing ground
-
-                /* how do to something like this? I can't index into a specific channel
-                    for sample in buffer.iter_samples() {
-                        let left_sample = sample[0];
-                        let right_sample = sample[1];
-                        left_sample *= input_gain;
-                    } */
-
+                NoteEvent::NoteOff { timing, note, .. } => {
+                    self.processor.queue_note_event(sidebox_core::NoteEvent::Off { timing, note })
                 }
+                _ => (),
             }
-        
         }
-    
+        let latency_samples = self.processor.latency_samples();
+        if latency_samples != self.latency_samples {
+            self.latency_samples = latency_samples;
+            context.set_latency_samples(latency_samples);
+        }
+
+        self.processor.process(buffer.as_slice(), &sidechain);
+        self.params
+            .learned_alignment
+            .store(self.processor.learned_alignment_ms(), Ordering::Relaxed);
+
         ProcessStatus::Normal
     }
 }
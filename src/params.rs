@@ -0,0 +1,1204 @@
+// Parameter groups for the modes that need more than the shared gain parameters
+
+use nih_plug::prelude::*;
+use sidebox_core::adsr::AdsrSettings;
+use sidebox_core::alignment::{self, AlignmentSettings};
+use sidebox_core::bitwise::{self, BitwiseSettings};
+use sidebox_core::bleed_cancel::{self, BleedCancelSettings};
+use sidebox_core::envelope_filter::{self, EnvelopeFilterSettings};
+use sidebox_core::gate::{self, GateSettings};
+use sidebox_core::ghost::{self, GhostSettings};
+use sidebox_core::logic::ThresholdSwitchSettings;
+use sidebox_core::multiband::{self, BandSettings, MultibandSettings, MAX_BANDS};
+use sidebox_core::mid_side::{self, MidSideSettings};
+use sidebox_core::mode;
+use sidebox_core::onset::{self, OnsetSettings};
+use sidebox_core::ring_mod::RingModSettings;
+use sidebox_core::smart_sum::SmartSumSettings;
+use sidebox_core::spectral_denoise::{self, SpectralDenoiseSettings};
+use sidebox_core::spectral_duck::SpectralDuckSettings;
+use sidebox_core::spectral_morph::{self, SpectralMorphSettings};
+use sidebox_core::stutter::{self, StutterSettings};
+use std::sync::Arc;
+
+#[derive(Params)]
+pub struct AlignmentParams {
+    #[id = "align learn"]
+    pub learn: IntParam,
+
+    #[id = "align manual"]
+    pub manual: IntParam,
+
+    #[id = "align offset"]
+    pub offset: FloatParam,
+}
+
+impl AlignmentParams {
+    pub fn new(defaults: &AlignmentSettings) -> Self {
+
+        Self {
+            learn: IntParam::new(
+                "Learn alignment",
+                defaults.learn as i32,
+                IntRange::Linear { min: 0, max: 1 },
+            ),
+            manual: IntParam::new(
+                "Manual alignment",
+                defaults.manual as i32,
+                IntRange::Linear { min: 0, max: 1 },
+            ),
+            offset: FloatParam::new(
+                "Alignment offset",
+                defaults.offset_ms,
+                FloatRange::Linear {
+                    min: -alignment::MAX_OFFSET_MS,
+                    max: alignment::MAX_OFFSET_MS,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+        }
+    }
+
+    pub fn settings(&self) -> AlignmentSettings {
+        AlignmentSettings {
+            learn: self.learn.value() == 1,
+            manual: self.manual.value() == 1,
+            offset_ms: self.offset.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct SmartSumParams {
+    #[id = "smart sum auto"]
+    pub auto: IntParam,
+
+    #[id = "smart sum rotation"]
+    pub rotation: FloatParam,
+
+    #[id = "smart sum cutoff"]
+    pub cutoff: FloatParam,
+
+    #[id = "smart sum response"]
+    pub response: FloatParam,
+}
+
+impl SmartSumParams {
+    pub fn new(defaults: &SmartSumSettings) -> Self {
+
+        Self {
+            auto: IntParam::new(
+                "Automatic rotation",
+                defaults.auto as i32,
+                IntRange::Linear { min: 0, max: 1 },
+            ),
+            rotation: FloatParam::new(
+                "Phase rotation",
+                defaults.rotation_degrees,
+                FloatRange::Linear {
+                    min: -180.0,
+                    max: 180.0,
+                },
+            )
+            .with_unit("°")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            cutoff: FloatParam::new(
+                "Coherence cutoff",
+                defaults.cutoff_hz,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" Hz")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+            response: FloatParam::new(
+                "Rotation response",
+                defaults.response_ms,
+                FloatRange::Skewed {
+                    min: 10.0,
+                    max: 5000.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(0)),
+        }
+    }
+
+    pub fn settings(&self) -> SmartSumSettings {
+        SmartSumSettings {
+            auto: self.auto.value() == 1,
+            rotation_degrees: self.rotation.value(),
+            cutoff_hz: self.cutoff.value(),
+            response_ms: self.response.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct GateParams {
+    #[id = "gate threshold"]
+    pub threshold: FloatParam,
+
+    #[id = "gate hysteresis"]
+    pub hysteresis: FloatParam,
+
+    #[id = "gate attack"]
+    pub attack: FloatParam,
+
+    #[id = "gate hold"]
+    pub hold: FloatParam,
+
+    #[id = "gate release"]
+    pub release: FloatParam,
+
+    #[id = "gate range"]
+    pub range: FloatParam,
+}
+
+impl GateParams {
+    pub fn new(defaults: &GateSettings) -> Self {
+
+        Self {
+            threshold: FloatParam::new(
+                "Gate threshold",
+                defaults.threshold_db,
+                FloatRange::Linear { min: -60.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            hysteresis: FloatParam::new(
+                "Gate hysteresis",
+                defaults.hysteresis_db,
+                FloatRange::Linear { min: 0.0, max: 20.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            attack: FloatParam::new(
+                "Gate attack",
+                defaults.attack_ms,
+                FloatRange::Skewed {
+                    min: gate::MIN_ATTACK_MS,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            hold: FloatParam::new(
+                "Gate hold",
+                defaults.hold_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 500.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            release: FloatParam::new(
+                "Gate release",
+                defaults.release_ms,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            range: FloatParam::new(
+                "Gate range",
+                defaults.range_db,
+                FloatRange::Linear { min: -80.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+        }
+    }
+
+    pub fn settings(&self) -> GateSettings {
+        GateSettings {
+            threshold_db: self.threshold.value(),
+            hysteresis_db: self.hysteresis.value(),
+            attack_ms: self.attack.value(),
+            hold_ms: self.hold.value(),
+            release_ms: self.release.value(),
+            range_db: self.range.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct GhostParams {
+    #[id = "ghost division"]
+    pub division: IntParam,
+
+    #[id = "ghost shape"]
+    pub shape: IntParam,
+
+    #[id = "ghost length"]
+    pub length: FloatParam,
+}
+
+impl GhostParams {
+    pub fn new(defaults: &GhostSettings) -> Self {
+
+        Self {
+            division: IntParam::new(
+                "Ghost division",
+                defaults.division,
+                IntRange::Linear { min: 0, max: ghost::MAX_DIVISION },
+            )
+            .with_value_to_string(Arc::new(|value| ghost::division_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| ghost::division_from_name(string))),
+            shape: IntParam::new(
+                "Ghost shape",
+                defaults.shape,
+                IntRange::Linear {
+                    min: 0,
+                    max: ghost::SHAPE_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| ghost::shape_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| ghost::shape_from_name(string))),
+            length: FloatParam::new(
+                "Ghost length",
+                defaults.length,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+        }
+    }
+
+    pub fn settings(&self) -> GhostSettings {
+        GhostSettings {
+            division: self.division.value(),
+            shape: self.shape.value(),
+            length: self.length.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct MidiParams {
+    #[id = "midi attack"]
+    pub attack: FloatParam,
+
+    #[id = "midi decay"]
+    pub decay: FloatParam,
+
+    #[id = "midi sustain"]
+    pub sustain: FloatParam,
+
+    #[id = "midi release"]
+    pub release: FloatParam,
+
+    #[id = "midi velocity"]
+    pub velocity_amount: FloatParam,
+}
+
+impl MidiParams {
+    pub fn new(defaults: &AdsrSettings) -> Self {
+        let time_range = |min: f32, max: f32| FloatRange::Skewed {
+            min,
+            max,
+            factor: FloatRange::skew_factor(-2.0),
+        };
+
+        Self {
+            attack: FloatParam::new("MIDI attack", defaults.attack_ms, time_range(0.0, 100.0))
+                .with_unit(" ms")
+                .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            decay: FloatParam::new("MIDI decay", defaults.decay_ms, time_range(1.0, 2000.0))
+                .with_unit(" ms")
+                .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            sustain: FloatParam::new(
+                "MIDI sustain",
+                defaults.sustain,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            release: FloatParam::new("MIDI release", defaults.release_ms, time_range(1.0, 2000.0))
+                .with_unit(" ms")
+                .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            velocity_amount: FloatParam::new(
+                "MIDI velocity",
+                defaults.velocity_amount,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+        }
+    }
+
+    pub fn settings(&self) -> AdsrSettings {
+        AdsrSettings {
+            attack_ms: self.attack.value(),
+            decay_ms: self.decay.value(),
+            sustain: self.sustain.value(),
+            release_ms: self.release.value(),
+            velocity_amount: self.velocity_amount.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct SpectralDuckParams {
+    #[id = "spectral depth"]
+    pub depth: FloatParam,
+
+    #[id = "spectral low"]
+    pub low: FloatParam,
+
+    #[id = "spectral high"]
+    pub high: FloatParam,
+
+    #[id = "spectral attack"]
+    pub attack: FloatParam,
+
+    #[id = "spectral release"]
+    pub release: FloatParam,
+
+    #[id = "spectral smoothing"]
+    pub smoothing: FloatParam,
+}
+
+impl SpectralDuckParams {
+    pub fn new(defaults: &SpectralDuckSettings) -> Self {
+        let frequency_range = || FloatRange::Skewed {
+            min: 20.0,
+            max: 20000.0,
+            factor: FloatRange::skew_factor(-2.0),
+        };
+        let time_range = |min: f32, max: f32| FloatRange::Skewed {
+            min,
+            max,
+            factor: FloatRange::skew_factor(-2.0),
+        };
+
+        Self {
+            depth: FloatParam::new(
+                "Spectral depth",
+                defaults.depth_db,
+                FloatRange::Linear { min: 0.0, max: 60.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            low: FloatParam::new("Spectral low", defaults.low_hz, frequency_range())
+                .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+                .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            high: FloatParam::new("Spectral high", defaults.high_hz, frequency_range())
+                .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+                .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            attack: FloatParam::new(
+                "Spectral attack",
+                defaults.attack_ms,
+                time_range(0.0, 500.0),
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            release: FloatParam::new(
+                "Spectral release",
+                defaults.release_ms,
+                time_range(1.0, 2000.0),
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            smoothing: FloatParam::new(
+                "Spectral smoothing",
+                defaults.smoothing_octaves,
+                FloatRange::Linear { min: 0.0, max: 2.0 },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+        }
+    }
+
+    pub fn settings(&self) -> SpectralDuckSettings {
+        SpectralDuckSettings {
+            depth_db: self.depth.value(),
+            low_hz: self.low.value(),
+            high_hz: self.high.value(),
+            attack_ms: self.attack.value(),
+            release_ms: self.release.value(),
+            smoothing_octaves: self.smoothing.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct SpectralMorphParams {
+    #[id = "morph amount"]
+    pub amount: FloatParam,
+
+    #[id = "morph type"]
+    pub morph: IntParam,
+}
+
+impl SpectralMorphParams {
+    pub fn new(defaults: &SpectralMorphSettings) -> Self {
+
+        Self {
+            amount: FloatParam::new(
+                "Morph amount",
+                defaults.amount,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+            morph: IntParam::new(
+                "Morph type",
+                defaults.morph,
+                IntRange::Linear {
+                    min: 0,
+                    max: spectral_morph::MORPH_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| spectral_morph::morph_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| spectral_morph::morph_from_name(string))),
+        }
+    }
+
+    pub fn settings(&self) -> SpectralMorphSettings {
+        SpectralMorphSettings {
+            amount: self.amount.value(),
+            morph: self.morph.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct SpectralDenoiseParams {
+    #[id = "denoise method"]
+    pub method: IntParam,
+
+    #[id = "denoise reduction"]
+    pub reduction: FloatParam,
+
+    #[id = "denoise smoothing"]
+    pub smoothing: FloatParam,
+
+    #[id = "denoise musical noise"]
+    pub musical_noise: FloatParam,
+}
+
+impl SpectralDenoiseParams {
+    pub fn new(defaults: &SpectralDenoiseSettings) -> Self {
+
+        Self {
+            method: IntParam::new(
+                "Denoise method",
+                defaults.method,
+                IntRange::Linear {
+                    min: 0,
+                    max: spectral_denoise::METHOD_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| {
+                spectral_denoise::method_name(value).to_string()
+            }))
+            .with_string_to_value(Arc::new(|string| spectral_denoise::method_from_name(string))),
+            reduction: FloatParam::new(
+                "Denoise reduction",
+                defaults.reduction_db,
+                FloatRange::Linear { min: 0.0, max: 60.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            smoothing: FloatParam::new(
+                "Denoise smoothing",
+                defaults.smoothing_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 2000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            musical_noise: FloatParam::new(
+                "Musical noise suppression",
+                defaults.musical_noise,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+        }
+    }
+
+    pub fn settings(&self) -> SpectralDenoiseSettings {
+        SpectralDenoiseSettings {
+            method: self.method.value(),
+            reduction_db: self.reduction.value(),
+            smoothing_ms: self.smoothing.value(),
+            musical_noise: self.musical_noise.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct RingModParams {
+    #[id = "ring transpose"]
+    pub transpose: FloatParam,
+
+    #[id = "ring glide"]
+    pub glide: FloatParam,
+
+    #[id = "ring confidence"]
+    pub min_confidence: FloatParam,
+}
+
+impl RingModParams {
+    pub fn new(defaults: &RingModSettings) -> Self {
+
+        Self {
+            transpose: FloatParam::new(
+                "Ring transpose",
+                defaults.transpose_semitones,
+                FloatRange::Linear { min: -24.0, max: 24.0 },
+            )
+            .with_unit(" st")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            glide: FloatParam::new(
+                "Ring glide",
+                defaults.glide_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            min_confidence: FloatParam::new(
+                "Ring confidence",
+                defaults.min_confidence,
+                FloatRange::Linear { min: 0.0, max: 1.0 },
+            )
+            .with_unit("%")
+            .with_value_to_string(formatters::v2s_f32_percentage(0))
+            .with_string_to_value(formatters::s2v_f32_percentage()),
+        }
+    }
+
+    pub fn settings(&self) -> RingModSettings {
+        RingModSettings {
+            transpose_semitones: self.transpose.value(),
+            glide_ms: self.glide.value(),
+            min_confidence: self.min_confidence.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct EnvelopeFilterParams {
+    #[id = "filter type"]
+    pub filter: IntParam,
+
+    #[id = "filter cutoff"]
+    pub cutoff: FloatParam,
+
+    #[id = "filter depth"]
+    pub depth: FloatParam,
+
+    #[id = "filter resonance"]
+    pub resonance: FloatParam,
+
+    #[id = "filter attack"]
+    pub attack: FloatParam,
+
+    #[id = "filter release"]
+    pub release: FloatParam,
+}
+
+impl EnvelopeFilterParams {
+    pub fn new(defaults: &EnvelopeFilterSettings) -> Self {
+        let time_range = |min: f32, max: f32| FloatRange::Skewed {
+            min,
+            max,
+            factor: FloatRange::skew_factor(-2.0),
+        };
+
+        Self {
+            filter: IntParam::new(
+                "Filter type",
+                defaults.filter,
+                IntRange::Linear {
+                    min: 0,
+                    max: envelope_filter::FILTER_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| envelope_filter::filter_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| envelope_filter::filter_from_name(string))),
+            cutoff: FloatParam::new(
+                "Filter cutoff",
+                defaults.cutoff_hz,
+                FloatRange::Skewed {
+                    min: envelope_filter::MIN_CUTOFF_HZ,
+                    max: envelope_filter::MAX_CUTOFF_HZ,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+            depth: FloatParam::new(
+                "Filter depth",
+                defaults.depth_octaves,
+                FloatRange::Linear { min: -8.0, max: 8.0 },
+            )
+            .with_unit(" oct")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            resonance: FloatParam::new(
+                "Filter resonance",
+                defaults.resonance,
+                FloatRange::Skewed {
+                    min: 0.5,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            attack: FloatParam::new("Filter attack", defaults.attack_ms, time_range(0.0, 500.0))
+                .with_unit(" ms")
+                .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            release: FloatParam::new(
+                "Filter release",
+                defaults.release_ms,
+                time_range(1.0, 2000.0),
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+        }
+    }
+
+    pub fn settings(&self) -> EnvelopeFilterSettings {
+        EnvelopeFilterSettings {
+            filter: self.filter.value(),
+            cutoff_hz: self.cutoff.value(),
+            depth_octaves: self.depth.value(),
+            resonance: self.resonance.value(),
+            attack_ms: self.attack.value(),
+            release_ms: self.release.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct StutterParams {
+    #[id = "stutter trigger"]
+    pub trigger: IntParam,
+
+    #[id = "stutter threshold"]
+    pub threshold: FloatParam,
+
+    #[id = "stutter sync"]
+    pub sync: IntParam,
+
+    #[id = "stutter length"]
+    pub length: FloatParam,
+
+    #[id = "stutter division"]
+    pub division: IntParam,
+
+    #[id = "stutter crossfade"]
+    pub crossfade: FloatParam,
+
+    #[id = "stutter release"]
+    pub release: FloatParam,
+}
+
+impl StutterParams {
+    pub fn new(defaults: &StutterSettings) -> Self {
+
+        Self {
+            trigger: IntParam::new(
+                "Stutter trigger",
+                defaults.trigger,
+                IntRange::Linear {
+                    min: 0,
+                    max: stutter::TRIGGER_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| stutter::trigger_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| stutter::trigger_from_name(string))),
+            threshold: FloatParam::new(
+                "Stutter threshold",
+                defaults.threshold_db,
+                FloatRange::Linear { min: -80.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            sync: IntParam::new(
+                "Stutter sync",
+                defaults.sync as i32,
+                IntRange::Linear { min: 0, max: 1 },
+            ),
+            length: FloatParam::new(
+                "Stutter length",
+                defaults.length_ms,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: stutter::MAX_LENGTH_MS,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            division: IntParam::new(
+                "Stutter division",
+                defaults.division,
+                IntRange::Linear { min: 0, max: ghost::MAX_DIVISION },
+            )
+            .with_value_to_string(Arc::new(|value| ghost::division_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| ghost::division_from_name(string))),
+            crossfade: FloatParam::new(
+                "Stutter crossfade",
+                defaults.crossfade_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 50.0,
+                    factor: FloatRange::skew_factor(-1.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            release: FloatParam::new(
+                "Stutter release",
+                defaults.release_db,
+                FloatRange::Linear { min: -100.0, max: 0.0 },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+        }
+    }
+
+    pub fn settings(&self) -> StutterSettings {
+        StutterSettings {
+            trigger: self.trigger.value(),
+            threshold_db: self.threshold.value(),
+            sync: self.sync.value() == 1,
+            length_ms: self.length.value(),
+            division: self.division.value(),
+            crossfade_ms: self.crossfade.value(),
+            release_db: self.release.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct BleedCancelParams {
+    #[id = "bleed step"]
+    pub step_size: FloatParam,
+
+    #[id = "bleed length"]
+    pub length: FloatParam,
+
+    #[id = "bleed freeze"]
+    pub freeze: IntParam,
+}
+
+impl BleedCancelParams {
+    pub fn new(defaults: &BleedCancelSettings) -> Self {
+
+        Self {
+            step_size: FloatParam::new(
+                "Bleed step size",
+                defaults.step_size,
+                FloatRange::Skewed {
+                    min: 0.001,
+                    max: 1.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(3)),
+            length: FloatParam::new(
+                "Bleed length",
+                defaults.length_ms,
+                FloatRange::Linear {
+                    min: 0.1,
+                    max: bleed_cancel::MAX_LENGTH_MS,
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            freeze: IntParam::new(
+                "Bleed freeze",
+                defaults.freeze as i32,
+                IntRange::Linear { min: 0, max: 1 },
+            ),
+        }
+    }
+
+    pub fn settings(&self) -> BleedCancelSettings {
+        BleedCancelSettings {
+            step_size: self.step_size.value(),
+            length_ms: self.length.value(),
+            freeze: self.freeze.value() == 1,
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct ThresholdSwitchParams {
+    #[id = "switch threshold"]
+    pub threshold: FloatParam,
+
+    #[id = "switch release"]
+    pub release: FloatParam,
+
+    #[id = "switch crossfade"]
+    pub crossfade: FloatParam,
+}
+
+impl ThresholdSwitchParams {
+    pub fn new(defaults: &ThresholdSwitchSettings) -> Self {
+
+        Self {
+            threshold: FloatParam::new(
+                "Switch threshold",
+                defaults.threshold_db,
+                FloatRange::Linear {
+                    min: -24.0,
+                    max: 24.0,
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            release: FloatParam::new(
+                "Switch release",
+                defaults.release_ms,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+            crossfade: FloatParam::new(
+                "Switch crossfade",
+                defaults.crossfade_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 100.0,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+        }
+    }
+
+    pub fn settings(&self) -> ThresholdSwitchSettings {
+        ThresholdSwitchSettings {
+            threshold_db: self.threshold.value(),
+            release_ms: self.release.value(),
+            crossfade_ms: self.crossfade.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct BitwiseParams {
+    #[id = "bitwise bits"]
+    pub bits: IntParam,
+
+    #[id = "bitwise dither"]
+    pub dither: IntParam,
+
+    #[id = "bitwise smoothing"]
+    pub smoothing: FloatParam,
+}
+
+impl BitwiseParams {
+    pub fn new(defaults: &BitwiseSettings) -> Self {
+
+        Self {
+            bits: IntParam::new(
+                "Bitwise bits",
+                defaults.bits,
+                IntRange::Linear {
+                    min: bitwise::MIN_BITS,
+                    max: bitwise::MAX_BITS,
+                },
+            )
+            .with_unit(" bits"),
+            dither: IntParam::new(
+                "Bitwise dither",
+                defaults.dither,
+                IntRange::Linear {
+                    min: 0,
+                    max: bitwise::DITHER_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| bitwise::dither_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| bitwise::dither_from_name(string))),
+            // The filter is bypassed at the maximum
+            smoothing: FloatParam::new(
+                "Bitwise smoothing",
+                defaults.smoothing_hz,
+                FloatRange::Skewed {
+                    min: 20.0,
+                    max: bitwise::MAX_SMOOTHING_HZ,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz()),
+        }
+    }
+
+    pub fn settings(&self) -> BitwiseSettings {
+        BitwiseSettings {
+            bits: self.bits.value(),
+            dither: self.dither.value(),
+            smoothing_hz: self.smoothing.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct OnsetParams {
+    #[id = "onset method"]
+    pub method: IntParam,
+
+    #[id = "onset threshold"]
+    pub threshold: FloatParam,
+
+    #[id = "onset interval"]
+    pub min_interval: FloatParam,
+}
+
+impl OnsetParams {
+    pub fn new(defaults: &OnsetSettings) -> Self {
+
+        Self {
+            method: IntParam::new(
+                "Onset method",
+                defaults.method,
+                IntRange::Linear {
+                    min: 0,
+                    max: onset::METHOD_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| onset::method_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| onset::method_from_name(string))),
+            threshold: FloatParam::new(
+                "Onset threshold",
+                defaults.threshold,
+                FloatRange::Skewed {
+                    min: 1.0,
+                    max: 20.0,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_rounded(2)),
+            min_interval: FloatParam::new(
+                "Onset min interval",
+                defaults.min_interval_ms,
+                FloatRange::Skewed {
+                    min: 0.0,
+                    max: 1000.0,
+                    factor: FloatRange::skew_factor(-1.5),
+                },
+            )
+            .with_unit(" ms")
+            .with_value_to_string(formatters::v2s_f32_rounded(1)),
+        }
+    }
+
+    pub fn settings(&self) -> OnsetSettings {
+        OnsetSettings {
+            method: self.method.value(),
+            threshold: self.threshold.value(),
+            min_interval_ms: self.min_interval.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct MultibandParams {
+    #[id = "bands"]
+    pub num_bands: IntParam,
+
+    #[id = "crossover 1"]
+    pub crossover_1: FloatParam,
+
+    #[id = "crossover 2"]
+    pub crossover_2: FloatParam,
+
+    #[id = "crossover 3"]
+    pub crossover_3: FloatParam,
+
+    #[nested(array, group = "Band")]
+    pub bands: [BandParams; MAX_BANDS],
+}
+
+impl MultibandParams {
+    pub fn new(defaults: &MultibandSettings) -> Self {
+        let crossover = |number: usize| {
+            FloatParam::new(
+                format!("Crossover {number}"),
+                defaults.crossovers_hz[number - 1],
+                FloatRange::Skewed {
+                    min: multiband::MIN_CROSSOVER_HZ,
+                    max: multiband::MAX_CROSSOVER_HZ,
+                    factor: FloatRange::skew_factor(-2.0),
+                },
+            )
+            .with_value_to_string(formatters::v2s_f32_hz_then_khz(0))
+            .with_string_to_value(formatters::s2v_f32_hz_then_khz())
+        };
+
+        Self {
+            num_bands: IntParam::new(
+                "Bands",
+                defaults.num_bands as i32,
+                IntRange::Linear {
+                    min: 1,
+                    max: MAX_BANDS as i32,
+                },
+            ),
+            crossover_1: crossover(1),
+            crossover_2: crossover(2),
+            crossover_3: crossover(3),
+            bands: std::array::from_fn(|idx| {
+                BandParams::new(&format!("Band {}", idx + 1), &defaults.bands[idx])
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> MultibandSettings {
+        MultibandSettings {
+            num_bands: self.num_bands.value() as usize,
+            crossovers_hz: [
+                self.crossover_1.value(),
+                self.crossover_2.value(),
+                self.crossover_3.value(),
+            ],
+            bands: self.bands.each_ref().map(BandParams::settings),
+        }
+    }
+}
+
+/// The mode and gains for a single band, or for the side component with mid/side processing. The
+/// IDs get the band's number or a prefix added to them.
+#[derive(Params)]
+pub struct BandParams {
+    #[id = "band mode"]
+    pub mode: IntParam,
+
+    #[id = "band input gain"]
+    pub input_gain: FloatParam,
+
+    #[id = "band sidechain gain"]
+    pub sidechain_input_gain: FloatParam,
+
+    #[id = "band output gain"]
+    pub output_gain: FloatParam,
+}
+
+impl BandParams {
+    /// `prefix` is prepended to the parameter names.
+    fn new(prefix: &str, defaults: &BandSettings) -> Self {
+        let gain = |name: &str, default: f32| {
+            FloatParam::new(
+                format!("{prefix} {name}"),
+                default,
+                FloatRange::Skewed {
+                    min: util::db_to_gain(-30.0),
+                    max: util::db_to_gain(30.0),
+                    factor: FloatRange::gain_skew_factor(-30.0, 30.0),
+                },
+            )
+            .with_unit(" dB")
+            .with_value_to_string(formatters::v2s_f32_gain_to_db(2))
+            .with_string_to_value(formatters::s2v_f32_gain_to_db())
+        };
+
+        Self {
+            mode: IntParam::new(
+                format!("{prefix} mode"),
+                defaults.mode,
+                IntRange::Linear {
+                    min: 0,
+                    max: mode::MAX,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| mode::name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| mode::from_name(string))),
+            input_gain: gain("input gain", defaults.input_gain),
+            sidechain_input_gain: gain("sidechain gain", defaults.sidechain_input_gain),
+            output_gain: gain("output gain", defaults.output_gain),
+        }
+    }
+
+    pub fn settings(&self) -> BandSettings {
+        BandSettings {
+            mode: self.mode.value(),
+            input_gain: self.input_gain.value(),
+            sidechain_input_gain: self.sidechain_input_gain.value(),
+            output_gain: self.output_gain.value(),
+        }
+    }
+}
+
+#[derive(Params)]
+pub struct MidSideParams {
+    #[id = "mid side channels"]
+    pub channels: IntParam,
+
+    #[id = "mid side key"]
+    pub key: IntParam,
+
+    #[nested(id_prefix = "side", group = "Side")]
+    pub side: BandParams,
+}
+
+impl MidSideParams {
+    pub fn new(defaults: &MidSideSettings) -> Self {
+
+        Self {
+            channels: IntParam::new(
+                "Mid/side channels",
+                defaults.channels,
+                IntRange::Linear {
+                    min: 0,
+                    max: mid_side::CHANNELS_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| mid_side::channels_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| mid_side::channels_from_name(string))),
+            key: IntParam::new(
+                "Mid/side key",
+                defaults.key,
+                IntRange::Linear {
+                    min: 0,
+                    max: mid_side::KEY_NAMES.len() as i32 - 1,
+                },
+            )
+            .with_value_to_string(Arc::new(|value| mid_side::key_name(value).to_string()))
+            .with_string_to_value(Arc::new(|string| mid_side::key_from_name(string))),
+            side: BandParams::new("Side", &defaults.side),
+        }
+    }
+
+    pub fn settings(&self) -> MidSideSettings {
+        MidSideSettings {
+            channels: self.channels.value(),
+            key: self.key.value(),
+            side: self.side.settings(),
+        }
+    }
+}
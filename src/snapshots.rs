@@ -0,0 +1,351 @@
+// A/B snapshot slots and undo/redo history for the full parameter state
+
+use nih_plug::prelude::*;
+use serde::{Deserialize, Serialize};
+use sidebox_core::curve::Curve;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::ghost_curve::GhostCurve;
+use crate::SideboxParams;
+
+/// The number of snapshot slots shown in the editor. Slot 0 is A, slot 1 is B, and so on.
+pub const NUM_SLOTS: usize = 4;
+
+/// The maximum number of undo steps that are kept around. The oldest step is dropped once this is
+/// exceeded.
+pub const MAX_HISTORY: usize = 64;
+
+/// How many editor frames to wait for the host to apply a snapshot before giving up on reading the
+/// values back. Hosts may apply parameter changes from the GUI asynchronously.
+const MAX_APPLY_FRAMES: usize = 30;
+
+/// The normalized value of every parameter, keyed by the parameter's stable ID, along with the rest
+/// of the persisted state that affects the sound. Storing normalized values keyed by ID means
+/// snapshots keep working when parameters are added or reordered.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    values: BTreeMap<String, f32>,
+    /// See `SideboxParams::ghost_curve`. This is `None` in snapshots saved before the curve was
+    /// part of them.
+    #[serde(default)]
+    ghost_curve: Option<GhostCurve>,
+    /// See `SideboxParams::learned_alignment`, also `None` in older snapshots.
+    #[serde(default)]
+    learned_alignment_ms: Option<f32>,
+}
+
+impl Snapshot {
+    /// Read the current (unmodulated) value of every parameter and the rest of the persisted state.
+    /// This allocates and locks, so it should only ever be called from the GUI thread.
+    pub fn capture(params: &SideboxParams) -> Self {
+        let values = params
+            .param_map()
+            .into_iter()
+            // This only tells the host the curve changed, the curve itself is stored below
+            .filter(|(id, _, _)| id != "curve revision")
+            .map(|(id, ptr, _)| (id, unsafe { ptr.unmodulated_normalized_value() }))
+            .collect();
+
+        Self {
+            values,
+            ghost_curve: Some(params.ghost_curve.lock().unwrap().clone()),
+            learned_alignment_ms: Some(params.learned_alignment.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Set every parameter to the value stored in this snapshot through the host, so the changes
+    /// end up in the host's automation and undo history as well. The curve is sent to the audio
+    /// thread through `curve_input`. Anything that did not exist when the snapshot was taken is
+    /// left alone.
+    pub fn apply(
+        &self,
+        params: &SideboxParams,
+        setter: &ParamSetter,
+        curve_input: &Mutex<triple_buffer::Input<Curve>>,
+    ) {
+        if let Some(ghost_curve) = &self.ghost_curve {
+            let mut current = params.ghost_curve.lock().unwrap();
+            if *current != *ghost_curve {
+                *current = ghost_curve.clone();
+                curve_input.lock().unwrap().write(ghost_curve.curve());
+                params.curve_edited(setter);
+            }
+        }
+        if let Some(learned_alignment_ms) = self.learned_alignment_ms {
+            // The audio thread picks this up on the next block, and until then this is what gets
+            // saved
+            params
+                .learned_alignment
+                .store(learned_alignment_ms, Ordering::Relaxed);
+            params
+                .restored_alignment
+                .store(learned_alignment_ms, Ordering::Relaxed);
+        }
+
+        for (id, ptr, _) in params.param_map() {
+            let Some(&normalized) = self.values.get(&id) else {
+                continue;
+            };
+
+            unsafe {
+                if ptr.unmodulated_normalized_value() == normalized {
+                    continue;
+                }
+
+                setter.raw_context.raw_begin_set_parameter(ptr);
+                setter.raw_context.raw_set_parameter_normalized(ptr, normalized);
+                setter.raw_context.raw_end_set_parameter(ptr);
+            }
+        }
+    }
+
+    /// Whether every value in this snapshot has taken effect in `current`. Parameters that no
+    /// longer exist are ignored. The learned offset is too, since the audio thread keeps moving it
+    /// while learning.
+    fn is_applied_in(&self, current: &Snapshot) -> bool {
+        let values_applied = self.values.iter().all(|(id, &normalized)| {
+            current
+                .values
+                .get(id)
+                .map_or(true, |&value| (value - normalized).abs() <= 1e-6)
+        });
+        let curve_applied = self.ghost_curve.is_none() || self.ghost_curve == current.ghost_curve;
+
+        values_applied && curve_applied
+    }
+
+    /// Whether anything the user can change differs between the two snapshots. The learned offset
+    /// changes by itself while learning, so that alone doesn't count.
+    fn is_edited_in(&self, current: &Snapshot) -> bool {
+        self.values != current.values || self.ghost_curve != current.ghost_curve
+    }
+}
+
+/// A snapshot that has been sent to the host, but whose values may not have been applied yet.
+#[derive(Debug, Clone)]
+struct PendingApply {
+    snapshot: Snapshot,
+    frames_left: usize,
+}
+
+/// The snapshot slots plus the undo/redo history. This is persisted with the plugin state and only
+/// ever touched from the GUI thread.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SavedSnapshotBank")]
+pub struct SnapshotBank {
+    /// The stored state for each slot. A slot is `None` until it has been visited or copied to.
+    slots: Vec<Option<Snapshot>>,
+    /// The slot the current parameter values belong to.
+    active: usize,
+
+    undo: VecDeque<Snapshot>,
+    redo: VecDeque<Snapshot>,
+    /// The state from before the current editor gesture, see `begin_gesture()`. Anything the
+    /// gesture changes compared to this is a new undo step.
+    #[serde(skip)]
+    gesture_start: Option<Snapshot>,
+    /// Set after switching slots or undoing or redoing. No gestures are recorded until the host has
+    /// applied these values, since until then the parameters are a mix of the old and new state.
+    #[serde(skip)]
+    pending: Option<PendingApply>,
+}
+
+/// A [`SnapshotBank`] as read from a saved state, which may have been edited or saved by a version
+/// with a different number of slots.
+#[derive(Deserialize)]
+struct SavedSnapshotBank {
+    slots: Vec<Option<Snapshot>>,
+    active: usize,
+    undo: VecDeque<Snapshot>,
+    redo: VecDeque<Snapshot>,
+}
+
+impl From<SavedSnapshotBank> for SnapshotBank {
+    fn from(saved: SavedSnapshotBank) -> Self {
+        let mut slots = saved.slots;
+        slots.resize(NUM_SLOTS, None);
+        let mut undo = saved.undo;
+        let mut redo = saved.redo;
+        // The most recent steps are at the back
+        undo.drain(..undo.len().saturating_sub(MAX_HISTORY));
+        redo.drain(..redo.len().saturating_sub(MAX_HISTORY));
+
+        Self {
+            slots,
+            active: if saved.active < NUM_SLOTS { saved.active } else { 0 },
+
+            undo,
+            redo,
+            gesture_start: None,
+            pending: None,
+        }
+    }
+}
+
+impl Default for SnapshotBank {
+    fn default() -> Self {
+        Self {
+            slots: vec![None; NUM_SLOTS],
+            active: 0,
+
+            undo: VecDeque::new(),
+            redo: VecDeque::new(),
+            gesture_start: None,
+            pending: None,
+        }
+    }
+}
+
+impl SnapshotBank {
+    pub fn active_slot(&self) -> usize {
+        self.active
+    }
+
+    pub fn slot_name(slot: usize) -> String {
+        char::from(b'A' + slot as u8).to_string()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Store the current parameter values in the active slot, and then load `slot`. Switching to a
+    /// slot that has never been stored to keeps the current values, which makes it easy to branch
+    /// off from the current sound.
+    pub fn switch_to(
+        &mut self,
+        slot: usize,
+        params: &SideboxParams,
+        setter: &ParamSetter,
+        curve_input: &Mutex<triple_buffer::Input<Curve>>,
+    ) {
+        nih_debug_assert!(slot < NUM_SLOTS);
+        if slot == self.active {
+            return;
+        }
+
+        let current = self.current(params);
+        self.slots[self.active] = Some(current.clone());
+        self.active = slot;
+        if let Some(snapshot) = self.slots[slot].clone() {
+            // The switch is a single undo step, even if the host applies it over several frames
+            self.push_undo(current);
+            self.redo.clear();
+            self.apply(snapshot, params, setter, curve_input);
+        }
+    }
+
+    /// Copy the current parameter values of the active slot into `slot` without switching to it.
+    pub fn copy_to(&mut self, slot: usize, params: &SideboxParams) {
+        nih_debug_assert!(slot < NUM_SLOTS);
+        self.slots[slot] = Some(self.current(params));
+    }
+
+    /// Remember the current state when the user starts interacting with the editor, like when
+    /// pressing a mouse button. Changes made by automation, presets, or the audio thread outside of
+    /// a gesture never become undo steps.
+    pub fn begin_gesture(&mut self, params: &SideboxParams) {
+        if self.pending.is_none() {
+            self.gesture_start = Some(Snapshot::capture(params));
+        }
+    }
+
+    /// Record an undo step if the current gesture changed anything. The current state becomes the
+    /// start of the next gesture, which catches values that are typed in after the mouse button
+    /// has already been released.
+    pub fn end_gesture(&mut self, params: &SideboxParams) {
+        if self.pending.is_some() {
+            return;
+        }
+
+        let current = Snapshot::capture(params);
+        match self.gesture_start.replace(current.clone()) {
+            Some(previous) if previous.is_edited_in(&current) => {
+                self.push_undo(previous);
+                self.redo.clear();
+            }
+            _ => (),
+        }
+    }
+
+    /// Check whether the host has applied the last switch, undo, or redo yet. This should be called
+    /// once per editor frame, and it only does any work while a snapshot is being applied.
+    pub fn update(&mut self, params: &SideboxParams) {
+        let Some(pending) = &mut self.pending else {
+            return;
+        };
+
+        let current = Snapshot::capture(params);
+        if !pending.snapshot.is_applied_in(&current) && pending.frames_left > 0 {
+            pending.frames_left -= 1;
+            return;
+        }
+
+        // Whatever the host ended up with is where the next gesture starts, without an undo step
+        // of its own
+        self.pending = None;
+        self.gesture_start = Some(current);
+    }
+
+    pub fn undo(
+        &mut self,
+        params: &SideboxParams,
+        setter: &ParamSetter,
+        curve_input: &Mutex<triple_buffer::Input<Curve>>,
+    ) {
+        if let Some(snapshot) = self.undo.pop_back() {
+            let current = self.current(params);
+            self.redo.push_back(current);
+            self.apply(snapshot, params, setter, curve_input);
+        }
+    }
+
+    pub fn redo(
+        &mut self,
+        params: &SideboxParams,
+        setter: &ParamSetter,
+        curve_input: &Mutex<triple_buffer::Input<Curve>>,
+    ) {
+        if let Some(snapshot) = self.redo.pop_back() {
+            let current = self.current(params);
+            self.push_undo(current);
+            self.apply(snapshot, params, setter, curve_input);
+        }
+    }
+
+    /// The current state. While a snapshot is still being applied, the parameters may not reflect
+    /// it yet, so the snapshot is used instead.
+    fn current(&self, params: &SideboxParams) -> Snapshot {
+        match &self.pending {
+            Some(pending) => pending.snapshot.clone(),
+            None => Snapshot::capture(params),
+        }
+    }
+
+    fn apply(
+        &mut self,
+        snapshot: Snapshot,
+        params: &SideboxParams,
+        setter: &ParamSetter,
+        curve_input: &Mutex<triple_buffer::Input<Curve>>,
+    ) {
+        snapshot.apply(params, setter, curve_input);
+        self.pending = Some(PendingApply {
+            snapshot,
+            frames_left: MAX_APPLY_FRAMES,
+        });
+    }
+
+    fn push_undo(&mut self, snapshot: Snapshot) {
+        self.undo.push_back(snapshot);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.pop_front();
+        }
+    }
+}
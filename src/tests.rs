@@ -0,0 +1,321 @@
+// Drives the plugin itself through `initialize()`/`reset()`/`process()` the way a host would, once
+// for every mode with the default settings and once with the other parameters varied. The DSP is
+// tested in depth by `sidebox-core/tests/realtime.rs`, this covers the glue around it in
+// `process()`: reading the parameters, queueing note events, reporting the latency, picking up the
+// drawn ghost curve, and saving the learned alignment. nih-plug doesn't let parameters be changed
+// from outside of a host, so every combination of settings gets its own plugin instance.
+//
+// Every block runs inside of `assert_no_alloc()`, which uses the allocator nih-plug installs for
+// the `assert_process_allocs` feature. Meanwhile the test thread holds every lock the editor can
+// take, so `process()` gets stuck if it tries to take one of them as well.
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use nih_plug::prelude::*;
+use sidebox_core::alignment::{self, AlignmentSettings};
+use sidebox_core::curve::{Breakpoint, Curve};
+use sidebox_core::mid_side::{self, MidSideSettings};
+use sidebox_core::multiband::{BandSettings, MultibandSettings, MAX_BANDS};
+use sidebox_core::{detection_link, key_source, mode, Settings, MAX_LOOKAHEAD_MS, MAX_NOTE_EVENTS};
+
+use super::{Sidebox, SideboxParams};
+
+const SAMPLE_RATE: f32 = 48000.0;
+/// Block sizes hosts commonly use, plus some awkward ones. The last one is also the maximum block
+/// size the plugin gets initialized with.
+const BLOCK_SIZES: &[usize] = &[1, 17, 441, 512, 4096];
+/// The number of blocks processed at every block size.
+const NUM_BLOCKS: usize = 8;
+/// A block taking this long means `process()` is waiting for one of the locks held by the test.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A host with a queue of note events, which remembers the latency the plugin last reported.
+struct TestContext {
+    transport: Transport,
+    events: VecDeque<PluginNoteEvent<Sidebox>>,
+    latency_samples: Cell<u32>,
+}
+
+impl TestContext {
+    fn new() -> Self {
+        let mut transport = Transport::new(SAMPLE_RATE);
+        transport.playing = true;
+        transport.tempo = Some(120.0);
+        transport.time_sig_numerator = Some(4);
+        transport.time_sig_denominator = Some(4);
+
+        Self {
+            transport,
+            // Queueing the events happens outside of `process()`, but this way it doesn't
+            // allocate either
+            events: VecDeque::with_capacity(MAX_NOTE_EVENTS * 2),
+            latency_samples: Cell::new(0),
+        }
+    }
+}
+
+impl InitContext<Sidebox> for TestContext {
+    fn plugin_api(&self) -> PluginApi {
+        PluginApi::Clap
+    }
+
+    fn execute(&self, _task: ()) {}
+
+    fn set_latency_samples(&self, samples: u32) {
+        self.latency_samples.set(samples);
+    }
+
+    fn set_current_voice_capacity(&self, _capacity: u32) {}
+}
+
+impl ProcessContext<Sidebox> for TestContext {
+    fn plugin_api(&self) -> PluginApi {
+        PluginApi::Clap
+    }
+
+    fn execute_background(&self, _task: ()) {}
+
+    fn execute_gui(&self, _task: ()) {}
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn next_event(&mut self) -> Option<PluginNoteEvent<Sidebox>> {
+        self.events.pop_front()
+    }
+
+    fn send_event(&mut self, _event: PluginNoteEvent<Sidebox>) {}
+
+    fn set_latency_samples(&self, samples: u32) {
+        self.latency_samples.set(samples);
+    }
+
+    fn set_current_voice_capacity(&self, _capacity: u32) {}
+}
+
+/// Every mode with the default settings, and again with the parameters the glue in `process()`
+/// deals with picked at random from across their ranges.
+fn settings_sweep() -> Vec<Settings> {
+    let mut rng_state = 0x2545_f491u32;
+    let mut pick = |len: usize| {
+        rng_state ^= rng_state << 13;
+        rng_state ^= rng_state >> 17;
+        rng_state ^= rng_state << 5;
+        rng_state as usize % len
+    };
+    let gains = [util::db_to_gain(-30.0), 1.0, util::db_to_gain(30.0)];
+    let band = |pick: &mut dyn FnMut(usize) -> usize| BandSettings {
+        mode: pick(mode::MAX as usize + 1) as i32,
+        input_gain: gains[pick(gains.len())],
+        sidechain_input_gain: gains[pick(gains.len())],
+        output_gain: gains[pick(gains.len())],
+    };
+
+    let mut sweep = Vec::new();
+    for mode in 0..=mode::MAX {
+        sweep.push(Settings {
+            mode,
+            ..Settings::default()
+        });
+        sweep.push(Settings {
+            mode,
+            input_gain: gains[pick(gains.len())],
+            sidechain_input_gain: gains[pick(gains.len())],
+            output_gain: gains[pick(gains.len())],
+            sidechain_phase_flip: pick(2) == 1,
+            lookahead_ms: MAX_LOOKAHEAD_MS * pick(3) as f32 / 2.0,
+            key_source: pick(key_source::MAX as usize + 1) as i32,
+            detection_link: pick(detection_link::SUM as usize + 1) as i32,
+            detection_link_amount: pick(3) as f32 / 2.0,
+            alignment: AlignmentSettings {
+                learn: pick(2) == 1,
+                manual: pick(2) == 1,
+                offset_ms: alignment::MAX_OFFSET_MS * (pick(3) as f32 - 1.0),
+            },
+            multiband: MultibandSettings {
+                num_bands: pick(MAX_BANDS) + 1,
+                bands: std::array::from_fn(|_| band(&mut pick)),
+                ..MultibandSettings::default()
+            },
+            mid_side: MidSideSettings {
+                channels: pick(mid_side::CHANNELS_NAMES.len()) as i32,
+                key: pick(mid_side::KEY_NAMES.len()) as i32,
+                side: band(&mut pick),
+            },
+            ..Settings::default()
+        });
+    }
+
+    sweep
+}
+
+/// Run `f` and panic if it allocated or freed any memory.
+fn assert_no_allocations<T>(what: &str, f: impl FnOnce() -> T) -> T {
+    assert_no_alloc::reset_violation_count();
+    let result = assert_no_alloc::assert_no_alloc(f);
+
+    let num_allocations = assert_no_alloc::violation_count();
+    assert_eq!(
+        num_allocations, 0,
+        "{what} allocated {num_allocations} times"
+    );
+
+    result
+}
+
+#[test]
+fn processing_does_not_allocate_or_lock() {
+    let max_block_size = *BLOCK_SIZES.last().unwrap();
+    let sweep = settings_sweep();
+    let mut plugins = Vec::new();
+    for settings in &sweep {
+        let mut plugin = Sidebox::new(SideboxParams::new(settings));
+        let mut context = TestContext::new();
+
+        // As if the state was just loaded. This should get saved again unchanged unless the
+        // alignment is being learned.
+        plugin
+            .params
+            .learned_alignment
+            .store(1.5, Ordering::Relaxed);
+        // These are allowed to allocate and lock
+        assert!(plugin.initialize(
+            &Sidebox::AUDIO_IO_LAYOUTS[0],
+            &BufferConfig {
+                sample_rate: SAMPLE_RATE,
+                min_buffer_size: None,
+                max_buffer_size: max_block_size as u32,
+                process_mode: ProcessMode::Realtime,
+            },
+            &mut context,
+        ));
+        plugin.reset();
+        plugins.push((plugin, context));
+    }
+
+    // The editor draws a new ghost curve and then holds on to every lock it can take
+    let shared: Vec<_> = plugins
+        .iter()
+        .map(|(plugin, _)| (plugin.params.clone(), plugin.curve_input.clone()))
+        .collect();
+    let mut guards: Vec<_> = shared
+        .iter()
+        .map(|(params, curve_input)| {
+            (
+                curve_input.lock().unwrap(),
+                params.snapshots.lock().unwrap(),
+                params.ghost_curve.lock().unwrap(),
+            )
+        })
+        .collect();
+    for (curve_input, _, _) in &mut guards {
+        curve_input.write(Curve::from_points(&[
+            Breakpoint::new(0.0, 0.0, 0.5),
+            Breakpoint::new(0.5, 1.0, -0.5),
+        ]));
+    }
+
+    let (block_sender, block_receiver) = mpsc::channel();
+    let audio_thread = thread::spawn(move || {
+        let mut main = vec![vec![0.0; max_block_size]; 2];
+        let mut side = vec![vec![0.0; max_block_size]; 2];
+
+        for (plugin_idx, (plugin, context)) in plugins.iter_mut().enumerate() {
+            let mode = plugin.params.mode.value();
+            for &block_size in BLOCK_SIZES {
+                for block_idx in 0..NUM_BLOCKS {
+                    for (idx, sample) in main.iter_mut().chain(&mut side).flatten().enumerate() {
+                        *sample = ((idx * 7919 + block_idx) % 200) as f32 / 100.0 - 1.0;
+                    }
+
+                    // Some blocks get more events than the processor's queue fits
+                    let num_events = block_idx * MAX_NOTE_EVENTS / 3;
+                    for event_idx in 0..num_events {
+                        let timing = (event_idx * block_size / num_events) as u32;
+                        let note = (event_idx % 128) as u8;
+                        context.events.push_back(if event_idx % 3 == 2 {
+                            NoteEvent::NoteOff {
+                                timing,
+                                voice_id: None,
+                                channel: 0,
+                                note,
+                                velocity: 0.0,
+                            }
+                        } else {
+                            NoteEvent::NoteOn {
+                                timing,
+                                voice_id: None,
+                                channel: 0,
+                                note,
+                                velocity: 0.8,
+                            }
+                        });
+                    }
+                    context.transport.playing = block_idx % 2 == 0;
+
+                    let mut buffer = Buffer::default();
+                    let mut side_buffer = Buffer::default();
+                    unsafe {
+                        buffer.set_slices(block_size, |slices| {
+                            slices.clear();
+                            slices
+                                .extend(main.iter_mut().map(|channel| &mut channel[..block_size]));
+                        });
+                        side_buffer.set_slices(block_size, |slices| {
+                            slices.clear();
+                            slices
+                                .extend(side.iter_mut().map(|channel| &mut channel[..block_size]));
+                        });
+                    }
+                    let mut aux_inputs = [side_buffer];
+                    let mut aux = AuxiliaryBuffers {
+                        inputs: &mut aux_inputs,
+                        outputs: &mut [],
+                    };
+
+                    assert_no_allocations(
+                        &format!(
+                            "a block of {block_size} samples in mode {mode} (settings {plugin_idx})"
+                        ),
+                        || plugin.process(&mut buffer, &mut aux, context),
+                    );
+                    block_sender.send(()).unwrap();
+                }
+            }
+        }
+
+        plugins
+    });
+
+    for _ in 0..sweep.len() * BLOCK_SIZES.len() * NUM_BLOCKS {
+        match block_receiver.recv_timeout(TIMEOUT) {
+            Ok(()) => (),
+            Err(RecvTimeoutError::Timeout) => panic!("process() is waiting for the editor's locks"),
+            // The audio thread panicked, `join()` passes that on below
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    for (curve_input, _, _) in &guards {
+        assert!(curve_input.consumed(), "the ghost curve was never read");
+    }
+    drop(guards);
+
+    let plugins = audio_thread
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+    for ((plugin, context), settings) in plugins.iter().zip(&sweep) {
+        assert_eq!(
+            context.latency_samples.get(),
+            plugin.processor.latency_samples()
+        );
+        if !settings.alignment.learn {
+            assert_eq!(plugin.params.learned_alignment.load(Ordering::Relaxed), 1.5);
+        }
+    }
+}